@@ -0,0 +1,63 @@
+//! Loading times of the binary format compared to the JSON serialization for a crowd of rigs.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mannequin::ndarray::{
+    binary::{from_binary, to_binary},
+    robot::{Axis, LinkNodeId, Segment},
+    rotate_z_4x4, translate_x_4x4,
+};
+use mannequin::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable};
+use ndarray::{array, Array2};
+use std::hint::black_box;
+
+/// A crowd of `characters` identical rigs of 50 segments each attached to a common root.
+fn crowd(characters: usize) -> DepthFirstArenaTree<Segment, LinkNodeId> {
+    let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+    let root = tree.set_root(Segment::new(&Array2::eye(4), Axis::Fixed, None), "world".to_string());
+    (0..characters).for_each(|character| {
+        let pelvis = tree
+            .add(
+                Segment::new(&translate_x_4x4(character as f64 * 1.37), Axis::Fixed, None),
+                format!("{character}/pelvis"),
+                &root,
+            )
+            .unwrap();
+        (0..5).for_each(|limb| {
+            let mut parent = pelvis.clone();
+            (0..10).for_each(|index| {
+                let angle = 0.1 * (limb * 10 + index) as f64 + 0.123456789;
+                let link = rotate_z_4x4(angle).dot(&translate_x_4x4(0.3 + angle / 7.0));
+                let segment = Segment::with_inertia(
+                    &link,
+                    Axis::RotationZ,
+                    (index == 9).then(|| translate_x_4x4(0.1)),
+                    1.0 + angle,
+                    array![angle / 3.0, 0.0, 0.0],
+                    Array2::from_diag(&array![0.01, 0.02, angle / 100.0]),
+                );
+                parent = tree
+                    .add(segment, format!("{character}/{limb}/{index}"), &parent)
+                    .unwrap();
+                tree.set_metadata(&parent, mannequin::pose::LIMIT_MAX, &angle.to_string())
+                    .unwrap();
+            });
+        });
+    });
+    tree.into()
+}
+
+fn loading(c: &mut Criterion) {
+    let tree = crowd(40);
+    let bytes = to_binary(&tree);
+    let json = serde_json::to_string(&tree).unwrap();
+
+    let mut group = c.benchmark_group("load crowd");
+    group.bench_function("binary", |b| b.iter(|| from_binary(black_box(&bytes)).unwrap()));
+    group.bench_function("json", |b| {
+        b.iter(|| serde_json::from_str::<DepthFirstArenaTree<Segment, LinkNodeId>>(black_box(&json)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, loading);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! Looking up nodes by their ids in a large tree. Run with and without the feature `rustc-hash` to compare the
+//! hashers.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mannequin::{arena::iterables::BaseDirectionIterable, DirectedArenaTree, DirectionIterable};
+use std::hint::black_box;
+
+fn lookup(c: &mut Criterion) {
+    let count = 10_000;
+    let mut tree = DirectedArenaTree::<usize, usize>::with_capacity(count);
+    tree.set_root(0, 0);
+    (1..count).for_each(|id| {
+        tree.add(id, id, &(id / 8)).unwrap();
+    });
+
+    c.bench_function("1,000 lookups", |b| {
+        b.iter(|| {
+            let mut id = 0;
+            for _ in 0..1_000 {
+                id = (id * 7919 + 13) % count;
+                black_box(tree.node_by_id(black_box(&id)).unwrap());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
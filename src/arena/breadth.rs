@@ -1,26 +1,196 @@
-//! **TBD.** Breadth-first traversal implementations
+//! Breadth-first traversal implementations
 
-// TODO Add Breadth-first Implementation
+use super::{
+    iterables::OptimizedDirectionIterable, utils::sort_by_indices, ArenaIndex, ArenaNode, BaseDirectionIterable,
+    BreadthFirstIterable, DirectedArenaTree,
+};
+use crate::MannequinError;
+use std::{collections::VecDeque, fmt::Debug, hash::Hash};
 
-use super::{ArenaIndex, ArenaNode, DirectedArenaTree};
+/// Data structure representing an arena tree in which the arena is sorted in breadth-first
+/// (i.e., level) order for faster access
+///
+/// "Extends" [DirectedArenaTree] by composition.
+pub struct BreadthFirstArenaTree<Load, NodeId>(DirectedArenaTree<Load, NodeId>);
 
+impl<Load, NodeId> From<DirectedArenaTree<Load, NodeId>> for BreadthFirstArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq,
+    NodeId: Eq + 'static + Clone + Hash + Debug,
+{
+    fn from(mut value: DirectedArenaTree<Load, NodeId>) -> Self {
+        // sorts the order of nodes such that the levels are stored consecutively
+        let optimal_order = breadth_first_order(&value.nodes, ArenaIndex(0));
+
+        DirectedArenaTree::update_child_indices(&mut value.nodes, &optimal_order);
+        sort_by_indices(&mut value.nodes, optimal_order);
+
+        value.nodes.iter().for_each(|node| {
+            value.lookup.insert(node.id.clone(), node.index);
+        });
+        Self(value)
+    }
+}
+
+/// Indices of the nodes (reachable from `root`) in breadth-first order.
+fn breadth_first_order<Load, NodeId>(nodes: &[ArenaNode<Load, NodeId>], root: ArenaIndex) -> Vec<ArenaIndex> {
+    let mut result = Vec::with_capacity(nodes.len());
+    if root.0 >= nodes.len() {
+        return result;
+    }
+    let mut queue = VecDeque::from([root]);
+    while let Some(index) = queue.pop_front() {
+        queue.extend(nodes[index.0].children.iter().copied());
+        result.push(index);
+    }
+    result
+}
+
+impl<Load, NodeId> BaseDirectionIterable<Load, NodeId> for BreadthFirstArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq,
+    NodeId: Eq + 'static + Clone + Hash + Debug,
+{
+    type Node = ArenaNode<Load, NodeId>;
+
+    fn root(&self) -> Result<&Self::Node, MannequinError<NodeId>> {
+        self.0.root()
+    }
+
+    fn children(&self, node: &Self::Node) -> Result<Vec<&Self::Node>, MannequinError<NodeId>> {
+        self.0.children(node)
+    }
+
+    fn parent(&self, node: &Self::Node) -> Option<&Self::Node> {
+        self.0.parent(node)
+    }
+
+    fn node_by_load(&self, load: &Load) -> Option<&Self::Node> {
+        self.0.node_by_load(load)
+    }
+
+    fn node_by_id(&self, node_id: &NodeId) -> Option<&Self::Node> {
+        self.0.node_by_id(node_id)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<Load, NodeId> OptimizedDirectionIterable<Load, NodeId> for BreadthFirstArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq,
+    NodeId: Eq + 'static + Clone + Hash + Debug,
+{
+    fn iter(&self) -> impl Iterator<Item = &Self::Node> {
+        self.0.nodes.iter()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Self::Node> {
+        self.0.nodes.iter_mut()
+    }
+}
+
+impl<Load, NodeId> BreadthFirstIterable<Load, NodeId> for BreadthFirstArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq,
+    NodeId: Eq + 'static + Clone + Hash + Debug,
+{
+    fn iter_sub(&self, root: &Self::Node) -> impl Iterator<Item = &Self::Node> {
+        // Subtrees are not contiguous in level order, so the subtree is walked again
+        breadth_first_order(&self.0.nodes, root.index)
+            .into_iter()
+            .map(|index| &self.0.nodes[index.0])
+    }
+}
+
+/// Iterator for a breadth-first (i.e., level-order) iteration over a tree that implements
+/// [super::DirectionIterable], starting at an arbitrary node.
 pub struct BreadthFirstIterator<'a, T, NodeRef> {
-    #[allow(dead_code)]
     tree: &'a DirectedArenaTree<T, NodeRef>,
+    /// Nodes of the current level that have not been visited yet followed by their successors
+    queue: VecDeque<ArenaIndex>,
 }
 
 impl<'a, T, NodeRef> BreadthFirstIterator<'a, T, NodeRef> {
-    pub fn new(tree: &'a DirectedArenaTree<T, NodeRef>, _root: ArenaIndex) -> Self {
-        BreadthFirstIterator { tree }
+    pub fn new(tree: &'a DirectedArenaTree<T, NodeRef>, root: ArenaIndex) -> Self {
+        let queue = if root.0 < tree.nodes.len() {
+            VecDeque::from([root])
+        } else {
+            VecDeque::new()
+        };
+        BreadthFirstIterator { tree, queue }
     }
 }
 impl<'a, T, NodeRef> Iterator for BreadthFirstIterator<'a, T, NodeRef> {
     type Item = &'a ArenaNode<T, NodeRef>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        let index = self.queue.pop_front()?;
+        // The root does not exist in an empty tree
+        let node = self.tree.nodes.get(index.0)?;
+        self.queue.extend(node.children.iter().copied());
+        Some(node)
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+
+    use super::*;
+    use crate::arena::sample_tree;
+    use crate::*;
+    use arena::iterables::OptimizedDirectionIterable;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_breadth_first_iterator() {
+        // The tree is not optimized
+        let tree = sample_tree();
+        let first = "first".to_string();
+        let third = "third".to_string();
+
+        let result = tree.iter_breadth().map(|n| *n.get()).collect_vec();
+        assert_eq!(result, &[0, 1, 5, 2, 4, 6, 3]);
+
+        // Starts at the given node
+        let first_node = tree.node_by_id(&first).unwrap();
+        let result = tree.iter_breadth_sub(first_node).map(|n| *n.get()).collect_vec();
+        assert_eq!(result, &[1, 2, 4, 3]);
+        let third_node = tree.node_by_id(&third).unwrap();
+        let result = tree.iter_breadth_sub(third_node).map(|n| n.id()).collect_vec();
+        assert_eq!(result, &["third", "fifth"]);
+
+        // Empty tree
+        let tree = DirectedArenaTree::<usize, String>::new();
+        assert_eq!(tree.iter_breadth().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_sub() {
+        let tree = sample_tree();
+        let first = "first".to_string();
+        let second = "second".to_string();
+
+        let tree: BreadthFirstArenaTree<usize, String> = tree.into();
+
+        // Storage is in level order
+        assert_eq!(tree.iter().map(|n| *n.get()).collect_vec(), &[0, 1, 5, 2, 4, 6, 3]);
+
+        let first_node = tree.node_by_id(&first).unwrap();
+        let result = tree.iter_sub(first_node).map(|n| *n.get()).collect_vec();
+        assert_eq!(result, &[1, 2, 4, 3]);
+
+        let second_node = tree.node_by_id(&second).unwrap();
+        let result = tree.iter_sub(second_node).map(|n| n.id()).collect_vec();
+        assert_eq!(result, &["second", "sixth"]);
+
+        let root_node = tree.root().unwrap();
+        assert_eq!(tree.iter_sub(root_node).count(), tree.len());
+    }
+}
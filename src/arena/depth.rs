@@ -4,20 +4,140 @@ use super::{
     iterables::OptimizedDirectionIterable, utils::sort_by_indices, ArenaIndex, ArenaNode, BaseDirectionIterable,
     DepthFirstIterable, DirectedArenaTree, DirectionIterable,
 };
-use crate::MannequinError;
-use itertools::Itertools;
+use crate::{MannequinError, NodeLike};
+use itertools::{EitherOrBoth, Itertools};
 use std::{fmt::Debug, hash::Hash};
 
 /// Data structure representing an arena tree in which the arena is sorted in depth-first
 /// order for faster access
 ///
-/// "Extends" [DirectedArenaTree] by composition.
-pub struct DepthFirstArenaTree<Load, NodeId>(DirectedArenaTree<Load, NodeId>);
+/// "Extends" [DirectedArenaTree] by composition. A clone is an independent (deep) copy, e.g., for trying
+/// alternative edits of a skeleton without building it again.
+#[derive(Clone)]
+pub struct DepthFirstArenaTree<Load, NodeId>(pub(super) DirectedArenaTree<Load, NodeId>);
+
+/// Corresponding nodes of two trees, see [DepthFirstArenaTree::zip_nodes]
+pub type NodePair<'a, Load, NodeId> = (&'a ArenaNode<Load, NodeId>, &'a ArenaNode<Load, NodeId>);
 
 impl<Load, NodeId> DepthFirstArenaTree<Load, NodeId> {
     pub fn new() -> Self{
         DepthFirstArenaTree(DirectedArenaTree::new())
     }
+
+    /// See [DirectedArenaTree::into_loads] (the loads are in depth-first order)
+    pub fn into_loads(self) -> Vec<(NodeId, Load)> {
+        self.0.into_loads()
+    }
+
+    /// Iterates over the nodes (in depth-first order) together with their direct children. The children are
+    /// looked up by their arena indices, which avoids the scan of [BaseDirectionIterable::children] per node.
+    pub fn iter_with_children(
+        &self,
+    ) -> impl Iterator<Item = (&ArenaNode<Load, NodeId>, Vec<&ArenaNode<Load, NodeId>>)> {
+        self.0.nodes.iter().map(|node| {
+            let children = node.children.iter().map(|child| &self.0.nodes[child.0]).collect();
+            (node, children)
+        })
+    }
+}
+
+impl<Load, NodeId> DepthFirstArenaTree<Load, NodeId>
+where
+    NodeId: Eq + Clone + Hash,
+{
+    /// Creates a tree from `nodes` that are already in depth-first order (e.g., when loading from a file), which
+    /// avoids the reordering of the conversion from a [DirectedArenaTree]. Each entry consists of the load, the
+    /// id and the id of the parent (`None` for the root, which has to come first). Fails if the parent is not an
+    /// ancestor of the previous node (or the previous node itself), that is, if a node's children (and
+    /// descendants) are not stored contiguously right after it.
+    pub fn from_ordered(
+        nodes: impl IntoIterator<Item = (Load, NodeId, Option<NodeId>)>,
+    ) -> Result<Self, MannequinError<NodeId>> {
+        let mut tree = DirectedArenaTree::new();
+        // Arena indices of the ancestors of the last node (including itself)
+        let mut ancestors: Vec<ArenaIndex> = vec![];
+
+        for (load, id, parent) in nodes {
+            let index = ArenaIndex(tree.nodes.len());
+            let parent_ref = match parent {
+                None if tree.nodes.is_empty() => None,
+                None => return Err(MannequinError::NotDepthFirst(id)),
+                Some(parent) => {
+                    while let Some(ancestor) = ancestors.last() {
+                        if tree.nodes[ancestor.0].id == parent {
+                            break;
+                        }
+                        ancestors.pop();
+                    }
+                    match ancestors.last() {
+                        Some(ancestor) => Some(*ancestor),
+                        None if tree.lookup.contains_key(&parent) => return Err(MannequinError::NotDepthFirst(id)),
+                        None if tree.nodes.is_empty() => return Err(MannequinError::RootNotSet),
+                        None => return Err(MannequinError::UnknownNode(parent)),
+                    }
+                }
+            };
+            if tree.lookup.insert(id.clone(), index).is_some() {
+                return Err(MannequinError::NotUnique(id));
+            }
+            if let Some(parent_ref) = parent_ref {
+                tree.nodes[parent_ref.0].children.push(index);
+            }
+            tree.nodes
+                .push(ArenaNode::new(load, id, index, 1, vec![], ancestors.len(), parent_ref));
+            ancestors.push(index);
+        }
+
+        // Children are stored after their parents, so the widths can be accumulated backwards
+        (0..tree.nodes.len()).rev().for_each(|index| {
+            let width = 1 + tree.nodes[index]
+                .children
+                .iter()
+                .map(|child| tree.nodes[child.0].width)
+                .sum::<usize>();
+            tree.nodes[index].width = width;
+        });
+        Ok(Self(tree))
+    }
+
+    /// Iterates over the nodes of this and the `other` tree in lockstep (depth-first), e.g., for retargeting
+    /// motions between skeletons with the same structure but different ids and loads. Fails with
+    /// [MannequinError::StructureMismatch] naming the first node of this tree without a counterpart at the same
+    /// position in the hierarchy (or the first surplus node of the larger tree).
+    pub fn zip_nodes<'a>(
+        &'a self,
+        other: &'a Self,
+    ) -> Result<impl Iterator<Item = NodePair<'a, Load, NodeId>>, MannequinError<NodeId>> {
+        // Depth-first order and depths determine the structure
+        for pair in self.0.nodes.iter().zip_longest(other.0.nodes.iter()) {
+            match pair {
+                EitherOrBoth::Both(node, counterpart) if node.depth() == counterpart.depth() => {}
+                EitherOrBoth::Both(node, _) | EitherOrBoth::Left(node) | EitherOrBoth::Right(node) => {
+                    return Err(MannequinError::StructureMismatch(node.id.clone()))
+                }
+            }
+        }
+        Ok(self.0.nodes.iter().zip(other.0.nodes.iter()))
+    }
+
+    /// Iterates over the depth levels of the tree (starting at the root's level 0) with the nodes at the
+    /// respective depth (in depth-first order), e.g., for processing a level in parallel once the level of the
+    /// parents is done. The levels are collected in a single pass over the arena.
+    pub fn iter_by_depth(&self) -> impl Iterator<Item = (usize, Vec<&ArenaNode<Load, NodeId>>)> {
+        let mut levels: Vec<Vec<&ArenaNode<Load, NodeId>>> = vec![];
+        for node in self.0.nodes.iter() {
+            if levels.len() <= node.depth() {
+                levels.resize_with(node.depth() + 1, Vec::new);
+            }
+            levels[node.depth()].push(node);
+        }
+        levels.into_iter().enumerate()
+    }
+
+    /// See [DirectedArenaTree::set_metadata]
+    pub fn set_metadata(&mut self, node_id: &NodeId, key: &str, value: &str) -> Result<(), MannequinError<NodeId>> {
+        self.0.set_metadata(node_id, key, value)
+    }
 }
 
 impl<Load, NodeId> From<DirectedArenaTree<Load, NodeId>> for DepthFirstArenaTree<Load, NodeId>
@@ -55,6 +175,10 @@ where
         self.0.children(node)
     }
 
+    fn parent(&self, node: &Self::Node) -> Option<&Self::Node> {
+        self.0.parent(node)
+    }
+
     fn node_by_load(&self, load: &Load) -> Option<&Self::Node> {
         self.0.node_by_load(load)
     }
@@ -92,6 +216,8 @@ where
     NodeId: Eq + 'static + Clone + Hash + Debug,
 {
     fn iter_sub(&self, root: &Self::Node) -> impl Iterator<Item = &Self::Node> {
+        // In depth-first order, the subtree is the block of `width` (the number of nodes in the subtree, which
+        // does not depend on the order) nodes starting at the root
         let (start, width) = (root.index, root.width);
         self.0.nodes[start.0..start.0 + width].iter()
     }
@@ -119,7 +245,7 @@ where
 {
     pub fn new(tree: &'a DirectedArenaTree<T, N>, root: ArenaIndex) -> Self {
         let stack = Vec::with_capacity(tree.max_depth);
-        println!("Creating new depth-first iterator (slow)");
+        tracing::trace!("Creating new depth-first iterator (slow)");
         DepthFirstIterator {
             tree,
             stack,
@@ -134,10 +260,10 @@ where
     type Item = &'a ArenaNode<T, N>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(root) = &self.root {
-            let root = &self.tree.nodes[root.0];
+        if let Some(root) = self.root.take() {
+            // The root does not exist in an empty tree
+            let root = self.tree.nodes.get(root.0)?;
             self.stack.push(root.children.iter());
-            self.root = None;
             Some(root)
         } else if let Some(last) = self.stack.last_mut() {
             if let Some(child_ref) = last.next() {
@@ -158,9 +284,10 @@ where
 mod tests {
 
     use super::*;
+    use crate::arena::sample_tree;
     use crate::*;
     use arena::directed::ArenaIndex;
-    use itertools::Itertools;
+    use itertools::{izip, Itertools};
     use test_log;
 
     #[test_log::test]
@@ -179,17 +306,9 @@ mod tests {
         // |
         // 3
 
-        let mut tree = DirectedArenaTree::<usize, String>::new();
-
-        let root = tree.set_root(0, "root".to_string());
-
-        let first = tree.add(1, "first".to_string(), &root).unwrap();
-        let second = tree.add(5, "second".to_string(), &root).unwrap();
-        let third = tree.add(2, "third".to_string(), &first).unwrap();
-
-        tree.add(4, "fourth".to_string(), &first).unwrap();
-        tree.add(3, "fifth".to_string(), &third).unwrap();
-        tree.add(6, "sixth".to_string(), &second).unwrap();
+        let tree = sample_tree();
+        let first = "first".to_string();
+        let second = "second".to_string();
 
         // Check storage unoptimized for depth-first decent
         assert_eq!(tree.nodes.iter().map(|n| n.load).collect_vec(), &[0, 1, 5, 2, 4, 3, 6]);
@@ -271,6 +390,310 @@ mod tests {
         assert_eq!(result, &[5, 6]);
     }
 
+    #[test]
+    fn test_clone() {
+        let tree = sample_tree();
+        let first = "first".to_string();
+        let second = "second".to_string();
+        let third = "third".to_string();
+        let tree: DepthFirstArenaTree<usize, String> = tree.into();
+
+        let mut fork = tree.clone();
+        fork.iter_mut()
+            .filter(|node| node.id() == &third)
+            .for_each(|node| *node.get_mut() = 42);
+        fork.set_metadata(&second, "name", "fork").unwrap();
+
+        assert_eq!(
+            tree.iter().map(|node| *node.get()).collect_vec(),
+            &[0, 1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            fork.iter().map(|node| *node.get()).collect_vec(),
+            &[0, 1, 42, 3, 4, 5, 6]
+        );
+        assert_eq!(tree.node_by_id(&second).unwrap().metadata("name"), None);
+        // The structure (including the lookup) is copied
+        let node = fork.node_by_id(&first).unwrap();
+        assert_eq!(
+            fork.iter_sub(node).map(|node| *node.get()).collect_vec(),
+            &[1, 42, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let tree = DirectedArenaTree::<usize, String>::new();
+        assert_eq!(tree.iter_depth().count(), 0);
+        assert_eq!(tree.iter_breadth().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_ancestors() {
+        let tree = sample_tree();
+
+        let fifth = tree.node_by_id(&"fifth".to_string()).unwrap();
+        let result = tree.iter_ancestors(fifth).map(|n| n.id()).collect_vec();
+        assert_eq!(result, &["third", "first", "root"]);
+
+        // Parent references survive the reordering
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let fifth = tree.node_by_id(&"fifth".to_string()).unwrap();
+        let result = tree.iter_ancestors(fifth).map(|n| n.id()).collect_vec();
+        assert_eq!(result, &["third", "first", "root"]);
+        let sixth = tree.node_by_id(&"sixth".to_string()).unwrap();
+        let result = tree.iter_ancestors(sixth).map(|n| n.id()).collect_vec();
+        assert_eq!(result, &["second", "root"]);
+        assert_eq!(tree.iter_ancestors(tree.root().unwrap()).count(), 0);
+    }
+
+    #[test]
+    fn test_zip_nodes() {
+        // Same structure with different ids and insertion order
+        let mut source = DirectedArenaTree::<usize, String>::new();
+        let root = source.set_root(0, "root".to_string());
+        let first = source.add(1, "first".to_string(), &root).unwrap();
+        source.add(2, "second".to_string(), &root).unwrap();
+        source.add(3, "third".to_string(), &first).unwrap();
+        let mut source: DepthFirstArenaTree<_, _> = source.into();
+
+        let mut target = DirectedArenaTree::<usize, String>::new();
+        let root = target.set_root(10, "pelvis".to_string());
+        let first = target.add(11, "hip".to_string(), &root).unwrap();
+        target.add(13, "knee".to_string(), &first).unwrap();
+        target.add(12, "spine".to_string(), &root).unwrap();
+        let mut target: DepthFirstArenaTree<_, _> = target.into();
+
+        let pairs = source
+            .zip_nodes(&target)
+            .unwrap()
+            .map(|(a, b)| (a.id().as_str(), b.id().as_str()))
+            .collect_vec();
+        assert_eq!(
+            pairs,
+            &[
+                ("root", "pelvis"),
+                ("first", "hip"),
+                ("third", "knee"),
+                ("second", "spine")
+            ]
+        );
+        assert!(source.zip_nodes(&source).unwrap().all(|(a, b)| a.id() == b.id()));
+
+        // Additional leaf (last in depth-first order)
+        target.0.add(14, "neck".to_string(), &"spine".to_string()).unwrap();
+        let mut target: DepthFirstArenaTree<_, _> = target.0.into();
+        assert!(matches!(
+            source.zip_nodes(&target),
+            Err(MannequinError::StructureMismatch(id)) if id == "neck"
+        ));
+        assert!(matches!(
+            target.zip_nodes(&source),
+            Err(MannequinError::StructureMismatch(id)) if id == "neck"
+        ));
+
+        // Same number of nodes but different hierarchies
+        target.0.remove(&"neck".to_string()).unwrap();
+        target.0.add(14, "ankle".to_string(), &"knee".to_string()).unwrap();
+        source.0.add(4, "fourth".to_string(), &"second".to_string()).unwrap();
+        let (source, target): (DepthFirstArenaTree<_, _>, DepthFirstArenaTree<_, _>) =
+            (source.0.into(), target.0.into());
+        assert!(matches!(
+            source.zip_nodes(&target),
+            Err(MannequinError::StructureMismatch(id)) if id == "second"
+        ));
+    }
+
+    #[test]
+    fn test_iter_with_children() {
+        let tree: DepthFirstArenaTree<usize, String> = sample_tree().into();
+
+        let result = tree
+            .iter_with_children()
+            .map(|(node, children)| (*node.get(), children.iter().map(|c| *c.get()).collect_vec()))
+            .collect_vec();
+        assert_eq!(
+            result,
+            &[
+                (0, vec![1, 5]),
+                (1, vec![2, 4]),
+                (2, vec![3]),
+                (3, vec![]),
+                (4, vec![]),
+                (5, vec![6]),
+                (6, vec![])
+            ]
+        );
+
+        // Consistent with the (slower) lookup
+        tree.iter_with_children().for_each(|(node, children)| {
+            let indices = children.iter().map(|child| child.index).collect_vec();
+            assert_eq!(indices, node.children);
+            let expected = tree
+                .children(node)
+                .unwrap()
+                .iter()
+                .map(|child| child.index)
+                .collect_vec();
+            assert_eq!(indices, expected);
+        });
+    }
+
+    #[test]
+    fn test_iter_by_depth() {
+        let tree: DepthFirstArenaTree<usize, String> = sample_tree().into();
+
+        let result = tree
+            .iter_by_depth()
+            .map(|(depth, nodes)| (depth, nodes.iter().map(|node| node.id().as_str()).collect_vec()))
+            .collect_vec();
+        assert_eq!(
+            result,
+            &[
+                (0, vec!["root"]),
+                (1, vec!["first", "second"]),
+                (2, vec!["third", "fourth", "sixth"]),
+                (3, vec!["fifth"])
+            ]
+        );
+        assert_eq!(DepthFirstArenaTree::<usize, String>::new().iter_by_depth().count(), 0);
+    }
+
+    #[test]
+    fn test_from_ordered() {
+        let tree = sample_tree();
+        let second = "second".to_string();
+        let expected: DepthFirstArenaTree<usize, String> = tree.into();
+
+        let node = |load: usize, id: &str, parent: Option<&str>| (load, id.to_string(), parent.map(str::to_string));
+        let ordered = vec![
+            node(0, "root", None),
+            node(1, "first", Some("root")),
+            node(2, "third", Some("first")),
+            node(3, "fifth", Some("third")),
+            node(4, "fourth", Some("first")),
+            node(5, "second", Some("root")),
+            node(6, "sixth", Some("second")),
+        ];
+        let tree = DepthFirstArenaTree::from_ordered(ordered.clone()).unwrap();
+
+        assert_eq!(tree.len(), expected.len());
+        izip!(tree.iter(), expected.iter()).for_each(|(actual, expected)| {
+            assert_eq!(actual.load, expected.load);
+            assert_eq!(actual.id, expected.id);
+            assert_eq!(actual.index, expected.index);
+            assert_eq!(actual.children, expected.children);
+            assert_eq!(actual.width, expected.width);
+            assert_eq!(actual.depth(), expected.depth());
+            assert_eq!(tree.node_by_id(&actual.id).unwrap().index, actual.index);
+        });
+        let second_node = tree.node_by_id(&second).unwrap();
+        assert_eq!(tree.iter_sub(second_node).map(|n| n.load).collect_vec(), &[5, 6]);
+
+        // "fourth" is not stored right after the subtree of its sibling
+        let mut invalid = ordered.clone();
+        invalid.swap(4, 5);
+        invalid.swap(5, 6);
+        assert!(matches!(
+            DepthFirstArenaTree::from_ordered(invalid),
+            Err(MannequinError::NotDepthFirst(id)) if id == "fourth"
+        ));
+        let mut invalid = ordered.clone();
+        invalid[3].2 = Some("unknown".to_string());
+        assert!(matches!(
+            DepthFirstArenaTree::from_ordered(invalid),
+            Err(MannequinError::UnknownNode(id)) if id == "unknown"
+        ));
+        let mut invalid = ordered.clone();
+        invalid[6].1 = "first".to_string();
+        assert!(matches!(
+            DepthFirstArenaTree::from_ordered(invalid),
+            Err(MannequinError::NotUnique(_))
+        ));
+        assert!(matches!(
+            DepthFirstArenaTree::from_ordered(ordered[1..].to_vec()),
+            Err(MannequinError::RootNotSet)
+        ));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+        let root = tree.set_root(0, "root".to_string());
+        let first = tree.add(1, "first".to_string(), &root).unwrap();
+        let second = tree.add(2, "second".to_string(), &root).unwrap();
+        tree.add(3, "third".to_string(), &first).unwrap();
+
+        tree.set_metadata(&first, "name", "upper arm").unwrap();
+        tree.set_metadata(&second, "color", "red").unwrap();
+        tree.set_metadata(&second, "color", "blue").unwrap();
+        assert!(matches!(
+            tree.set_metadata(&"unknown".to_string(), "name", "none"),
+            Err(MannequinError::UnknownNode(_))
+        ));
+
+        // The optimization reorders the nodes (second is moved behind third)
+        let mut tree: DepthFirstArenaTree<usize, String> = tree.into();
+        tree.set_metadata(&root, "name", "pelvis").unwrap();
+
+        assert_eq!(tree.node_by_id(&root).unwrap().metadata("name"), Some("pelvis"));
+        assert_eq!(tree.node_by_id(&first).unwrap().metadata("name"), Some("upper arm"));
+        assert_eq!(tree.node_by_id(&second).unwrap().metadata("color"), Some("blue"));
+        assert_eq!(tree.node_by_id(&second).unwrap().metadata("name"), None);
+    }
+
+    #[test]
+    fn test_iter_sub_random_trees() {
+        // Deterministic linear congruential generator, the trees only have to be diverse
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |bound: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize % bound
+        };
+
+        for _ in 0..50 {
+            // Random parents (the ground truth) and a few random moves of subtrees, which lets insertion
+            // order and depth-first order diverge further
+            let count = 1 + random(40);
+            let mut parents: Vec<Option<usize>> = vec![None];
+            let mut tree = DirectedArenaTree::<usize, usize>::new();
+            tree.set_root(0, 0);
+            for id in 1..count {
+                let parent = random(id);
+                tree.add(id, id, &parent).unwrap();
+                parents.push(Some(parent));
+            }
+            for _ in 0..random(5) {
+                let (moved, parent) = (random(count), random(count));
+                if tree.reparent(&moved, &parent).is_ok() {
+                    parents[moved] = Some(parent);
+                }
+            }
+            let tree: DepthFirstArenaTree<usize, usize> = tree.into();
+
+            // Reference: recursive descent along the parent relation
+            fn descendants(parents: &[Option<usize>], id: usize, result: &mut Vec<usize>) {
+                result.push(id);
+                (0..parents.len())
+                    .filter(|child| parents[*child] == Some(id))
+                    .for_each(|child| descendants(parents, child, result));
+            }
+            for id in 0..count {
+                let mut expected = vec![];
+                descendants(&parents, id, &mut expected);
+                let node = tree.node_by_id(&id).unwrap();
+                let mut actual = tree.iter_sub(node).map(|node| node.id).collect_vec();
+                assert_eq!(actual[0], id);
+                assert_eq!(node.width, expected.len());
+                actual.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(actual, expected, "subtree of {id} with parents {parents:?}");
+            }
+        }
+    }
+
     #[test]
     fn test_iter_mut() {
         // TODO implement test for mutable iteration
@@ -7,7 +7,7 @@
 //! and [super::breadth] suubmodules.
 
 use super::iterables::{BaseDirectionIterable, DirectionIterable, NodeLike};
-use super::{BreadthFirstIterator, DepthFirstArenaTree, DepthFirstIterator};
+use super::{BreadthFirstArenaTree, BreadthFirstIterator, DepthFirstArenaTree, DepthFirstIterator};
 use crate::MannequinError;
 use core::fmt;
 use itertools::Itertools;
@@ -21,7 +21,7 @@ pub struct ArenaIndex(pub usize);
 /// the tree traits in [super::iterables].
 ///
 /// Some of the available Fields are used to speed up iteration.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ArenaNode<Load, NodeId> {
     /// The user-defined load that the node owns
     pub(super) load: Load,
@@ -37,10 +37,12 @@ pub struct ArenaNode<Load, NodeId> {
     depth: usize,
     /// Only used in [DirectedArenaTree]
     parent_ref: Option<ArenaIndex>,
+    /// User-defined tags (e.g., names, colors) that are independent of the load
+    pub(super) metadata: HashMap<String, String>,
 }
 
 impl<Load, NodeRef> ArenaNode<Load, NodeRef> {
-    fn new(
+    pub(super) fn new(
         payload: Load,
         node_ref: NodeRef,
         index: ArenaIndex,
@@ -57,10 +59,18 @@ impl<Load, NodeRef> ArenaNode<Load, NodeRef> {
             children,
             depth,
             parent_ref,
+            metadata: HashMap::new(),
         }
     }
 }
 
+impl<Load, NodeRef> ArenaNode<Load, NodeRef> {
+    /// All metadata entries (see [NodeLike::metadata]) in arbitrary order.
+    pub fn metadata_entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.metadata.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
 impl<Load, NodeRef> NodeLike<Load, NodeRef> for ArenaNode<Load, NodeRef>
 where
     NodeRef: Clone,
@@ -85,6 +95,14 @@ where
     fn get_mut(&mut self) -> &mut Load {
         &mut self.load
     }
+
+    fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    fn parent(&self) -> Option<ArenaIndex> {
+        self.parent_ref
+    }
 }
 
 impl<Load, NodeRef> fmt::Display for ArenaNode<Load, NodeRef>
@@ -108,17 +126,27 @@ where
 ///
 /// The tree is mutable, that is, adding nodes possible, unlike in
 /// the trees optimized for a single direction.
+#[derive(Clone)]
 pub struct DirectedArenaTree<Load, NodeID> {
     /// Memory allocated area for nodes
     pub(crate) nodes: Vec<ArenaNode<Load, NodeID>>,
 
     pub(super) max_depth: usize,
 
-    // TODO optimization: https://crates.io/crates/rustc-hash (feature)
     /// Lookup arena indices
-    pub(super) lookup: HashMap<NodeID, ArenaIndex>,
+    pub(super) lookup: Lookup<NodeID>,
 }
 
+/// Map from node ids to arena indices. With the feature `rustc-hash`, the faster (but not DoS-resistant)
+/// [rustc_hash::FxHashMap] is used, which pays off for integer ids.
+#[cfg(feature = "rustc-hash")]
+pub(crate) type Lookup<K> = rustc_hash::FxHashMap<K, ArenaIndex>;
+
+/// Map from node ids to arena indices. With the feature `rustc-hash`, the faster (but not DoS-resistant)
+/// `rustc_hash::FxHashMap` is used, which pays off for integer ids.
+#[cfg(not(feature = "rustc-hash"))]
+pub(crate) type Lookup<K> = HashMap<K, ArenaIndex>;
+
 impl<Load, NodeId> DirectedArenaTree<Load, NodeId> {
     /// Constructor. Sorting indicates whether the elements are stored to
     /// make either deoth or breadth first traversal efficient (slow insertion). `None` indicates
@@ -131,7 +159,7 @@ impl<Load, NodeId> DirectedArenaTree<Load, NodeId> {
             // depth_first_cache: None,
             // breadh_first_cache: None,
             max_depth: 42,
-            lookup: HashMap::with_capacity(capacity),
+            lookup: Lookup::with_capacity_and_hasher(capacity, Default::default()),
         }
     }
 
@@ -144,10 +172,15 @@ impl<Load, NodeId> DirectedArenaTree<Load, NodeId> {
             // depth_first_cache: None,
             // breadh_first_cache: None,
             max_depth: 42,
-            lookup: HashMap::new(),
+            lookup: Lookup::default(),
         }
     }
 
+    /// Consumes the tree and returns the owned loads with their ids (in storage order) without cloning.
+    pub fn into_loads(self) -> Vec<(NodeId, Load)> {
+        self.nodes.into_iter().map(|node| (node.id, node.load)).collect()
+    }
+
     /// Given an squenze of nodes (i.e., an areana), update the references to child nodes when
     /// the arena is reorderd. It takes a sequence of the same size with the new indices as a parameter
     pub(super) fn update_child_indices(nodes: &mut [ArenaNode<Load, NodeId>], indices: &[ArenaIndex]) {
@@ -166,10 +199,202 @@ impl<Load, NodeId> DirectedArenaTree<Load, NodeId> {
                     .position(|i| *i == node.index)
                     .expect("Internal error. Could not find index!"),
             );
+            node.parent_ref.iter_mut().for_each(|parent_ref| {
+                *parent_ref = ArenaIndex(
+                    indices
+                        .iter()
+                        .position(|i| *i == *parent_ref)
+                        .expect("Internal error. Could not find index!"),
+                )
+            });
         });
     }
 }
 
+impl<Load, NodeId> DirectedArenaTree<Load, NodeId>
+where
+    NodeId: Eq + Clone + Hash,
+{
+    /// Regenerates the lookup (id → arena index) from the nodes. A safety valve in case the
+    /// lookup got out of sync after editing nodes manually. Fails if the ids are not unique.
+    pub fn rebuild_lookup(&mut self) -> Result<(), MannequinError<NodeId>> {
+        self.lookup.clear();
+        for node in self.nodes.iter() {
+            if self.lookup.insert(node.id.clone(), node.index).is_some() {
+                return Err(MannequinError::NotUnique(node.id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the leaf `node_id` and returns its load. Fails if the node has children. The arena indices of
+    /// the nodes stored after the removed one shift by one.
+    pub fn remove(&mut self, node_id: &NodeId) -> Result<Load, MannequinError<NodeId>> {
+        let removed = *self
+            .lookup
+            .get(node_id)
+            .ok_or_else(|| MannequinError::UnknownNode(node_id.clone()))?;
+        if !self.nodes[removed.0].children.is_empty() {
+            return Err(MannequinError::HasChildren(node_id.clone()));
+        }
+
+        // Unlink from the parent and shrink the subtrees containing the node
+        let mut parent_ref = self.nodes[removed.0].parent_ref;
+        if let Some(parent) = parent_ref {
+            self.nodes[parent.0].children.retain(|child| *child != removed);
+        }
+        while let Some(parent) = parent_ref {
+            self.nodes[parent.0].width -= 1;
+            parent_ref = self.nodes[parent.0].parent_ref;
+        }
+
+        let node = self.nodes.remove(removed.0);
+        self.lookup.remove(node_id);
+
+        // Repair the references to the nodes after the removed one
+        let shift = |index: &mut ArenaIndex| {
+            if index.0 > removed.0 {
+                index.0 -= 1;
+            }
+        };
+        self.nodes.iter_mut().for_each(|node| {
+            shift(&mut node.index);
+            node.children.iter_mut().for_each(shift);
+            node.parent_ref.iter_mut().for_each(shift);
+        });
+        self.lookup.values_mut().for_each(shift);
+        Ok(node.load)
+    }
+
+    /// Moves the subtree rooted at `node_id` under `new_parent` (as its last child). Depths, widths and parent
+    /// references are updated, the arena indices do not change. Fails if `new_parent` is part of the moved
+    /// subtree (including the node itself and the root being moved).
+    ///
+    /// Note that the subtrees are not contiguous in memory afterwards, such that a [DepthFirstArenaTree] has
+    /// to be created again (with `into()`) to reflect the change.
+    pub fn reparent(&mut self, node_id: &NodeId, new_parent: &NodeId) -> Result<(), MannequinError<NodeId>> {
+        let index = |id: &NodeId| {
+            self.lookup
+                .get(id)
+                .copied()
+                .ok_or_else(|| MannequinError::UnknownNode(id.clone()))
+        };
+        let (moved, parent) = (index(node_id)?, index(new_parent)?);
+
+        // The new parent must not be a descendant
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == moved {
+                return Err(MannequinError::WouldCycle(new_parent.clone()));
+            }
+            ancestor = self.nodes[current.0].parent_ref;
+        }
+
+        // Detach from the old parent and attach to the new one
+        let width = self.nodes[moved.0].width;
+        let mut ancestor = self.nodes[moved.0].parent_ref;
+        if let Some(old_parent) = ancestor {
+            self.nodes[old_parent.0].children.retain(|child| *child != moved);
+        }
+        while let Some(current) = ancestor {
+            self.nodes[current.0].width -= width;
+            ancestor = self.nodes[current.0].parent_ref;
+        }
+        self.nodes[parent.0].children.push(moved);
+        self.nodes[moved.0].parent_ref = Some(parent);
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            self.nodes[current.0].width += width;
+            ancestor = self.nodes[current.0].parent_ref;
+        }
+
+        // Update the depths of the subtree
+        let mut stack = vec![(moved, self.nodes[parent.0].depth + 1)];
+        while let Some((current, depth)) = stack.pop() {
+            self.nodes[current.0].depth = depth;
+            stack.extend(self.nodes[current.0].children.iter().map(|child| (*child, depth + 1)));
+        }
+        Ok(())
+    }
+
+    /// Attaches metadata (e.g., a label) to a node without changing its load. Overwrites an existing value for `key`.
+    pub fn set_metadata(&mut self, node_id: &NodeId, key: &str, value: &str) -> Result<(), MannequinError<NodeId>> {
+        let index = self
+            .lookup
+            .get(node_id)
+            .ok_or_else(|| MannequinError::UnknownNode(node_id.clone()))?;
+        self.nodes[index.0].metadata.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+impl<Load, NodeId> DirectedArenaTree<Load, NodeId>
+where
+    Load: 'static + fmt::Debug + PartialEq,
+    NodeId: Eq + 'static + Clone + Hash + Debug,
+{
+    /// Mutable depth-first iteration of the subtree rooted at `root_id` (including the node itself), e.g., for
+    /// modifying the loads of a limb. Fails with [MannequinError::UnknownNode] if the node is not in the tree.
+    pub fn iter_depth_sub_mut(
+        &mut self,
+        root_id: &NodeId,
+    ) -> Result<impl Iterator<Item = &mut ArenaNode<Load, NodeId>>, MannequinError<NodeId>> {
+        let root = self.node_by_id_or_err(root_id)?;
+        // The order is determined first as the (immutable) iterator borrows the tree
+        let indices = DepthFirstIterator::new(self, root.index)
+            .map(|node| node.index.0)
+            .collect_vec();
+        let mut nodes = self.nodes.iter_mut().map(Some).collect_vec();
+        Ok(indices.into_iter().filter_map(move |index| nodes[index].take()))
+    }
+
+    /// Removes all nodes (except the root) whose load matches `predicate` and attaches their children to
+    /// the nearest remaining ancestor. Intended for nodes that do not contribute to the kinematics (e.g.,
+    /// fixed joints without offset) to speed up traversal. Returns the number of removed nodes.
+    pub fn collapse<P>(&mut self, predicate: P) -> Result<usize, MannequinError<NodeId>>
+    where
+        P: Fn(&Load) -> bool,
+    {
+        let mut nodes = std::mem::take(&mut self.nodes).into_iter().map(Some).collect_vec();
+        self.lookup.clear();
+        if nodes.is_empty() {
+            return Ok(0);
+        }
+
+        // Re-insert the nodes in depth-first order (which keeps the order of siblings)
+        let mut removed = 0;
+        let mut stack = vec![(ArenaIndex(0), None)];
+        while let Some((index, parent)) = stack.pop() {
+            let ArenaNode {
+                load,
+                id,
+                children,
+                metadata,
+                ..
+            } = nodes[index.0]
+                .take()
+                .ok_or(MannequinError::ReferenceOutOfBound(index.0))?;
+
+            let parent = match parent {
+                Some(parent) if predicate(&load) => {
+                    removed += 1;
+                    parent
+                }
+                parent => {
+                    let id = match parent {
+                        None => self.set_root(load, id),
+                        Some(parent) => self.add(load, id, &parent)?,
+                    };
+                    self.nodes.last_mut().unwrap().metadata = metadata;
+                    id
+                }
+            };
+            stack.extend(children.iter().rev().map(|child| (*child, Some(parent.clone()))));
+        }
+        Ok(removed)
+    }
+}
+
 impl<Load, NodeId> Default for DirectedArenaTree<Load, NodeId> {
     fn default() -> Self {
         Self::new()
@@ -188,16 +413,13 @@ where
     }
 
     fn children(&self, node: &Self::Node) -> Result<Vec<&Self::Node>, MannequinError<NodeId>> {
-        let id = node.id();
-        // can we rely on this check?
-        self.node_by_id(id).ok_or(MannequinError::UnknownNode(id.clone()))?;
+        // The children are looked up in this tree (the node might belong to another one)
+        let node = self.node_by_id_or_err(node.id())?;
+        Ok(node.children.iter().map(|child| &self.nodes[child.0]).collect_vec())
+    }
 
-        // FIXME: map node.children to the nodes (don't loop over everything)
-        Ok(self
-            .nodes
-            .iter()
-            .filter(|n| node.children.iter().contains(&n.index))
-            .collect_vec())
+    fn parent(&self, node: &Self::Node) -> Option<&Self::Node> {
+        self.nodes.get(node.parent_ref?.0)
     }
 
     fn node_by_load(&self, load: &Load) -> Option<&Self::Node> {
@@ -244,14 +466,17 @@ where
         result
     }
 
-    // fn breadth_first(self) -> impl crate::BreadthFirstIterable<Load, NodeId> {
-    //     unimplemented!();
-    // }
+    fn breadth_first(self) -> impl crate::BreadthFirstIterable<Load, NodeId> {
+        let result: BreadthFirstArenaTree<Load, NodeId> = self.into();
+        result
+    }
 
     fn add(&mut self, load: Load, node_id: NodeId, parent: &NodeId) -> Result<NodeId, MannequinError<NodeId>> {
-        let parent = self
-            .node_by_id(parent)
-            .ok_or(MannequinError::UnknownNode(parent.clone()))?;
+        // Any parent is unknown in an empty tree, which is misleading as an error
+        if self.nodes.is_empty() {
+            return Err(MannequinError::RootNotSet);
+        }
+        let parent = self.node_by_id_or_err(parent)?;
         // println!("Adding {:?} to parent {:?}", load, parent);
 
         let index = self.nodes.len();
@@ -307,3 +532,282 @@ where
         self.nodes[0].id.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::arena::iterables::OptimizedDirectionIterable;
+    use crate::arena::sample_tree;
+
+    #[test]
+    fn test_node_by_id_or_err() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+        let root = tree.set_root(0, "root".to_string());
+        tree.add(1, "first".to_string(), &root).unwrap();
+
+        assert_eq!(*tree.node_by_id_or_err(&"first".to_string()).unwrap().get(), 1);
+        assert!(matches!(
+            tree.node_by_id_or_err(&"second".to_string()),
+            Err(MannequinError::UnknownNode(id)) if id == "second"
+        ));
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+        assert!(!tree.contains(&"root".to_string()));
+        let root = tree.set_root(0, "root".to_string());
+        tree.add(1, "first".to_string(), &root).unwrap();
+
+        assert!(tree.contains(&"first".to_string()));
+        assert!(!tree.contains(&"second".to_string()));
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        assert!(tree.contains(&root));
+    }
+
+    #[test]
+    fn test_lookup_many() {
+        // Same results with and without the feature `rustc-hash` (see the `lookup` benchmark for the timing)
+        let count = 10_000;
+        let mut tree = DirectedArenaTree::<usize, usize>::with_capacity(count);
+        tree.set_root(0, 0);
+        (1..count).for_each(|id| {
+            tree.add(id, id, &(id / 8)).unwrap();
+        });
+
+        let mut id = 0;
+        for _ in 0..1_000 {
+            id = (id * 7919 + 13) % count;
+            let node = tree.node_by_id(&id).unwrap();
+            assert_eq!(*node.get(), id);
+            assert_eq!(node.index.0, id);
+        }
+        assert!(tree.node_by_id(&count).is_none());
+    }
+
+    #[test]
+    fn test_add_without_root() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+
+        let result = tree.add(1, "first".to_string(), &"root".to_string());
+        assert!(matches!(result, Err(MannequinError::RootNotSet)));
+
+        let root = tree.set_root(0, "root".to_string());
+        assert!(tree.add(1, "first".to_string(), &root).is_ok());
+        assert!(matches!(
+            tree.add(2, "second".to_string(), &"unknown".to_string()),
+            Err(MannequinError::UnknownNode(_))
+        ));
+    }
+    #[test]
+    fn test_remove() {
+        let mut tree = sample_tree();
+        let first = "first".to_string();
+        let third = "third".to_string();
+        let fourth = "fourth".to_string();
+        let fifth = "fifth".to_string();
+
+        assert!(matches!(tree.remove(&first), Err(MannequinError::HasChildren(id)) if id == first));
+        assert!(matches!(
+            tree.remove(&"unknown".to_string()),
+            Err(MannequinError::UnknownNode(_))
+        ));
+
+        // Removes a node stored before others
+        assert_eq!(tree.remove(&fourth).unwrap(), 4);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.iter_depth().map(|n| *n.get()).collect_vec(), &[0, 1, 2, 3, 5, 6]);
+        assert_eq!(tree.iter_breadth().map(|n| *n.get()).collect_vec(), &[0, 1, 5, 2, 6, 3]);
+        assert!(tree.node_by_id(&fourth).is_none());
+        tree.nodes
+            .iter()
+            .enumerate()
+            .for_each(|(index, node)| assert_eq!(tree.lookup[&node.id], ArenaIndex(index)));
+        assert_eq!(tree.nodes.iter().map(|n| n.width).collect_vec(), &[6, 3, 2, 2, 1, 1]);
+
+        // The subtree can be shrunk further and nodes can be added again
+        assert_eq!(tree.remove(&fifth).unwrap(), 3);
+        tree.add(7, "seventh".to_string(), &third).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        assert_eq!(
+            tree.into_loads().into_iter().map(|(_, load)| load).collect_vec(),
+            &[0, 1, 2, 7, 5, 6]
+        );
+    }
+
+    #[test]
+    fn test_iter_depth_sub_mut() {
+        let mut tree = sample_tree();
+        let first = "first".to_string();
+
+        let visited = tree
+            .iter_depth_sub_mut(&first)
+            .unwrap()
+            .map(|node| {
+                node.load *= 10;
+                node.load
+            })
+            .collect_vec();
+        assert_eq!(visited, &[10, 20, 30, 40]);
+        // The root and the sibling subtree are untouched
+        assert_eq!(
+            tree.iter_depth().map(|n| *n.get()).collect_vec(),
+            &[0, 10, 20, 30, 40, 5, 6]
+        );
+
+        assert!(matches!(
+            tree.iter_depth_sub_mut(&"unknown".to_string()),
+            Err(MannequinError::UnknownNode(_))
+        ));
+    }
+
+    #[test]
+    fn test_children_order() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+        let root = tree.set_root(0, "root".to_string());
+        let first = tree.add(1, "first".to_string(), &root).unwrap();
+        tree.add(2, "second".to_string(), &root).unwrap();
+        tree.add(3, "third".to_string(), &root).unwrap();
+        // Moving the first child to the end makes the order of the children differ from the storage order
+        tree.reparent(&first, &root).unwrap();
+
+        let root = tree.root().unwrap();
+        let children = tree.children(root).unwrap();
+        assert_eq!(children.iter().map(|n| n.index).collect_vec(), root.children);
+        assert_eq!(children.iter().map(|n| *n.get()).collect_vec(), &[2, 3, 1]);
+    }
+
+    #[test]
+    fn test_reparent() {
+        // Moves the subtree at 2 under 6
+        //     0            0
+        //    / \          / \
+        //  1    5   ->   1   5
+        // | \   |        |   |
+        // 2  4  6        4   6
+        // |                  |
+        // 3                  2
+        //                    |
+        //                    3
+        let mut tree = sample_tree();
+        let root = "root".to_string();
+        let first = "first".to_string();
+        let third = "third".to_string();
+        let fifth = "fifth".to_string();
+        let sixth = "sixth".to_string();
+
+        assert!(matches!(tree.reparent(&first, &fifth), Err(MannequinError::WouldCycle(id)) if id == fifth));
+        assert!(matches!(
+            tree.reparent(&third, &third),
+            Err(MannequinError::WouldCycle(_))
+        ));
+        assert!(matches!(
+            tree.reparent(&root, &sixth),
+            Err(MannequinError::WouldCycle(_))
+        ));
+        assert!(matches!(
+            tree.reparent(&"unknown".to_string(), &root),
+            Err(MannequinError::UnknownNode(_))
+        ));
+
+        tree.reparent(&third, &sixth).unwrap();
+        let depth = |id: &String| tree.node_by_id(id).unwrap().depth();
+        assert_eq!((depth(&third), depth(&fifth)), (3, 4));
+        assert_eq!(tree.nodes.iter().map(|n| n.width).collect_vec(), &[7, 2, 4, 2, 1, 1, 3]);
+        assert_eq!(
+            tree.iter_depth().map(|n| *n.get()).collect_vec(),
+            &[0, 1, 4, 5, 6, 2, 3]
+        );
+
+        // The subtree is contiguous again after the conversion
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        assert_eq!(
+            tree.into_loads().into_iter().map(|(_, load)| load).collect_vec(),
+            &[0, 1, 4, 5, 6, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_parent() {
+        let tree = sample_tree();
+
+        fn parents<'a>(nodes: &[&'a ArenaNode<usize, String>]) -> Vec<Option<&'a str>> {
+            nodes
+                .iter()
+                .map(|node| node.parent().map(|parent| nodes[parent.0].id.as_str()))
+                .collect_vec()
+        }
+        assert_eq!(
+            parents(&tree.nodes.iter().collect_vec()),
+            [
+                None,
+                Some("root"),
+                Some("root"),
+                Some("first"),
+                Some("first"),
+                Some("third"),
+                Some("second")
+            ]
+        );
+
+        // Stored in depth-first order, the parents still match
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let nodes = tree.iter().collect_vec();
+        assert_eq!(
+            nodes.iter().map(|node| node.id.as_str()).collect_vec(),
+            &["root", "first", "third", "fifth", "fourth", "second", "sixth"]
+        );
+        assert_eq!(
+            parents(&nodes),
+            [
+                None,
+                Some("root"),
+                Some("first"),
+                Some("third"),
+                Some("first"),
+                Some("root"),
+                Some("second")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebuild_lookup() {
+        let mut tree = DirectedArenaTree::<usize, String>::new();
+        let root = tree.set_root(0, "root".to_string());
+        let first = tree.add(1, "first".to_string(), &root).unwrap();
+        let second = tree.add(2, "second".to_string(), &first).unwrap();
+
+        // Corrupt the lookup
+        tree.lookup.clear();
+        tree.lookup.insert(second.clone(), ArenaIndex(0));
+        assert!(tree.node_by_id(&first).is_none());
+        assert_eq!(*tree.node_by_id(&second).unwrap().get(), 0);
+
+        tree.rebuild_lookup().unwrap();
+        assert_eq!(*tree.node_by_id(&root).unwrap().get(), 0);
+        assert_eq!(*tree.node_by_id(&first).unwrap().get(), 1);
+        assert_eq!(*tree.node_by_id(&second).unwrap().get(), 2);
+
+        // Duplicated ids
+        tree.nodes[2].id = first.clone();
+        assert!(matches!(tree.rebuild_lookup(), Err(MannequinError::NotUnique(id)) if id == first));
+    }
+    #[test]
+    fn test_into_loads() {
+        let mut tree = DirectedArenaTree::<Vec<usize>, String>::new();
+        let root = tree.set_root(vec![0], "root".to_string());
+        let first = tree.add(vec![1, 2], "first".to_string(), &root).unwrap();
+        tree.add(vec![3], "second".to_string(), &first).unwrap();
+
+        assert_eq!(
+            tree.into_loads(),
+            vec![
+                ("root".to_string(), vec![0]),
+                ("first".to_string(), vec![1, 2]),
+                ("second".to_string(), vec![3])
+            ]
+        );
+    }
+}
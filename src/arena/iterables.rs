@@ -1,5 +1,6 @@
 //! Definitions of all the traits for iterable trees in this crate.
 
+use super::ArenaIndex;
 use crate::MannequinError;
 use std::{fmt::Debug, hash::Hash};
 
@@ -13,6 +14,17 @@ pub trait NodeLike<Load, NodeId> {
     fn id(&self) -> &NodeId;
     /// Get the node's distance to its root node. Required for computing accumulations.
     fn depth(&self) -> usize;
+    /// Get the metadata value stored under `key` (e.g., a label or joint limits). Nodes without metadata
+    /// support return `None`.
+    fn metadata(&self, _key: &str) -> Option<&str> {
+        None
+    }
+
+    /// Get the arena index of the node's parent (`None` for the root). In optimized trees, the index is the
+    /// position in the traversal order. Nodes that do not track their parent return `None`.
+    fn parent(&self) -> Option<ArenaIndex> {
+        None
+    }
 
     // TODO Note: a get children would be useful but it is quite a challenge
     // (enforcing equality of associated types). Simpler to implement on [BaseDirectionIterable]
@@ -31,10 +43,26 @@ where
     fn root(&self) -> Result<&Self::Node, MannequinError<NodeId>>;
     /// Access the children of a node. Not implemented on [NodeLike] for simplicity
     fn children(&self, node: &Self::Node) -> Result<Vec<&Self::Node>, MannequinError<NodeId>>;
+    /// Access the parent of a node (`None` for the root).
+    fn parent(&self, node: &Self::Node) -> Option<&Self::Node>;
+    /// Iterates over the ancestors of a node starting with its parent and ending with the root. Empty for the root.
+    fn iter_ancestors<'a>(&'a self, node: &'a Self::Node) -> impl Iterator<Item = &'a Self::Node> {
+        std::iter::successors(self.parent(node), |ancestor| self.parent(ancestor))
+    }
     /// Lookup a node by its load.
     fn node_by_load(&self, load: &Load) -> Option<&Self::Node>;
     /// Get [NodeLike] from an identifier.
     fn node_by_id(&self, node_id: &NodeId) -> Option<&Self::Node>;
+    /// Like [BaseDirectionIterable::node_by_id] but fails with [MannequinError::UnknownNode] if the node is
+    /// not in the tree.
+    fn node_by_id_or_err(&self, node_id: &NodeId) -> Result<&Self::Node, MannequinError<NodeId>> {
+        self.node_by_id(node_id)
+            .ok_or_else(|| MannequinError::UnknownNode(node_id.clone()))
+    }
+    /// Whether a node with the identifier is part of the tree.
+    fn contains(&self, node_id: &NodeId) -> bool {
+        self.node_by_id(node_id).is_some()
+    }
 
     /// Get the number of nodes.
     fn len(&self) -> usize;
@@ -68,8 +96,8 @@ where
 
     /// Generate optimized
     fn depth_first(self) -> impl DepthFirstIterable<Load, NodeId>;
-    // TODO: breadth-first implementation
-    // fn breadth_first(self) -> impl BreadthFirstIterable<Load, NodeId>;
+    /// Generate optimized for breadth-first traversal
+    fn breadth_first(self) -> impl BreadthFirstIterable<Load, NodeId>;
 }
 
 /// (Abstract) Base trait for [DepthFirstIterable] and [BreadthFirstIterable]
@@ -102,5 +130,7 @@ where
     Load: PartialEq,
     NodeId: Eq + Clone + Hash + Debug,
 {
-    // subtree iteration more difficult in bredth-first ordering. This trait likely remains empty
+    /// Breadth-first iteration of a subtree. Subtrees are not stored contiguously in level order, so
+    /// unlike [DepthFirstIterable::iter_sub], the subtree is traversed again (slower).
+    fn iter_sub(&self, root: &Self::Node) -> impl Iterator<Item = &Self::Node>;
 }
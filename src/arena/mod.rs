@@ -8,10 +8,36 @@ pub mod breadth;
 pub mod depth;
 pub mod directed;
 pub mod iterables;
+#[cfg(feature = "serde")]
+mod serialization;
 mod utils;
 
-pub use breadth::BreadthFirstIterator;
+pub use breadth::{BreadthFirstArenaTree, BreadthFirstIterator};
 pub use depth::{DepthFirstArenaTree, DepthFirstIterator};
 pub use directed::{ArenaIndex, ArenaNode, DirectedArenaTree};
 use iterables::BaseDirectionIterable;
 pub use iterables::{BreadthFirstIterable, DepthFirstIterable, DirectionIterable, NodeLike};
+
+/// The tree shared by the tests. Loads reflect the depth-first order whereas the nodes are inserted (and
+/// stored) out of that order, and the ids reflect the order of insertion.
+/// ```text
+///     0
+///    / \
+///  1    5
+/// | \   |
+/// 2  4  6
+/// |
+/// 3
+/// ```
+#[cfg(test)]
+pub(crate) fn sample_tree() -> DirectedArenaTree<usize, String> {
+    let mut tree = DirectedArenaTree::new();
+    let root = tree.set_root(0, "root".to_string());
+    let first = tree.add(1, "first".to_string(), &root).unwrap();
+    let second = tree.add(5, "second".to_string(), &root).unwrap();
+    let third = tree.add(2, "third".to_string(), &first).unwrap();
+    tree.add(4, "fourth".to_string(), &first).unwrap();
+    tree.add(3, "fifth".to_string(), &third).unwrap();
+    tree.add(6, "sixth".to_string(), &second).unwrap();
+    tree
+}
@@ -0,0 +1,170 @@
+//! [Serde](https://serde.rs) support for the arena trees (feature `serde`).
+//!
+//! A tree is stored as a sequence of nodes in depth-first order, each with its id, the id of its parent (`null`
+//! for the root), its load and its metadata (sorted by key, omitted if empty in human-readable formats). Derived
+//! data such as depths and widths is not stored but recomputed by adding the nodes again with
+//! [DirectionIterable::set_root] and [DirectionIterable::add].
+
+use super::{ArenaIndex, DepthFirstArenaTree, DirectedArenaTree, DirectionIterable};
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    hash::Hash,
+};
+
+/// A node as it is serialized (borrowing from the tree).
+struct NodeRecord<'a, Load, NodeId> {
+    id: &'a NodeId,
+    parent: Option<&'a NodeId>,
+    load: &'a Load,
+    metadata: &'a HashMap<String, String>,
+}
+
+impl<Load, NodeId> Serialize for NodeRecord<'_, Load, NodeId>
+where
+    Load: Serialize,
+    NodeId: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Formats that are not self-describing (e.g., binary ones) cannot omit fields
+        let skip_metadata = serializer.is_human_readable() && self.metadata.is_empty();
+        let mut record = serializer.serialize_struct("NodeRecord", if skip_metadata { 3 } else { 4 })?;
+        record.serialize_field("id", self.id)?;
+        record.serialize_field("parent", &self.parent)?;
+        record.serialize_field("load", self.load)?;
+        if skip_metadata {
+            record.skip_field("metadata")?;
+        } else {
+            // Sorted for a deterministic output
+            record.serialize_field("metadata", &self.metadata.iter().collect::<BTreeMap<_, _>>())?;
+        }
+        record.end()
+    }
+}
+
+/// A node as it is deserialized.
+#[derive(Deserialize)]
+struct OwnedNodeRecord<Load, NodeId> {
+    id: NodeId,
+    parent: Option<NodeId>,
+    load: Load,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+impl<Load, NodeId> Serialize for DirectedArenaTree<Load, NodeId>
+where
+    Load: Serialize,
+    NodeId: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut records = Vec::with_capacity(self.nodes.len());
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![(ArenaIndex(0), None)]
+        };
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index.0];
+            records.push(NodeRecord {
+                id: &node.id,
+                parent,
+                load: &node.load,
+                metadata: &node.metadata,
+            });
+            stack.extend(node.children.iter().rev().map(|child| (*child, Some(&node.id))));
+        }
+        serializer.collect_seq(records)
+    }
+}
+
+impl<'de, Load, NodeId> Deserialize<'de> for DirectedArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq + Deserialize<'de>,
+    NodeId: Eq + 'static + Clone + Hash + Debug + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let records = Vec::<OwnedNodeRecord<Load, NodeId>>::deserialize(deserializer)?;
+        let mut tree = DirectedArenaTree::with_capacity(records.len());
+        for (position, record) in records.into_iter().enumerate() {
+            match record.parent {
+                None if position == 0 => {
+                    tree.set_root(record.load, record.id);
+                }
+                None => return Err(de::Error::custom(format!("second root: {:?}", record.id))),
+                Some(parent) => {
+                    tree.add(record.load, record.id, &parent)
+                        .map_err(|error| de::Error::custom(format!("{error:?}")))?;
+                }
+            }
+            tree.nodes.last_mut().unwrap().metadata = record.metadata;
+        }
+        Ok(tree)
+    }
+}
+
+impl<Load, NodeId> Serialize for DepthFirstArenaTree<Load, NodeId>
+where
+    Load: Serialize,
+    NodeId: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, Load, NodeId> Deserialize<'de> for DepthFirstArenaTree<Load, NodeId>
+where
+    Load: 'static + Debug + PartialEq + Deserialize<'de>,
+    NodeId: Eq + 'static + Clone + Hash + Debug + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DirectedArenaTree::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::iterables::{BaseDirectionIterable, OptimizedDirectionIterable};
+    use crate::arena::sample_tree;
+    use crate::{DepthFirstIterable, NodeLike};
+    use itertools::Itertools;
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut tree = sample_tree();
+        let third = "third".to_string();
+        tree.set_metadata(&third, "label", "knee").unwrap();
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(!json.contains("depth") && !json.contains("width"));
+        let tree: DepthFirstArenaTree<usize, String> = tree.into();
+        assert_eq!(serde_json::to_string(&tree).unwrap(), json);
+
+        let restored: DepthFirstArenaTree<usize, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored
+                .iter()
+                .map(|node| (node.id(), node.get(), node.depth()))
+                .collect_vec(),
+            tree.iter()
+                .map(|node| (node.id(), node.get(), node.depth()))
+                .collect_vec()
+        );
+        assert_eq!(
+            restored.iter().map(|node| *node.get()).collect_vec(),
+            &[0, 1, 2, 3, 4, 5, 6]
+        );
+        let node = restored.node_by_id(&third).unwrap();
+        assert_eq!(node.metadata("label"), Some("knee"));
+        assert_eq!(restored.iter_sub(node).count(), 2);
+
+        // Parents have to precede their children
+        let json = r#"[{"id": "root", "parent": null, "load": 0}, {"id": "a", "parent": "b", "load": 1}]"#;
+        assert!(serde_json::from_str::<DirectedArenaTree<usize, String>>(json).is_err());
+        let json = r#"[{"id": "root", "parent": null, "load": 0}, {"id": "a", "parent": null, "load": 1}]"#;
+        assert!(serde_json::from_str::<DirectedArenaTree<usize, String>>(json).is_err());
+    }
+}
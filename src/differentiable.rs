@@ -4,12 +4,15 @@
 //! The algorithms are independent of
 //! the numerical backend and support [f32] and [f64] floating point representations.
 
-use crate::{forward::TransformationAccumulation, DepthFirstIterable, NodeLike, Rigid};
+use crate::{
+    forward::{TransformationAccumulation, Widen},
+    DepthFirstIterable, MannequinError, MaybeSendSync, NodeLike, Rigid,
+};
 use itertools::{izip, Itertools};
 use num_traits::Float;
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
-use std::{collections::HashSet, fmt::Debug, hash::Hash};
+use std::{cmp::Ordering, collections::HashSet, fmt::Debug, hash::Hash, ops::Range};
 
 /// Computation shares common intermediate results. This enum
 /// allows selecting which results should be computed.
@@ -42,12 +45,40 @@ pub trait Differentiable<F: Float> {
     fn effectors(&self) -> Vec<&[F]>;
 
     /// Prepare algorithms for computation. This avoids memory allocation when calling [Differentiable::compute].
-    fn setup<T, R, I>(&mut self, tree: &T, selected_joints: &[&I], selected_effectors: &[&I])
+    /// Fails with [MannequinError::UnknownNode] if a selected joint or effector is not in the tree.
+    fn setup<T, R, I>(
+        &mut self,
+        tree: &T,
+        selected_joints: &[&I],
+        selected_effectors: &[&I],
+    ) -> Result<(), MannequinError<I>>
     where
         T: DepthFirstIterable<R, I>,
         R: Rigid<FloatType = F>,
         I: Eq + Clone + Hash + Debug;
 
+    /// Same as [Differentiable::setup] but selects all nodes with an effector if `selected_effectors` is `None`
+    /// (see [select_effectors_from_tree]).
+    fn setup_with<T, R, I>(
+        &mut self,
+        tree: &T,
+        selected_joints: &[&I],
+        selected_effectors: Option<&[&I]>,
+    ) -> Result<(), MannequinError<I>>
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        match selected_effectors {
+            Some(selected_effectors) => self.setup(tree, selected_joints, selected_effectors),
+            None => {
+                let effectors = select_effectors_from_tree(tree);
+                self.setup(tree, selected_joints, &effectors.iter().collect_vec())
+            }
+        }
+    }
+
     /// Compute is necessary as the structure holds the memory for the jacobian and the forward vector.
     /// Call [Differentiable::setup] first.
     fn compute<T, R, I>(&mut self, tree: &T, params: &[R::FloatType], selection: ComputeSelection)
@@ -56,6 +87,34 @@ pub trait Differentiable<F: Float> {
         R: Rigid<FloatType = F>,
         I: Eq + Clone + Hash + Debug;
 
+    /// Same as [Differentiable::compute] but reuses the world transformations in `cache` that are not affected by
+    /// changed parameters (see [TransformationCache]). The default implementation ignores the cache.
+    fn compute_cached<T, R, I>(
+        &mut self,
+        tree: &T,
+        params: &[R::FloatType],
+        selection: ComputeSelection,
+        _cache: &mut TransformationCache<R>,
+    ) where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        self.compute(tree, params, selection);
+    }
+
+    /// Same as [Differentiable::compute] but accumulates the world transformations in the wider representation of
+    /// [Widen] (see [TransformationAccumulation::accumulate_wide]), e.g., for long chains in single precision.
+    /// The default implementation ignores the wider representation.
+    fn compute_wide<T, R, I>(&mut self, tree: &T, params: &[R::FloatType], selection: ComputeSelection)
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Widen<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        self.compute(tree, params, selection);
+    }
+
     /// Get the number of rows of the Jacobian matrix. Call [Differentiable::setup] first.
     fn rows(&self) -> usize;
     /// Get the number of columns of the Jacobian matrix. Call [Differentiable::setup] first.
@@ -67,9 +126,61 @@ pub trait Differentiable<F: Float> {
     /// Call [Differentiable::setup] first.
     fn active(&self) -> &[bool];
 
+    /// Rows in the Jacobian matrix (and in [Differentiable::flat_effectors]) of the node at position `index`
+    /// in depth-first order. `None` if the node's effector is not selected. Call [Differentiable::setup] first.
+    fn effector_range(&self, index: usize) -> Option<Range<usize>>;
 
-}
+    /// Partial derivatives of the coordinate `axis` of an effector for all active joints (one entry per column of
+    /// the Jacobian matrix, see [jacobian_columns]), sorted by magnitude (largest first). Helps answering questions such as "which joint affects the hand's x position most?".
+    /// Call [Differentiable::compute] first.
+    fn joint_sensitivities<T, R, I>(
+        &self,
+        tree: &T,
+        effector: &I,
+        axis: usize,
+    ) -> Result<Vec<(I, F)>, MannequinError<I>>
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        let range = tree
+            .iter()
+            .position(|node| node.id() == effector)
+            .and_then(|index| self.effector_range(index))
+            .ok_or_else(|| MannequinError::UnknownNode(effector.clone()))?;
+        if axis >= range.len() {
+            return Err(MannequinError::DimensionMismatch(axis));
+        }
+        let row = range.start + axis;
+
+        // A joint with several parameters has a column (and thus an entry) for each
+        let nodes = tree.iter().collect_vec();
+        let mut result = jacobian_columns(tree, self.active())
+            .into_iter()
+            .zip(self.jacobian().chunks(self.rows()))
+            .map(|((index, _), col)| (nodes[index].id().clone(), col[row]))
+            .collect_vec();
+        result.sort_by(|(_, a), (_, b)| b.abs().partial_cmp(&a.abs()).unwrap_or(Ordering::Equal));
+        Ok(result)
+    }
 
+    /// Geometric center of the selected effectors (e.g., for bringing both hands together). Averages the
+    /// blocks in [Differentiable::flat_effectors] coordinate-wise, thus all effectors are expected to have
+    /// the same dimension. Empty if no effector is selected. Call [Differentiable::compute] first.
+    fn effector_centroid(&self) -> Vec<F> {
+        let effectors = self.effectors();
+        let Some(first) = effectors.first() else {
+            return vec![];
+        };
+        debug_assert!(effectors.iter().all(|effector| effector.len() == first.len()));
+
+        let count = F::from(effectors.len()).unwrap();
+        (0..first.len())
+            .map(|axis| effectors.iter().fold(F::zero(), |sum, effector| sum + effector[axis]) / count)
+            .collect()
+    }
+}
 
 /// Helper trait that is implemented for all iterators. Is used
 /// to filter a sequence by the output of [Differentiable::active].
@@ -93,6 +204,89 @@ impl<T, I> Filterable<T> for I where I: Iterator<Item = T> {
     }
 }
 
+/// Number of rows the selected effectors take in the Jacobian matrix (i.e., the expected length of
+/// the target buffer). Equals [Differentiable::rows] after the corresponding [Differentiable::setup] but
+/// does not require a model. If `selected_effectors` is empty, all nodes with an effector are counted.
+pub fn total_effector_rows<T, R, I>(tree: &T, selected_effectors: &[&I]) -> usize
+where
+    T: DepthFirstIterable<R, I>,
+    R: Rigid,
+    I: Eq + Clone + Hash + Debug,
+{
+    if selected_effectors.is_empty() {
+        return tree.iter().map(|node| node.get().effector_size()).sum();
+    }
+    let selected_effectors: HashSet<&I> = HashSet::from_iter(selected_effectors.iter().copied());
+    tree.iter()
+        .filter(|node| selected_effectors.contains(&node.id()))
+        .map(|node| node.get().effector_size())
+        .sum()
+}
+
+/// Ids of all nodes that define an effector (i.e., [Rigid::effector_count] is positive) in depth-first order.
+/// Can be passed to [Differentiable::setup] instead of maintaining the list by hand.
+pub fn select_effectors_from_tree<T, R, I>(tree: &T) -> Vec<I>
+where
+    T: DepthFirstIterable<R, I>,
+    R: Rigid,
+    I: Eq + Clone + Hash + Debug,
+{
+    tree.iter()
+        .filter(|node| node.get().effector_count() > 0)
+        .map(|node| node.id().clone())
+        .collect()
+}
+
+/// Index of the first parameter of each node of `tree` in depth-first order. A node consumes
+/// [Rigid::param_count] parameters (e.g., none for a fixed joint or two for a universal joint), such that the
+/// index of a node generally differs from its position.
+pub fn param_offsets<T, R, I>(tree: &T) -> Vec<usize>
+where
+    T: DepthFirstIterable<R, I>,
+    R: Rigid,
+    I: Eq + Clone + Hash + Debug,
+{
+    tree.iter()
+        .scan(0, |offset, node| {
+            let result = Some(*offset);
+            *offset += node.get().param_count();
+            result
+        })
+        .collect()
+}
+
+/// Number of parameters the nodes of `tree` consume in total (see [Rigid::param_count]), i.e., the expected
+/// length of the parameters passed to the forward and inverse kinematics. Equals the number of nodes if every
+/// node has a single degree of freedom.
+pub fn parameter_count<T, R, I>(tree: &T) -> usize
+where
+    T: DepthFirstIterable<R, I>,
+    R: Rigid,
+    I: Eq + Clone + Hash + Debug,
+{
+    tree.iter().map(|node| node.get().param_count()).sum()
+}
+
+/// The node (position in depth-first order) and the parameter (see [param_offsets]) of each column of the
+/// Jacobian matrix given the `active` joints (see [Differentiable::active]). An active joint has a column per
+/// parameter, and a joint without parameters (e.g., a fixed joint) a single zero column without parameter (`None`).
+/// Maps the updates of solvers (one per column) to the parameters.
+pub fn jacobian_columns<T, R, I>(tree: &T, active: &[bool]) -> Vec<(usize, Option<usize>)>
+where
+    T: DepthFirstIterable<R, I>,
+    R: Rigid,
+    I: Eq + Clone + Hash + Debug,
+{
+    tree.iter()
+        .zip(param_offsets(tree))
+        .enumerate()
+        .filter_active(active)
+        .flat_map(|(index, (node, offset))| {
+            let count = node.get().param_count();
+            (0..count.max(1)).map(move |dof| (index, (dof < count).then_some(offset + dof)))
+        })
+        .collect()
+}
 
 // Note: Won't make the trait itself generic. That would be cleaner but mean more overhead
 // (i.e., requiring full qualifiers in compositions)
@@ -100,7 +294,7 @@ impl<T, I> Filterable<T> for I where I: Iterator<Item = T> {
 /// Backend-agnostic implementation of algorithms for computing the forward kinematics
 /// and partial derivatives (i.e, Jacobian matrix) or the application in inverse kinematics
 /// solvers. Generic in the floating point representation.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct DifferentiableModel<F: Float> {
     matrix: Vec<F>,
     configuration: Vec<F>,
@@ -110,6 +304,8 @@ pub struct DifferentiableModel<F: Float> {
     offsets: Vec<usize>,
     /// Widths
     sizes: Vec<usize>,
+    /// Index of the first parameter of each node (see [param_offsets]). Same length as nodes!
+    param_offsets: Vec<usize>,
     /// For each node a bool which decides whether its joint will be used. Same length as nodes!
     selected_joints: Vec<bool>,
     /// For each node a bool which decides whether its effector will be used. Same length as nodes!
@@ -122,7 +318,90 @@ impl<F: Float + Default> DifferentiableModel<F> {
     }
 }
 
-impl<F: Float> Differentiable<F> for DifferentiableModel<F> {
+/// World transformations (in depth-first order) of the last computation with the parameters they have been computed
+/// with. Allows [Differentiable::compute_cached] to recompute only the subtrees of joints whose parameters changed
+/// by more than `epsilon`, e.g., between the iterations of an inverse kinematics solver (see
+/// [crate::DifferentialInverseModel::set_incremental]). Pays off if few joints are active.
+///
+/// The cache is bound to a tree. Call [TransformationCache::set_dirty_all] after modifying the loads (e.g., link
+/// lengths) or when computing with a different tree.
+pub struct TransformationCache<R: Rigid> {
+    /// Empty if all have to be recomputed
+    poses: Vec<R::Transformation>,
+    /// Parameters the cached transformations have been computed with
+    params: Vec<R::FloatType>,
+    /// Parameter changes up to this value do not cause a recomputation
+    epsilon: R::FloatType,
+    /// Number of transformations recomputed in the last computation
+    recomputed_count: usize,
+}
+
+impl<R: Rigid> TransformationCache<R> {
+    pub fn new(epsilon: R::FloatType) -> Self {
+        Self {
+            poses: vec![],
+            params: vec![],
+            epsilon,
+            recomputed_count: 0,
+        }
+    }
+
+    /// Forces recomputing all transformations in the next computation.
+    pub fn set_dirty_all(&mut self) {
+        self.poses.clear();
+    }
+
+    /// Number of transformations that have been recomputed in the last computation
+    pub fn recomputed_count(&self) -> usize {
+        self.recomputed_count
+    }
+
+    /// Updates the world transformations of all nodes for `params` given the index of each node's first parameter
+    /// (see [param_offsets]). Reuses the transformations of the last call if possible.
+    fn update<T, I>(&mut self, tree: &T, params: &[R::FloatType], param_offsets: &[usize])
+    where
+        T: DepthFirstIterable<R, I>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        let initial = self.params.len() != params.len() || self.poses.len() != tree.len();
+        if initial {
+            self.poses.clear();
+            self.params = params.to_vec();
+        }
+        self.recomputed_count = 0;
+
+        // Indices of the ancestors of the current node and whether they have been recomputed
+        debug_assert_eq!(param_offsets.len(), tree.len());
+        let mut stack: Vec<(usize, bool)> = Vec::with_capacity(tree.len());
+        for (index, (node, offset)) in tree.iter().zip(param_offsets).enumerate() {
+            stack.truncate(node.depth());
+            let (offset, count) = (*offset, node.get().param_count());
+            let parent = stack.last().copied();
+
+            let range = offset..offset + count;
+            let update = initial
+                || parent.map_or(false, |(_, updated)| updated)
+                || izip!(&params[range.clone()], &self.params[range.clone()])
+                    .any(|(param, cached)| (*param - *cached).abs() > self.epsilon);
+            if update {
+                let neutral = R::neutral_element();
+                let parent_pose = parent.map_or(&neutral, |(parent, _)| &self.poses[parent]);
+                let pose = R::concat(parent_pose, &node.get().transform(params, offset));
+                if initial {
+                    self.poses.push(pose);
+                } else {
+                    self.poses[index] = pose;
+                    // Parameters within epsilon keep their reference value (to avoid drifting)
+                    self.params[range.clone()].copy_from_slice(&params[range]);
+                }
+                self.recomputed_count += 1;
+            }
+            stack.push((index, update));
+        }
+    }
+}
+
+impl<F: Float + MaybeSendSync> Differentiable<F> for DifferentiableModel<F> {
     fn jacobian(&self) -> &[F] {
         &self.matrix
     }
@@ -142,13 +421,32 @@ impl<F: Float> Differentiable<F> for DifferentiableModel<F> {
         &self.selected_joints
     }
 
-    fn setup<T, R, I>(&mut self, tree: &T, selected_joints: &[&I], selected_effectors: &[&I])
+    fn effector_range(&self, index: usize) -> Option<Range<usize>> {
+        if *self.selected_effectors.get(index)? {
+            Some(self.offsets[index]..self.offsets[index] + self.sizes[index])
+        } else {
+            None
+        }
+    }
+
+    fn setup<T, R, I>(
+        &mut self,
+        tree: &T,
+        selected_joints: &[&I],
+        selected_effectors: &[&I],
+    ) -> Result<(), MannequinError<I>>
     where
         T: DepthFirstIterable<R, I>,
         R: Rigid<FloatType = F>,
         I: Eq + Clone + Hash + Debug,
     {
-        // todo: fail if node not in tree.
+        if let Some(unknown) = selected_joints
+            .iter()
+            .chain(selected_effectors)
+            .find(|id| !tree.contains(id))
+        {
+            return Err(MannequinError::UnknownNode((*unknown).clone()));
+        }
 
         let selected_effectors: HashSet<&I> = HashSet::from_iter(selected_effectors.iter().copied());
         self.selected_effectors = tree.iter().map(|n| selected_effectors.contains(&n.id())).collect();
@@ -172,6 +470,7 @@ impl<F: Float> Differentiable<F> for DifferentiableModel<F> {
             .collect();
 
         self.sizes = tree.iter().map(|n| n.get().effector_size()).collect();
+        self.param_offsets = param_offsets(tree);
 
         self.rows = tree
             .iter()
@@ -184,14 +483,20 @@ impl<F: Float> Differentiable<F> for DifferentiableModel<F> {
             })
             .sum();
 
-        self.cols = self.selected_joints.iter().filter(|&selected| *selected).count();
-        dbg!((&self.selected_joints, self.rows, self.cols));
+        // A column per parameter of the selected joints. Joints without parameters keep a (zero) column
+        self.cols = tree
+            .iter()
+            .filter_active(&self.selected_joints)
+            .map(|node| node.get().param_count().max(1))
+            .sum();
+        tracing::debug!(selected_joints = ?self.selected_joints, self.rows, self.cols, "setup");
 
         self.matrix.clear();
         self.matrix.resize(self.rows * self.cols, F::zero());
 
         self.configuration.clear();
         self.configuration.resize(self.rows, F::zero());
+        Ok(())
     }
 
     fn rows(&self) -> usize {
@@ -212,63 +517,226 @@ impl<F: Float> Differentiable<F> for DifferentiableModel<F> {
         R: Rigid<FloatType = F>,
         I: Eq + Clone + Hash + Debug,
     {
-        debug_assert_eq!(params.len(), self.selected_joints.len());
+        // An empty cache computes all transformations
+        self.compute_cached(tree, params, selection, &mut TransformationCache::new(F::zero()));
+    }
+
+    fn compute_cached<T, R, I>(
+        &mut self,
+        tree: &T,
+        params: &[F],
+        selection: ComputeSelection,
+        cache: &mut TransformationCache<R>,
+    ) where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        // Nodes can consume more than one parameter
+        debug_assert_eq!(params.len(), parameter_count(tree));
 
         // compute transformations only once
-        let nodes_trafos = tree
+        cache.update(tree, params, &self.param_offsets);
+        self.compute_poses(tree, params, selection, &cache.poses);
+    }
+
+    fn compute_wide<T, R, I>(&mut self, tree: &T, params: &[F], selection: ComputeSelection)
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Widen<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        let poses = tree
             .iter()
-            .accumulate(params, 42)
-            .enumerate()
-            .map(|(idx, (node, trafo))| (idx, node, trafo)) // flatten
+            .accumulate_wide(params, tree.len())
+            .map(|(_, pose)| pose)
             .collect_vec();
+        self.compute_poses(tree, params, selection, &poses);
+    }
+}
 
+impl<F: Float + MaybeSendSync> DifferentiableModel<F> {
+    /// Computes the effectors and/or the Jacobian matrix given the world transformations of all nodes
+    fn compute_poses<T, R, I>(
+        &mut self,
+        tree: &T,
+        params: &[F],
+        selection: ComputeSelection,
+        poses: &[R::Transformation],
+    ) where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
         if matches!(selection, ComputeSelection::EffectorsOnly | ComputeSelection::All) {
-            izip!(&nodes_trafos, &self.selected_effectors, &self.offsets)
-                .filter_map(|(x, selected, offset)| if *selected { Some((x, offset)) } else { None })
-                .for_each(|((_, node, pose), offset)| {
+            izip!(tree.iter(), poses, &self.selected_effectors, &self.offsets)
+                .filter(|(_, _, selected, _)| **selected)
+                .for_each(|(node, pose, _, offset)| {
                     node.get().effector(pose, &mut self.configuration, *offset);
                 });
         }
 
         if matches!(selection, ComputeSelection::JacobianOnly | ComputeSelection::All) {
-            self.matrix
-                // .iter_mut()
-                // FIXME: Row below can panic .. handle errors
-                .chunks_mut(self.rows)
-                // TODO use rayon
-                .zip(
-                    nodes_trafos
-                        .iter()
-                        .zip(self.selected_joints.iter()) // Add the selected joint lists
-                        .filter_map(|(x, selected)| if *selected { Some(x) } else { None }), // filter inactive joints and remove flag
-                                                                                             //par_iter()
+            // The columns only access the loads and poses (not the tree) such that they can be computed in parallel.
+            // The subtree of a joint is stored contiguously after it (depth-first order).
+            let rigids_trafos = tree.iter().map(|node| node.get()).zip(poses).collect_vec();
+            // A column for each parameter of the selected joints: (node index, subtree size, parameter)
+            let joints = tree
+                .iter()
+                .enumerate()
+                .zip(self.selected_joints.iter()) // Add the selected joint lists
+                .filter_map(|(x, selected)| if *selected { Some(x) } else { None }) // filter inactive joints and remove flag
+                .flat_map(|(idx, joint_node)| {
+                    let count = tree.iter_sub(joint_node).count();
+                    (0..joint_node.get().param_count().max(1)).map(move |dof| (idx, count, dof))
+                })
+                .collect_vec();
+            let (offsets, selected_effectors) = (&self.offsets, &self.selected_effectors);
+            let param_offsets = &self.param_offsets;
+
+            let column = |col: &mut [F], (idx, count, dof): &(usize, usize, usize)| {
+                let (joint, joint_pose) = rigids_trafos[*idx];
+                let param_count = joint.param_count();
+                let joint_params = &params[param_offsets[*idx]..param_offsets[*idx] + param_count];
+                izip!(
+                    &rigids_trafos[*idx..*idx + *count],
+                    &offsets[*idx..*idx + *count],
+                    &selected_effectors[*idx..*idx + *count]
                 )
-                .for_each(|(col, (idx, joint_node, joint_pose))| {
-                    izip!(
-                        tree.iter_sub(joint_node), // iterating over the child tree
-                        // zipping the corresponding trafos (by skipping until the current node) and the offsets in the column
-                        // Using the index here is ok, keeping an iterator is to hard (gets mutated in a different closure)
-                        nodes_trafos.iter().skip(*idx).map(|(_, _, trafo)| trafo),
-                        self.offsets.iter().skip(*idx),
-                        self.selected_effectors.iter().skip(*idx)
-                    )
-                    .filter(|(_, _, _, selected)| **selected)
-                    .for_each(|(effector_node, effector_pose, offset, _)| {
-                        // The slice of the column is itself a column-first matrix
-                        effector_node.get().partial_derivative(
-                            effector_pose,
-                            joint_node.get(),
-                            joint_pose,
-                            col,
-                            // &mut col[*offset..*offset + effector_node.get().effector_size()],
-                            *offset,
-                        );
-                    });
+                .filter(|(_, _, selected)| **selected)
+                .for_each(|((effector, effector_pose), offset, _)| {
+                    // The slice of the column is itself a column-first matrix
+                    if param_count > 1 {
+                        let (target, dof) = (&mut col[*offset..], *dof);
+                        effector.partial_derivative_dof(effector_pose, joint, joint_pose, joint_params, dof, target);
+                    } else {
+                        effector.partial_derivative(effector_pose, joint, joint_pose, col, *offset);
+                    }
                 });
+            };
+
+            // FIXME: Row below can panic .. handle errors
+            #[cfg(not(feature = "rayon"))]
+            self.matrix
+                .chunks_mut(self.rows)
+                .zip(&joints)
+                .for_each(|(col, joint)| column(col, joint));
+            #[cfg(feature = "rayon")]
+            self.matrix
+                .par_chunks_mut(self.rows)
+                .zip(&joints)
+                .for_each(|(col, joint)| column(col, joint));
         }
     }
 }
 
+/// Reference implementation of [Differentiable] that approximates the Jacobian matrix numerically with central
+/// differences of the effectors, e.g., for validating the partial derivatives of a custom [Rigid]. It only relies
+/// on [Rigid::transform] and [Rigid::effector] and is much slower than [DifferentiableModel], which is used for
+/// the bookkeeping (selection and layout). Note that the numerical derivatives of orientations given as rotation
+/// vectors differ from the angular velocities of the analytic Jacobian.
+#[derive(Debug, Default, Clone)]
+pub struct FiniteDifferenceModel<F: Float> {
+    model: DifferentiableModel<F>,
+    /// Perturbation of each parameter
+    step: F,
+    matrix: Vec<F>,
+}
+
+impl<F: Float + Default> FiniteDifferenceModel<F> {
+    /// Each parameter is perturbed by `±step`
+    pub fn new(step: F) -> Self {
+        Self {
+            step,
+            ..Default::default()
+        }
+    }
+}
+
+impl<F: Float + MaybeSendSync> Differentiable<F> for FiniteDifferenceModel<F> {
+    fn jacobian(&self) -> &[F] {
+        &self.matrix
+    }
+
+    fn flat_effectors(&self) -> &[F] {
+        self.model.flat_effectors()
+    }
+
+    fn effectors(&self) -> Vec<&[F]> {
+        self.model.effectors()
+    }
+
+    fn setup<T, R, I>(
+        &mut self,
+        tree: &T,
+        selected_joints: &[&I],
+        selected_effectors: &[&I],
+    ) -> Result<(), MannequinError<I>>
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        self.model.setup(tree, selected_joints, selected_effectors)?;
+        self.matrix.clear();
+        self.matrix.resize(self.model.rows() * self.model.cols(), F::zero());
+        Ok(())
+    }
+
+    fn compute<T, R, I>(&mut self, tree: &T, params: &[R::FloatType], selection: ComputeSelection)
+    where
+        T: DepthFirstIterable<R, I>,
+        R: Rigid<FloatType = F>,
+        I: Eq + Clone + Hash + Debug,
+    {
+        if matches!(selection, ComputeSelection::JacobianOnly | ComputeSelection::All) {
+            let rows = self.model.rows();
+            let two = F::one() + F::one();
+            let mut perturbed = params.to_vec();
+            // Joints without parameters have a zero column
+            let columns = jacobian_columns(tree, self.model.active());
+            for (col, (_, param)) in self.matrix.chunks_mut(rows.max(1)).zip(columns) {
+                let Some(param) = param else {
+                    col.iter_mut().for_each(|x| *x = F::zero());
+                    continue;
+                };
+                perturbed[param] = params[param] + self.step;
+                self.model.compute(tree, &perturbed, ComputeSelection::EffectorsOnly);
+                col.copy_from_slice(self.model.flat_effectors());
+                perturbed[param] = params[param] - self.step;
+                self.model.compute(tree, &perturbed, ComputeSelection::EffectorsOnly);
+                col.iter_mut()
+                    .zip(self.model.flat_effectors())
+                    .for_each(|(x, below)| *x = (*x - *below) / (two * self.step));
+                perturbed[param] = params[param];
+            }
+        }
+        // Last, such that the effectors refer to `params`
+        self.model.compute(tree, params, ComputeSelection::EffectorsOnly);
+    }
+
+    fn rows(&self) -> usize {
+        self.model.rows()
+    }
+
+    fn cols(&self) -> usize {
+        self.model.cols()
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.model.shape()
+    }
+
+    fn active(&self) -> &[bool] {
+        self.model.active()
+    }
+
+    fn effector_range(&self, index: usize) -> Option<Range<usize>> {
+        self.model.effector_range(index)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -306,16 +774,18 @@ mod tests {
 
         let mut jacobian = DifferentiableModel::<f64>::new();
 
-        jacobian.setup(
-            &tree,
-            &[
-                &"link1".to_string(),
-                &"link2".to_string(),
-                &"link3".to_string(),
-                &"link4".to_string(),
-            ],
-            &[&"link2".to_string(), &"link4".to_string()],
-        );
+        jacobian
+            .setup(
+                &tree,
+                &[
+                    &"link1".to_string(),
+                    &"link2".to_string(),
+                    &"link3".to_string(),
+                    &"link4".to_string(),
+                ],
+                &[&"link2".to_string(), &"link4".to_string()],
+            )
+            .unwrap();
 
         jacobian.compute(
             &tree,
@@ -339,4 +809,189 @@ mod tests {
         assert_eq!(jacobian.shape(), (6, 4));
         assert_abs_diff_eq!(result, target, epsilon = 1e-6);
     }
+
+    /// The five-link sample tree used in [test_jacobian]. Links 2, 4 and 5 have an effector.
+    fn sample_tree() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+
+        let ref1 = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "link1".to_string());
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "link2".to_string(),
+            &ref1,
+        )
+        .unwrap();
+        let ref3 = tree
+            .add(Segment::new(&trafo, Axis::RotationZ, None), "link3".to_string(), &ref1)
+            .unwrap();
+        let ref4 = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "link4".to_string(),
+                &ref3,
+            )
+            .unwrap();
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "link5".to_string(),
+            &ref4,
+        )
+        .unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_finite_differences() {
+        let tree = sample_tree();
+        let effectors = [&"link2".to_string(), &"link4".to_string(), &"link5".to_string()];
+        let joints = [&"link1".to_string(), &"link3".to_string(), &"link4".to_string()];
+        let params = [0.3, -0.5, 1.1, 0.7, -0.2];
+
+        let mut analytic = DifferentiableModel::<f64>::new();
+        analytic.setup(&tree, &joints, &effectors).unwrap();
+        analytic.compute(&tree, &params, ComputeSelection::All);
+        let mut numeric = FiniteDifferenceModel::<f64>::new(1e-6);
+        numeric.setup(&tree, &joints, &effectors).unwrap();
+        numeric.compute(&tree, &params, ComputeSelection::All);
+
+        assert_eq!(numeric.shape(), (9, 3));
+        assert_eq!(numeric.flat_effectors(), analytic.flat_effectors());
+        izip!(numeric.jacobian(), analytic.jacobian()).for_each(|(n, a)| assert_abs_diff_eq!(n, a, epsilon = 1e-5));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_jacobian() {
+        let tree = sample_tree();
+        let effectors = [&"link2".to_string(), &"link4".to_string(), &"link5".to_string()];
+        let params = [0.3, -0.5, 1.1, 0.7, -0.2];
+        let jacobian = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let mut model = DifferentiableModel::<f64>::new();
+            model.setup(&tree, &[], &effectors).unwrap();
+            pool.install(|| model.compute(&tree, &params, ComputeSelection::JacobianOnly));
+            model.jacobian().to_vec()
+        };
+
+        let serial = jacobian(1);
+        assert_eq!(serial.len(), 9 * 5);
+        assert!(serial.iter().any(|value| *value != 0.0));
+        assert_eq!(jacobian(4), serial);
+    }
+
+    #[test]
+    fn test_total_effector_rows() {
+        let tree = sample_tree();
+        let selected = [&"link2".to_string(), &"link4".to_string()];
+
+        let mut jacobian = DifferentiableModel::<f64>::new();
+        jacobian.setup(&tree, &[], &selected).unwrap();
+
+        assert_eq!(total_effector_rows(&tree, &selected), jacobian.rows());
+        assert_eq!(total_effector_rows(&tree, &selected), 6);
+        // All three nodes with an effector
+        assert_eq!(total_effector_rows::<_, Segment, _>(&tree, &[]), 9);
+    }
+
+    #[test]
+    fn test_joint_sensitivities() {
+        let tree = sample_tree();
+        let tip = "link5".to_string();
+
+        let mut jacobian = DifferentiableModel::<f64>::new();
+        jacobian.setup(&tree, &[], &[&tip]).unwrap();
+        // Extended along the x-axis
+        jacobian.compute(&tree, &[0.0; 5], ComputeSelection::JacobianOnly);
+
+        // Lateral (y) position of the tip. The lever decreases along the chain
+        let result = jacobian.joint_sensitivities(&tree, &tip, 1).unwrap();
+        let ids = result.iter().map(|(id, _)| id.as_str()).collect_vec();
+        assert_eq!(ids, &["link1", "link3", "link4", "link5", "link2"]);
+        assert_abs_diff_eq!(result[0].1, 40.0, epsilon = 1e-9);
+
+        assert!(matches!(
+            jacobian.joint_sensitivities(&tree, &"link4".to_string(), 1),
+            Err(MannequinError::UnknownNode(_))
+        ));
+        assert!(matches!(
+            jacobian.joint_sensitivities(&tree, &tip, 3),
+            Err(MannequinError::DimensionMismatch(3))
+        ));
+    }
+
+    #[test]
+    fn test_effector_centroid() {
+        let tree = sample_tree();
+
+        let mut jacobian = DifferentiableModel::<f64>::new();
+        assert!(jacobian.effector_centroid().is_empty());
+
+        jacobian
+            .setup(&tree, &[], &[&"link2".to_string(), &"link4".to_string()])
+            .unwrap();
+        // link2 is bent upwards and ends at (20, 10, 0), link4 ends at (40, 0, 0)
+        jacobian.compute(
+            &tree,
+            &[0.0, std::f64::consts::FRAC_PI_2, 0.0, 0.0, 0.0],
+            ComputeSelection::EffectorsOnly,
+        );
+
+        assert_abs_diff_eq!(
+            Array1::from(jacobian.effector_centroid()),
+            array![30.0, 5.0, 0.0],
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_setup_unknown_node() {
+        let tree = sample_tree();
+        let tip = "link5".to_string();
+        let typo = "lnik3".to_string();
+
+        let mut jacobian = DifferentiableModel::<f64>::new();
+        assert!(matches!(
+            jacobian.setup(&tree, &[&typo], &[&tip]),
+            Err(MannequinError::UnknownNode(id)) if id == typo
+        ));
+        assert!(matches!(
+            jacobian.setup(&tree, &[], &[&tip, &typo]),
+            Err(MannequinError::UnknownNode(id)) if id == typo
+        ));
+        assert!(jacobian.setup(&tree, &[], &[&tip]).is_ok());
+        assert_eq!(jacobian.shape(), (3, 5));
+    }
+
+    #[test]
+    fn test_select_effectors_from_tree() {
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let segment = |effector: bool| Segment::new(&trafo, Axis::RotationZ, effector.then(|| trafo.clone()));
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let ref1 = tree.set_root(segment(false), "link1".to_string());
+        tree.add(segment(true), "link2".to_string(), &ref1).unwrap();
+        let ref3 = tree.add(segment(false), "link3".to_string(), &ref1).unwrap();
+        let ref4 = tree.add(segment(true), "link4".to_string(), &ref3).unwrap();
+        tree.add(segment(false), "link5".to_string(), &ref4).unwrap();
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+
+        assert_eq!(select_effectors_from_tree(&tree), ["link2", "link4"]);
+
+        let params = [0.1, 0.2, 0.3, 0.4, 0.5];
+        let mut explicit = DifferentiableModel::<f64>::new();
+        explicit
+            .setup(&tree, &[], &[&"link2".to_string(), &"link4".to_string()])
+            .unwrap();
+        explicit.compute(&tree, &params, ComputeSelection::All);
+        let mut automatic = DifferentiableModel::<f64>::new();
+        automatic.setup_with(&tree, &[], None).unwrap();
+        automatic.compute(&tree, &params, ComputeSelection::All);
+
+        assert_eq!(automatic.shape(), (6, 5));
+        assert_eq!(automatic.flat_effectors(), explicit.flat_effectors());
+        assert_eq!(automatic.jacobian(), explicit.jacobian());
+    }
 }
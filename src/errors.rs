@@ -18,10 +18,52 @@ pub enum MannequinError<NodeID> {
     NotUnique(NodeID),
     #[error("Wrong array dimensions: {0}")]
     DimensionMismatch(usize),
+    #[error("Parameter out of the joint limits: {0}")]
+    LimitViolation(NodeID),
+    #[error("Node not in depth-first order: {0}")]
+    NotDepthFirst(NodeID),
+    #[error("Node has children: {0}")]
+    HasChildren(NodeID),
+    #[error("Node would become its own ancestor: {0}")]
+    WouldCycle(NodeID),
+    #[error("Tree structures differ at node: {0}")]
+    StructureMismatch(NodeID),
+    #[error("Total mass is zero")]
+    ZeroMass,
+    #[error("Articulated inertia is singular at: {0}")]
+    SingularInertia(NodeID),
+    #[error("Node not on the chain to the effector: {0}")]
+    NotInChain(NodeID),
+    #[error("No movable joint on the chain to: {0}")]
+    NoMovableJoint(NodeID),
+    #[error("Joint type not supported: {0}")]
+    UnsupportedJoint(NodeID),
+    #[error("Invalid metadata value at: {0}")]
+    InvalidMetadata(NodeID),
     // Errors specific to ndarray
     #[cfg(feature = "ndarray")]
     #[error("Error raised by `ndarray`: ")]
     ShapeError(#[from] ndarray::ShapeError),
+    // Errors specific to serialization
+    #[cfg(feature = "serde")]
+    #[error("Error raised by `serde_json`: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "binary")]
+    #[error("Invalid binary data: {0}")]
+    InvalidBinary(String),
+    #[cfg(feature = "binary")]
+    #[error("Unsupported binary format version: {0}")]
+    UnsupportedVersion(u16),
+    // Errors specific to importers
+    #[cfg(feature = "urdf")]
+    #[error("Error raised by `roxmltree`: {0}")]
+    XmlError(#[from] roxmltree::Error),
+    #[cfg(feature = "urdf")]
+    #[error("Invalid URDF: {0}")]
+    InvalidUrdf(String),
+    #[cfg(feature = "gltf")]
+    #[error("Invalid glTF skeleton: {0}")]
+    InvalidGltf(String),
     // Add errors specific to nalgebra
     // Add errors specific to faer
 }
@@ -1,7 +1,171 @@
-use faer::{linalg::solvers::Solve, ColRef, Mat, MatRef, Scale};
+//! Module for the implementations using the faer backend. Contains the basic calculus required
+use faer::{linalg::solvers::Solve, Col, ColRef, Mat, MatRef, Scale};
 use faer_traits::RealField;
 use num_traits::Float;
 
+pub mod robot;
+
+/// Creates a 4x4 matrix from its rows
+fn from_rows(rows: [[f64; 4]; 4]) -> Mat<f64> {
+    Mat::from_fn(4, 4, |i, j| rows[i][j])
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the x axis.
+pub fn rotate_x_4x4(param: f64) -> Mat<f64> {
+    from_rows([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, param.cos(), -param.sin(), 0.0],
+        [0.0, param.sin(), param.cos(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the y axis.
+pub fn rotate_y_4x4(param: f64) -> Mat<f64> {
+    from_rows([
+        [param.cos(), 0.0, param.sin(), 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [-param.sin(), 0.0, param.cos(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the z axis.
+pub fn rotate_z_4x4(param: f64) -> Mat<f64> {
+    from_rows([
+        [param.cos(), -param.sin(), 0.0, 0.0],
+        [param.sin(), param.cos(), 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around an arbitrary `axis` (normalized internally) using Rodrigues'
+/// formula. A zero angle yields the identity for any axis.
+///
+/// # Panics
+/// If `axis` is zero (for a non-zero angle).
+pub fn rotate_axis_4x4(axis: &[f64; 3], param: f64) -> Mat<f64> {
+    if param == 0.0 {
+        return Mat::identity(4, 4);
+    }
+    let norm = axis.iter().map(|a| a * a).sum::<f64>().sqrt();
+    assert!(norm > 0.0, "Rotation axis must not be zero");
+    let [x, y, z] = axis.map(|a| a / norm);
+    let (sin, cos) = param.sin_cos();
+    let versine = 1.0 - cos;
+    from_rows([
+        [
+            cos + x * x * versine,
+            x * y * versine - z * sin,
+            x * z * versine + y * sin,
+            0.0,
+        ],
+        [
+            y * x * versine + z * sin,
+            cos + y * y * versine,
+            y * z * versine - x * sin,
+            0.0,
+        ],
+        [
+            z * x * versine - y * sin,
+            z * y * versine + x * sin,
+            cos + z * z * versine,
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the vector `[x, y, z]`.
+pub fn translate_4x4(x: f64, y: f64, z: f64) -> Mat<f64> {
+    from_rows([
+        [1.0, 0.0, 0.0, x],
+        [0.0, 1.0, 0.0, y],
+        [0.0, 0.0, 1.0, z],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the x axis.
+pub fn translate_x_4x4(param: f64) -> Mat<f64> {
+    translate_4x4(param, 0.0, 0.0)
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the y axis.
+pub fn translate_y_4x4(param: f64) -> Mat<f64> {
+    translate_4x4(0.0, param, 0.0)
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the z axis.
+pub fn translate_z_4x4(param: f64) -> Mat<f64> {
+    translate_4x4(0.0, 0.0, param)
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along an arbitrary `direction` (normalized internally) by the
+/// distance `param`.
+///
+/// # Panics
+/// If `direction` is zero (for a non-zero distance).
+pub fn translate_axis_4x4(direction: &[f64; 3], param: f64) -> Mat<f64> {
+    if param == 0.0 {
+        return Mat::identity(4, 4);
+    }
+    let norm = direction.iter().map(|d| d * d).sum::<f64>().sqrt();
+    assert!(norm > 0.0, "Translation axis must not be zero");
+    let [x, y, z] = direction.map(|d| d * param / norm);
+    translate_4x4(x, y, z)
+}
+
+/// inverts a homogeneous, 4x4 transformation matrix.
+pub fn invert_transformation_4x4(trafo: &Mat<f64>) -> Mat<f64> {
+    Mat::from_fn(4, 4, |i, j| match (i, j) {
+        (3, _) => (j == 3) as u8 as f64,
+        (_, 3) => -(0..3).map(|k| trafo[(k, i)] * trafo[(k, 3)]).sum::<f64>(),
+        _ => trafo[(j, i)],
+    })
+}
+
+/// Converts the rotational part of a homogeneous transformation into a rotation vector (axis times angle in
+/// `[0, π]`), i.e., the logarithmic map of SO(3).
+pub fn rotation_vector(trafo: &Mat<f64>) -> Col<f64> {
+    let r = |i: usize, j: usize| trafo[(i, j)];
+    let cos = ((r(0, 0) + r(1, 1) + r(2, 2) - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+    // Twice the sine times the axis
+    let skew = [r(2, 1) - r(1, 2), r(0, 2) - r(2, 0), r(1, 0) - r(0, 1)];
+
+    if angle < 1e-6 {
+        // first-order approximation
+        Col::from_fn(3, |i| skew[i] / 2.0)
+    } else if std::f64::consts::PI - angle < 1e-6 {
+        // The skew-symmetric part vanishes, the axis is the dominant column of `R + I`
+        let column = (0..3).max_by(|a, b| r(*a, *a).total_cmp(&r(*b, *b))).unwrap();
+        let axis = Col::from_fn(3, |i| r(i, column) + (i == column) as u8 as f64);
+        let norm = axis.norm_l2();
+        axis * Scale(angle / norm)
+    } else {
+        Col::from_fn(3, |i| skew[i] * angle / (2.0 * angle.sin()))
+    }
+}
+
+/// Shortest-path rotation (as rotation vector) from the orientation of the homogeneous transformation `current`
+/// to the one of `target`, i.e., the rotation vector of `target·currentᵀ`.
+pub fn orientation_error(current: &Mat<f64>, target: &Mat<f64>) -> Col<f64> {
+    rotation_vector(&(target * current.transpose()))
+}
+
+/// Shortest-path rotation from the orientation `current` to `target`, both given as rotation vectors, as a rotation
+/// vector (see [orientation_error]).
+pub fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+    let rotation = |vector: &[f64]| {
+        let vector = [vector[0], vector[1], vector[2]];
+        rotate_axis_4x4(&vector, vector.iter().map(|v| v * v).sum::<f64>().sqrt())
+    };
+    let error = orientation_error(&rotation(current), &rotation(target));
+    [error[0], error[1], error[2]]
+}
+
 /// Solves the normal equations with the [faer crate](https://docs.rs/faer/latest/faer/index.html)
 ///
 /// Notes: I am not too happy with constructing the normal equations, but it works for now.
@@ -9,15 +173,22 @@ use num_traits::Float;
 /// * https://math.stackexchange.com/questions/3518247/least-squares-using-qr-for-underdetermined-system
 /// * https://eigen.tuxfamily.org/dox/group__TutorialLinearAlgebra.html
 /// * https://math.stackexchange.com/a/2852117
-pub fn solve_linear<F>(matrix: &[F], rows: usize, cols: usize, vector: &[F], parameters: &mut [F], limit_radians: F)
-where
+pub fn solve_linear<F>(
+    matrix: &[F],
+    rows: usize,
+    cols: usize,
+    vector: &[F],
+    damping: F,
+    parameters: &mut [F],
+    limit_radians: F,
+) where
     F: RealField + Float,
 {
     let matrix = MatRef::from_column_major_slice(matrix, rows, cols);
     let vector = ColRef::from_slice(vector);
 
     let diff = matrix.transpose() * vector;
-    let matrix = matrix.transpose() * matrix + Mat::<F>::identity(cols, cols) * 1e-5;
+    let matrix = matrix.transpose() * matrix + Mat::<F>::identity(cols, cols) * Scale(damping);
 
     let lu = matrix.partial_piv_lu();
 
@@ -26,17 +197,39 @@ where
     // limit update to 10 degrees (assuming that the function is near linear in that range)
     let norm = update.norm_l2();
 
-    update /= Scale(norm);
-
-    let limited = limit_radians.min(norm);
+    // A vanishing update (e.g., at the optimum) cannot be normalized
+    if norm > F::zero() {
+        update /= Scale(norm);
 
-    update *= Scale(limited);
+        let limited = limit_radians.min(norm);
 
-    dbg!(limited, update.norm_l2());
+        update *= Scale(limited);
+    }
 
     update.iter().zip(parameters).for_each(|(a, b)| *b = *a);
 }
 
+/// Computes `J⁺ e` by solving the normal equations of the smaller dimension: `(JᵀJ) x = Jᵀe` if the system is
+/// over-determined, and `x = Jᵀ y` with `(JJᵀ) y = e` otherwise. A tiny regularization keeps singular
+/// configurations solvable.
+pub fn apply_pseudo_inverse<F>(matrix: &[F], rows: usize, cols: usize, vector: &[F], result: &mut [F])
+where
+    F: RealField + Float,
+{
+    let matrix = MatRef::from_column_major_slice(matrix, rows, cols);
+    let vector = ColRef::from_slice(vector);
+    let regularization = F::from(1e-12).unwrap();
+
+    let solution = if rows >= cols {
+        let normal = matrix.transpose() * matrix + Mat::<F>::identity(cols, cols) * Scale(regularization);
+        normal.partial_piv_lu().solve(matrix.transpose() * vector)
+    } else {
+        let normal = matrix * matrix.transpose() + Mat::<F>::identity(rows, rows) * Scale(regularization);
+        matrix.transpose() * normal.partial_piv_lu().solve(vector)
+    };
+    solution.iter().zip(result).for_each(|(a, b)| *b = *a);
+}
+
 #[cfg(test)]
 mod test {
     use std::f32::consts::PI;
@@ -50,6 +243,7 @@ mod test {
         let target = [0f32; 3];
         let limit = PI / 18.0;
 
-        solve_linear(&matrix, 3, 6, &target, &mut param, limit);
+        solve_linear(&matrix, 3, 6, &target, 1e-5, &mut param, limit);
+        assert!(param.iter().all(|p| *p == 0.0));
     }
 }
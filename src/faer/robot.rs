@@ -0,0 +1,314 @@
+//! Kinematics as encountered in robotics (see [crate::ndarray::robot]) with faer matrices. The updates of the
+//! inverse kinematics are computed by [super::solve_linear].
+
+use super::{
+    apply_pseudo_inverse, invert_transformation_4x4, orientation_error, rotate_axis_4x4, rotate_x_4x4, rotate_y_4x4,
+    rotate_z_4x4, rotation_vector, rotation_vector_difference, solve_linear, translate_axis_4x4, translate_x_4x4,
+    translate_y_4x4, translate_z_4x4,
+};
+use crate::Rigid;
+use core::fmt;
+use faer::{Col, Mat};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Axis {
+    RotationX,
+    RotationY,
+    #[default]
+    RotationZ,
+    Rotation([f64; 3]),
+    TranslationX,
+    TranslationY,
+    TranslationZ,
+    Translation([f64; 3]),
+    /// Rigid connection without a degree of freedom (consumes no parameter)
+    Fixed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Mode {
+    /// Position of the effector (3 values)
+    #[default]
+    Position,
+    /// Position and orientation as rotation vector (6 values)
+    Pose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    link: Mat<f64>,
+    axis: Axis,
+    mode: Mode,
+    effector_local: Option<Mat<f64>>,
+}
+
+impl Segment {
+    pub fn new(from_parent: &Mat<f64>, axis: Axis, effector: Option<Mat<f64>>) -> Self {
+        Self {
+            link: from_parent.clone(),
+            axis,
+            mode: Mode::Position,
+            effector_local: effector,
+        }
+    }
+
+    /// Selects what the effector represents (and thus its dimension, see [Mode]).
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bone, link: {:?}, Axis: {:?}", self.link, self.axis)
+    }
+}
+
+impl Rigid for Segment {
+    type Transformation = Mat<f64>;
+
+    type Point = Col<f64>;
+
+    type FloatType = f64;
+
+    type NodeId = String;
+
+    fn transform(&self, params: &[f64], index: usize) -> Self::Transformation {
+        let joint = match &self.axis {
+            Axis::RotationX => rotate_x_4x4(params[index]),
+            Axis::RotationY => rotate_y_4x4(params[index]),
+            Axis::RotationZ => rotate_z_4x4(params[index]),
+            Axis::Rotation(axis) => rotate_axis_4x4(axis, params[index]),
+            Axis::TranslationX => translate_x_4x4(params[index]),
+            Axis::TranslationY => translate_y_4x4(params[index]),
+            Axis::TranslationZ => translate_z_4x4(params[index]),
+            Axis::Translation(axis) => translate_axis_4x4(axis, params[index]),
+            Axis::Fixed => Mat::identity(4, 4),
+        };
+        &self.link * joint
+    }
+
+    fn param_count(&self) -> usize {
+        if self.axis == Axis::Fixed {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_revolute(&self) -> bool {
+        matches!(
+            self.axis,
+            Axis::RotationX | Axis::RotationY | Axis::RotationZ | Axis::Rotation(_)
+        )
+    }
+
+    fn globalize(&self, other: &Self::Point) -> Self::Point {
+        &self.link * other
+    }
+
+    fn localize(&self, other: &Self::Point) -> Self::Point {
+        invert_transformation_4x4(&self.link) * other
+    }
+
+    fn neutral_element() -> Self::Transformation {
+        Mat::identity(4, 4)
+    }
+
+    fn concat(first: &Self::Transformation, second: &Self::Transformation) -> Self::Transformation {
+        first * second
+    }
+
+    fn invert(trafo: &Self::Transformation) -> Self::Transformation {
+        invert_transformation_4x4(trafo)
+    }
+
+    fn origin(trafo: &Self::Transformation) -> Option<[f64; 3]> {
+        Some([trafo[(0, 3)], trafo[(1, 3)], trafo[(2, 3)]])
+    }
+
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point {
+        orientation_error(current, target)
+    }
+
+    fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+        rotation_vector_difference(current, target)
+    }
+
+    fn dim(&self) -> usize {
+        match self.mode {
+            Mode::Position => 3,
+            Mode::Pose => 6,
+        }
+    }
+
+    fn effector_count(&self) -> usize {
+        if self.effector_local.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn partial_derivative(
+        &self,
+        pose: &Self::Transformation,
+        joint: &Self,
+        joint_pose: &Self::Transformation,
+        buffer: &mut [f64],
+        offset: usize,
+    ) {
+        // Formula: axis_in_world x (end_effector_world - pivod_in_world) for revolute joints and
+        // axis_in_world for prismatic joints
+        let local_axis = match &joint.axis {
+            Axis::RotationX | Axis::TranslationX => [1.0, 0.0, 0.0],
+            Axis::RotationY | Axis::TranslationY => [0.0, 1.0, 0.0],
+            Axis::RotationZ | Axis::TranslationZ => [0.0, 0.0, 1.0],
+            Axis::Rotation(axis) | Axis::Translation(axis) => {
+                let norm = axis.iter().map(|a| a * a).sum::<f64>().sqrt();
+                axis.map(|a| a / norm)
+            }
+            Axis::Fixed => [0.0; 3],
+        };
+        let axis = [0, 1, 2].map(|i| (0..3).map(|k| joint_pose[(i, k)] * local_axis[k]).sum::<f64>());
+
+        let pose = match &self.effector_local {
+            Some(effector) => pose * effector,
+            None => pose.clone(),
+        };
+        let lever = [0, 1, 2].map(|i| pose[(i, 3)] - joint_pose[(i, 3)]);
+
+        let target = &mut buffer[offset..offset + self.effector_size()];
+        if joint.is_revolute() {
+            target[0] = axis[1] * lever[2] - axis[2] * lever[1];
+            target[1] = axis[2] * lever[0] - axis[0] * lever[2];
+            target[2] = axis[0] * lever[1] - axis[1] * lever[0];
+        } else {
+            target[0..3].copy_from_slice(&axis);
+        }
+        if self.mode == Mode::Pose {
+            // The angular velocity caused by a revolute joint is its axis
+            let angular = if joint.is_revolute() { axis } else { [0.0; 3] };
+            target[3..6].copy_from_slice(&angular);
+        }
+    }
+
+    /// Get the coordinates of the effector in the global (or an arbitrary) system.
+    fn effector(&self, pose: &Self::Transformation, buffer: &mut [f64], offset: usize) {
+        let target = &mut buffer[offset..offset + self.effector_size()];
+
+        if let Some(effector) = &self.effector_local {
+            let frame = pose * effector;
+            (0..3).for_each(|i| target[i] = frame[(i, 3)]);
+            if self.mode == Mode::Pose {
+                target[3..6]
+                    .iter_mut()
+                    .zip(rotation_vector(&frame).iter())
+                    .for_each(|(t, r)| *t = *r);
+            }
+        } else {
+            panic!("Should not call this method if no effector is defined")
+        }
+    }
+
+    fn solve_linear(
+        matrix: &[f64],
+        rows: usize,
+        cols: usize,
+        vector: &[f64],
+        damping: f64,
+        limit: f64,
+        parameters: &mut [f64],
+    ) {
+        solve_linear(matrix, rows, cols, vector, damping, parameters, limit);
+    }
+
+    fn apply_pseudo_inverse(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], result: &mut [f64]) {
+        apply_pseudo_inverse(matrix, rows, cols, vector, result);
+    }
+}
+
+pub type LinkNodeId = <Segment as Rigid>::NodeId;
+
+#[cfg(test)]
+mod tests {
+
+    // The trees and expectations are the ones of the tests with the `ndarray` backend
+
+    use super::*;
+    use crate::{
+        DepthFirstArenaTree, DifferentiableModel, DifferentialInverseModel, DirectedArenaTree, DirectionIterable,
+        Forward, ForwardModel, Inverse, UpdateRule,
+    };
+    use approx::assert_abs_diff_eq;
+    use itertools::Itertools;
+
+    /// The five-link tree of the ndarray tests. Links 2, 4 and 5 have an effector.
+    fn sample_tree() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let trafo = translate_x_4x4(10.0);
+        let segment = |effector: bool| Segment::new(&trafo, Axis::RotationZ, effector.then(|| trafo.clone()));
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let ref1 = tree.set_root(segment(false), "link1".to_string());
+        tree.add(segment(true), "link2".to_string(), &ref1).unwrap();
+        let ref3 = tree.add(segment(false), "link3".to_string(), &ref1).unwrap();
+        let ref4 = tree.add(segment(true), "link4".to_string(), &ref3).unwrap();
+        tree.add(segment(true), "link5".to_string(), &ref4).unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_fk() {
+        let tree = sample_tree();
+        let effectors = ["link2", "link4"].map(String::from);
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors.iter().collect_vec()).unwrap();
+
+        let result = fk
+            .solve(&tree, &[0.0, 0.0, std::f64::consts::FRAC_PI_2, 0.0, 0.3])
+            .concat();
+        assert_abs_diff_eq!(&result[..], &[30.0, 0.0, 0.0, 20.0, 20.0, 0.0][..], epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_ik() {
+        let tree = sample_tree();
+        let joints = ["link1", "link2", "link3", "link4"].map(String::from);
+        let effectors = ["link2", "link4"].map(String::from);
+        let targets = [20.0, 0.0, 0.0, 20.0, 10.0, 0.0];
+        let new_ik = |max_iterations_count| {
+            let mut ik = DifferentialInverseModel::new(
+                42,
+                max_iterations_count,
+                1e-12,
+                DifferentiableModel::new(),
+                1.0,
+                UpdateRule::PseudoInverse,
+            );
+            ik.setup(&tree, &joints.iter().collect_vec(), &effectors.iter().collect_vec())
+                .unwrap();
+            ik
+        };
+
+        let mut params = [0.0, 0.0, std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2, 0.0];
+        let result = new_ik(100).solve(&tree, &mut params, &targets);
+        assert!(result.squared_error < 1e-12, "{result:?}");
+
+        // Far from the targets (reachable with a large rotation), the updates are capped by the limit
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors.iter().collect_vec()).unwrap();
+        let targets = fk.solve(&tree, &[1.2, -0.4, 0.3, 0.5, 0.0]).concat();
+        let limit = std::f64::consts::PI / 180.0 * 40.0;
+        let mut ik = new_ik(1);
+        ik.set_step_limit(limit);
+        let mut params = [0.0; 5];
+        let mut changes = vec![];
+        for _ in 0..5 {
+            let previous = params;
+            ik.solve(&tree, &mut params, &targets);
+            let change = params.iter().zip(&previous).map(|(p, q)| (p - q).powi(2)).sum::<f64>();
+            changes.push(change.sqrt());
+        }
+        assert_abs_diff_eq!(changes[0], limit, epsilon = 1e-9);
+        assert!(changes.iter().all(|change| *change <= limit + 1e-9), "{changes:?}");
+    }
+}
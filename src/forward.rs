@@ -3,11 +3,17 @@
 //! Contains additional useful extension to the iterators over a tree
 //! that can be shared by implementers of the trait.
 
-use std::marker::PhantomData;
+use std::{collections::HashSet, f64::consts::PI, hash::Hash, marker::PhantomData};
 
-use num_traits::Float;
+use itertools::{izip, Itertools};
+use num_traits::{Float, Zero};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
-use crate::{differentiable::ComputeSelection, DepthFirstIterable, Differentiable, NodeLike, Rigid};
+use crate::{
+    differentiable::{param_offsets, parameter_count, ComputeSelection},
+    DepthFirstIterable, Differentiable, DifferentiableModel, MannequinError, MaybeSendSync, NodeLike, Rigid,
+};
 
 /// Trait representing a stateful forward kinematics algorithm. It allows selecting the effectors to be
 /// computed and thus a specific (or multiple) kinematic chain(s).
@@ -16,7 +22,12 @@ where
     IT: DepthFirstIterable<RB, RB::NodeId>,
     RB: Rigid,
 {
-    fn setup(&mut self, tree: &IT, selected_effectors: &[&<RB as Rigid>::NodeId]);
+    /// Selects the effectors to be computed. Fails with [MannequinError::UnknownNode] if one is not in the tree.
+    fn setup(
+        &mut self,
+        tree: &IT,
+        selected_effectors: &[&<RB as Rigid>::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>>;
     fn solve(&mut self, tree: &IT, params: &[RB::FloatType]) -> Vec<&[RB::FloatType]>;
 }
 
@@ -41,6 +52,74 @@ where
             p: PhantomData,
         }
     }
+
+    /// Forward kinematics for many frames on the same tree (e.g., motion capture). Returns the flat effectors
+    /// (as in [Differentiable::flat_effectors]) of each parameter set in `params`. Call [Forward::setup] first.
+    ///
+    /// The frames are computed one after the other reusing the buffers of the model. With the `rayon` feature,
+    /// they are computed in parallel into a pre-sized output instead, each thread with its own clone of the model.
+    pub fn solve_batch<IT, RB>(&mut self, tree: &IT, params: &[&[F]]) -> Vec<Vec<F>>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId> + MaybeSendSync,
+        RB: Rigid<FloatType = F>,
+        F: MaybeSendSync,
+        D: Clone + MaybeSendSync,
+    {
+        debug_assert!(params.iter().all(|params| params.len() == parameter_count(tree)));
+        #[cfg(not(feature = "rayon"))]
+        let result = params
+            .iter()
+            .map(|params| {
+                self.differential_model
+                    .compute(tree, params, ComputeSelection::EffectorsOnly);
+                self.differential_model.flat_effectors().to_vec()
+            })
+            .collect();
+
+        #[cfg(feature = "rayon")]
+        let result = {
+            let model = &self.differential_model;
+            let mut result = vec![vec![F::zero(); model.rows()]; params.len()];
+            result
+                .par_iter_mut()
+                .zip(params)
+                .for_each_with(model.clone(), |model, (effectors, params)| {
+                    model.compute(tree, params, ComputeSelection::EffectorsOnly);
+                    effectors.copy_from_slice(model.flat_effectors());
+                });
+            result
+        };
+
+        result
+    }
+
+    /// Same as [Forward::solve] but accumulates the world transformations in the wider representation of [Widen]
+    /// (see [Differentiable::compute_wide]), e.g., for long chains in single precision. Call [Forward::setup] first.
+    pub fn solve_wide<IT, RB>(&mut self, tree: &IT, params: &[F]) -> Vec<&[F]>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Widen<FloatType = F>,
+    {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        self.differential_model
+            .compute_wide(tree, params, ComputeSelection::EffectorsOnly);
+        self.differential_model.effectors()
+    }
+
+    /// World transformations of all nodes (in depth-first order) for `params`, e.g., for drawing the bones of a
+    /// skeleton. Unlike [Forward::solve], the result does not depend on the selection of effectors and does not
+    /// require [Forward::setup].
+    pub fn poses<IT, RB>(&self, tree: &IT, params: &[F]) -> Vec<(RB::NodeId, RB::Transformation)>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+    {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        tree.iter()
+            .accumulate(params, tree.len())
+            .map(|(node, pose)| (node.id().clone(), pose))
+            .collect()
+    }
 }
 
 impl<IT, RB, F, D> Forward<IT, RB> for ForwardModel<F, D>
@@ -51,14 +130,287 @@ where
     D: Differentiable<F>,
 {
     fn solve(&mut self, tree: &IT, params: &[<RB as Rigid>::FloatType]) -> Vec<&[F]> {
+        debug_assert_eq!(params.len(), parameter_count(tree));
         self.differential_model
             .compute(tree, params, ComputeSelection::EffectorsOnly);
         self.differential_model.effectors()
     }
 
-    fn setup(&mut self, tree: &IT, selected_effectors: &[&<RB as Rigid>::NodeId]) {
-        self.differential_model.setup(tree, &[], selected_effectors);
+    fn setup(
+        &mut self,
+        tree: &IT,
+        selected_effectors: &[&<RB as Rigid>::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>> {
+        self.differential_model.setup(tree, &[], selected_effectors)
+    }
+}
+
+/// Forward kinematics for interactive use (e.g., editing single joints) that caches the world transformation
+/// of each node. A node is recomputed only if it is dirty, that is, if its parameters changed since the last
+/// call to [Forward::solve], if it has been invalidated (see [CachedForwardModel::invalidate]), or if its
+/// parent has been recomputed. All other transformations (ancestors and untouched subtrees) are reused.
+pub struct CachedForwardModel<RB: Rigid> {
+    /// World transformation of each node in depth-first order. Empty if nothing has been computed yet
+    poses: Vec<RB::Transformation>,
+    /// Parameters of the last computation
+    params: Vec<RB::FloatType>,
+    /// Nodes to be recomputed regardless of their parameters
+    dirty: Vec<bool>,
+    selected_effectors: Vec<bool>,
+    /// Position of each node's effector in `configuration`
+    offsets: Vec<usize>,
+    /// Index of each node's first parameter
+    param_offsets: Vec<usize>,
+    configuration: Vec<RB::FloatType>,
+    /// Number of transformations recomputed in the last computation
+    recomputed_count: usize,
+}
+
+impl<RB: Rigid> Default for CachedForwardModel<RB> {
+    fn default() -> Self {
+        Self {
+            poses: vec![],
+            params: vec![],
+            dirty: vec![],
+            selected_effectors: vec![],
+            offsets: vec![],
+            param_offsets: vec![],
+            configuration: vec![],
+            recomputed_count: 0,
+        }
+    }
+}
+
+impl<RB: Rigid> CachedForwardModel<RB> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Force recomputing `node_id` and its subtree in the next call to [Forward::solve], e.g., after its load
+    /// (such as the link length) has been changed.
+    pub fn invalidate<IT>(&mut self, tree: &IT, node_id: &RB::NodeId) -> Result<(), MannequinError<RB::NodeId>>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+    {
+        let index = tree
+            .iter()
+            .position(|node| node.id() == node_id)
+            .ok_or_else(|| MannequinError::UnknownNode(node_id.clone()))?;
+        if let Some(dirty) = self.dirty.get_mut(index) {
+            *dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Number of transformations that have been recomputed in the last call to [Forward::solve]
+    pub fn recomputed_count(&self) -> usize {
+        self.recomputed_count
+    }
+}
+
+impl<IT, RB> Forward<IT, RB> for CachedForwardModel<RB>
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid,
+{
+    fn setup(&mut self, tree: &IT, selected_effectors: &[&RB::NodeId]) -> Result<(), MannequinError<RB::NodeId>> {
+        if let Some(unknown) = selected_effectors.iter().find(|id| !tree.contains(id)) {
+            return Err(MannequinError::UnknownNode((*unknown).clone()));
+        }
+        self.selected_effectors = tree
+            .iter()
+            .map(|node| selected_effectors.contains(&node.id()))
+            .collect();
+        self.offsets = tree
+            .iter()
+            .zip(&self.selected_effectors)
+            .scan(0, |offset, (node, selected)| {
+                let result = Some(*offset);
+                if *selected {
+                    *offset += node.get().effector_size();
+                }
+                result
+            })
+            .collect();
+        let rows = tree
+            .iter()
+            .zip(&self.selected_effectors)
+            .filter(|(_, selected)| **selected)
+            .map(|(node, _)| node.get().effector_size())
+            .sum();
+        self.configuration = vec![RB::FloatType::zero(); rows];
+        self.param_offsets = param_offsets(tree);
+        // Everything has to be computed initially
+        self.poses.clear();
+        self.params.clear();
+        self.dirty = vec![true; tree.len()];
+        Ok(())
+    }
+
+    fn solve(&mut self, tree: &IT, params: &[RB::FloatType]) -> Vec<&[RB::FloatType]> {
+        debug_assert_eq!(self.dirty.len(), tree.len());
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        let initial = self.poses.len() != tree.len() || self.params.len() != params.len();
+        if initial {
+            self.poses.clear();
+        }
+        self.recomputed_count = 0;
+
+        // Indices of the ancestors of the current node and whether they have been recomputed
+        let mut stack: Vec<(usize, bool)> = Vec::with_capacity(tree.len());
+        for (index, (node, offset)) in tree.iter().zip(&self.param_offsets).enumerate() {
+            stack.truncate(node.depth());
+            let (offset, count) = (*offset, node.get().param_count());
+            let parent = stack.last().copied();
+
+            let update = initial
+                || self.dirty[index]
+                || parent.map_or(false, |(_, updated)| updated)
+                || params[offset..offset + count] != self.params[offset..offset + count];
+            if update {
+                let neutral = RB::neutral_element();
+                let parent_pose = parent.map_or(&neutral, |(parent, _)| &self.poses[parent]);
+                let pose = RB::concat(parent_pose, &node.get().transform(params, offset));
+                if initial {
+                    self.poses.push(pose);
+                } else {
+                    self.poses[index] = pose;
+                }
+                self.recomputed_count += 1;
+                if self.selected_effectors[index] {
+                    node.get()
+                        .effector(&self.poses[index], &mut self.configuration, self.offsets[index]);
+                }
+            }
+            stack.push((index, update));
+        }
+
+        self.params = params.to_vec();
+        self.dirty.iter_mut().for_each(|dirty| *dirty = false);
+
+        izip!(tree.iter(), &self.selected_effectors, &self.offsets)
+            .filter(|(_, selected, _)| **selected)
+            .map(|(node, _, offset)| &self.configuration[*offset..*offset + node.get().effector_size()])
+            .collect()
+    }
+}
+
+/// Computes the effectors for `params` in each of the `trees` (e.g., variants of a model with different link
+/// lengths) with a single forward model. The trees need to share the same topology (nodes, joint types, and
+/// effectors) such that the selection of the last call to [Forward::setup] (with any of the trees) can be
+/// reused without repeating the setup for each variant. Returns the flattened effectors per tree.
+pub fn solve_forward_variants<IT, RB, FK>(
+    fk: &mut FK,
+    trees: &[&IT],
+    params: &[RB::FloatType],
+) -> Vec<Vec<RB::FloatType>>
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid,
+    FK: Forward<IT, RB>,
+{
+    debug_assert!(trees.iter().map(|tree| tree.len()).all_equal());
+    trees.iter().map(|tree| fk.solve(tree, params).concat()).collect()
+}
+
+/// Approximates the boundary of the region a planar chain's `effector` can reach in the x-y plane (e.g., for
+/// reach indicators in a UI). The joint space is sampled on a regular grid with `resolution` values per
+/// parameter spanning `joint_ranges` (in the order of the parameters, bounds included), which requires
+/// `resolution^n` evaluations of the forward kinematics.
+///
+/// The samples are binned by their angle around the center of their bounding box into `resolution` sectors,
+/// and the outermost sample of each sector forms the boundary in counter-clockwise order. If the region
+/// surrounds a hole (e.g., an annulus for chains with full rotation but different link lengths), the innermost
+/// samples are appended in clockwise order such that the result describes a polygon with a hole.
+pub fn reach_envelope<IT, RB, F>(
+    tree: &IT,
+    effector: &RB::NodeId,
+    joint_ranges: &[(F, F)],
+    resolution: usize,
+) -> Result<Vec<[F; 2]>, MannequinError<RB::NodeId>>
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid<FloatType = F>,
+    F: Float + Default + MaybeSendSync,
+{
+    debug_assert!(resolution >= 2);
+    let c = |x: f64| F::from(x).unwrap();
+
+    let node = tree
+        .node_by_id(effector)
+        .filter(|node| node.get().effector_count() > 0)
+        .ok_or_else(|| MannequinError::UnknownNode(effector.clone()))?;
+    let param_count = parameter_count(tree);
+    if joint_ranges.len() != param_count {
+        return Err(MannequinError::DimensionMismatch(joint_ranges.len()));
+    }
+
+    let mut fk = ForwardModel::new(DifferentiableModel::new());
+    Forward::<IT, RB>::setup(&mut fk, tree, &[node.id()])?;
+
+    // Sample the grid by interpreting the sample index as a number with base `resolution`
+    let steps = c((resolution - 1) as f64);
+    let mut params = vec![c(0.0); param_count];
+    let points = (0..resolution.pow(param_count as u32))
+        .map(|sample| {
+            let mut rest = sample;
+            params.iter_mut().zip(joint_ranges).for_each(|(param, (low, high))| {
+                *param = *low + (*high - *low) * c((rest % resolution) as f64) / steps;
+                rest /= resolution;
+            });
+            let position = Forward::<IT, RB>::solve(&mut fk, tree, &params)[0];
+            [position[0], position[1]]
+        })
+        .collect_vec();
+
+    let (min, max) = points.iter().fold(
+        ([c(f64::INFINITY); 2], [c(f64::NEG_INFINITY); 2]),
+        |(min, max), point| {
+            (
+                [min[0].min(point[0]), min[1].min(point[1])],
+                [max[0].max(point[0]), max[1].max(point[1])],
+            )
+        },
+    );
+    let center = [(min[0] + max[0]) / c(2.0), (min[1] + max[1]) / c(2.0)];
+
+    // Innermost and outermost sample (radius, point) per sector
+    type Sample<F> = (F, [F; 2]);
+    let mut sectors: Vec<Option<(Sample<F>, Sample<F>)>> = vec![None; resolution];
+    points.iter().for_each(|point| {
+        let (x, y) = (point[0] - center[0], point[1] - center[1]);
+        let radius = x.hypot(y);
+        let sector = ((y.atan2(x) + c(PI)) / c(2.0 * PI) * c(resolution as f64))
+            .to_usize()
+            .unwrap_or(0)
+            .min(resolution - 1);
+        let (inner, outer) = sectors[sector].get_or_insert(((radius, *point), (radius, *point)));
+        if radius < inner.0 {
+            *inner = (radius, *point);
+        }
+        if radius > outer.0 {
+            *outer = (radius, *point);
+        }
+    });
+
+    let mut boundary = sectors.iter().flatten().map(|(_, outer)| outer.1).collect_vec();
+    // A hole exists if it is surrounded by samples and larger than the spacing of the boundary
+    if sectors.iter().all(|sector| sector.is_some()) {
+        let inner_radius = sectors
+            .iter()
+            .flatten()
+            .map(|(inner, _)| inner.0)
+            .fold(c(f64::INFINITY), Float::min);
+        let outer_radius = sectors
+            .iter()
+            .flatten()
+            .map(|(_, outer)| outer.0)
+            .fold(c(0.0), Float::max);
+        if inner_radius > outer_radius * c(2.0 * PI) / c(resolution as f64) {
+            boundary.extend(sectors.iter().flatten().rev().map(|(inner, _)| inner.1));
+        }
     }
+    Ok(boundary)
 }
 
 /// Trait that adds an `accumulate` functions for accumulating transformations from direct path from a root to a node.
@@ -73,6 +425,96 @@ where
         params: &[Load::FloatType],
         max_depth: usize,
     ) -> impl Iterator<Item = (&'a Node, Load::Transformation)>;
+
+    /// Same as [TransformationAccumulation::accumulate] but the running products are kept in the wider
+    /// representation of [Widen] (e.g., `f64` in an `f32` pipeline) and only narrowed for the output. This
+    /// reduces the accumulation of round-off errors in long chains.
+    fn accumulate_wide(
+        self,
+        params: &[Load::FloatType],
+        max_depth: usize,
+    ) -> impl Iterator<Item = (&'a Node, Load::Transformation)>
+    where
+        Load: Widen;
+
+    /// World transformations of the nodes (see [TransformationAccumulation::accumulate]), e.g., for drawing the
+    /// bones of a skeleton without setting up a [DifferentiableModel]. Has to be called on a depth-first
+    /// iteration such as [crate::arena::iterables::OptimizedDirectionIterable::iter] of a [crate::DepthFirstArenaTree]
+    /// or [crate::DirectionIterable::iter_depth]. `max_depth` is only a hint for the capacity.
+    ///
+    /// ```
+    /// use mannequin::arena::iterables::OptimizedDirectionIterable;
+    /// use mannequin::forward::TransformationAccumulation;
+    /// use mannequin::ndarray::{robot::{Axis, Segment}, translate_x_4x4};
+    /// use mannequin::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable, NodeLike};
+    ///
+    /// // A planar chain of three bones with unit length, each rotating before its translation
+    /// let bone = || Segment::new(&translate_x_4x4(1.0), Axis::RotationZ, None);
+    /// let mut tree = DirectedArenaTree::new();
+    /// let shoulder = tree.set_root(bone(), "shoulder".to_string());
+    /// let elbow = tree.add(bone(), "elbow".to_string(), &shoulder).unwrap();
+    /// tree.add(bone(), "wrist".to_string(), &elbow).unwrap();
+    /// let tree: DepthFirstArenaTree<_, _> = tree.into();
+    ///
+    /// // The elbow is bent by 90°
+    /// let params = [0.0, std::f64::consts::FRAC_PI_2, 0.0];
+    /// let positions: Vec<_> = tree
+    ///     .iter()
+    ///     .world_poses(&params, 3)
+    ///     .map(|(node, pose)| (node.id().as_str(), [pose[[0, 3]], pose[[1, 3]]]))
+    ///     .collect();
+    /// let expected = [("shoulder", [1.0, 0.0]), ("elbow", [2.0, 0.0]), ("wrist", [2.0, 1.0])];
+    /// for ((id, position), (expected_id, expected_position)) in positions.iter().zip(expected) {
+    ///     assert_eq!(*id, expected_id);
+    ///     assert!((position[0] - expected_position[0]).abs() < 1e-12);
+    ///     assert!((position[1] - expected_position[1]).abs() < 1e-12);
+    /// }
+    /// ```
+    fn world_poses(
+        self,
+        params: &[Load::FloatType],
+        max_depth: usize,
+    ) -> impl Iterator<Item = (&'a Node, Load::Transformation)>
+    where
+        Self: Sized,
+    {
+        self.accumulate(params, max_depth)
+    }
+
+    /// Same as [TransformationAccumulation::world_poses] but only yields the nodes in `ids`. The transformations
+    /// of all nodes are computed nevertheless (as they are required for the descendants).
+    fn world_poses_of(
+        self,
+        params: &[Load::FloatType],
+        max_depth: usize,
+        ids: &HashSet<NodeRef>,
+    ) -> impl Iterator<Item = (&'a Node, Load::Transformation)>
+    where
+        Self: Sized,
+        NodeRef: Eq + Hash,
+    {
+        self.accumulate(params, max_depth)
+            .filter(|(node, _)| ids.contains(node.id()))
+    }
+}
+
+/// Rigid bodies whose transformations have a representation with a wider float type (e.g., `f64` for `f32`).
+/// Required for [TransformationAccumulation::accumulate_wide].
+pub trait Widen: Rigid {
+    /// The transformation type using the wider float type
+    type Wide: Clone;
+
+    /// Converts a transformation into the wide representation
+    fn widen(trafo: &Self::Transformation) -> Self::Wide;
+
+    /// Converts a wide transformation back (with loss of precision)
+    fn narrow(trafo: &Self::Wide) -> Self::Transformation;
+
+    /// See [Rigid::neutral_element]
+    fn neutral_wide() -> Self::Wide;
+
+    /// See [Rigid::concat]
+    fn concat_wide(first: &Self::Wide, second: &Self::Wide) -> Self::Wide;
 }
 
 impl<'a, Node, Load, NodeRef, T> TransformationAccumulation<'a, Node, Load, NodeRef> for T
@@ -86,21 +528,49 @@ where
         params: &[Load::FloatType],
         max_depth: usize,
     ) -> impl Iterator<Item = (&'a Node, <Load as Rigid>::Transformation)> {
-        self.into_iter().enumerate().scan(
-            Vec::<Load::Transformation>::with_capacity(max_depth),
-            |stack, (index, node)| {
+        self.into_iter().scan(
+            (Vec::<Load::Transformation>::with_capacity(max_depth), 0),
+            |(stack, offset), node| {
                 while node.depth() < stack.len() {
                     stack.pop();
                 }
                 let current = Load::concat(
                     stack.last().unwrap_or(&Load::neutral_element()),
-                    &node.get().transform(params, index),
+                    &node.get().transform(params, *offset),
                 );
+                // Nodes can consume a varying number of parameters
+                *offset += node.get().param_count();
                 stack.push(current.clone());
                 Some((node, current))
             },
         )
     }
+
+    fn accumulate_wide(
+        self,
+        params: &[Load::FloatType],
+        max_depth: usize,
+    ) -> impl Iterator<Item = (&'a Node, <Load as Rigid>::Transformation)>
+    where
+        Load: Widen,
+    {
+        self.into_iter().scan(
+            (Vec::<Load::Wide>::with_capacity(max_depth), 0),
+            |(stack, offset), node| {
+                while node.depth() < stack.len() {
+                    stack.pop();
+                }
+                let current = Load::concat_wide(
+                    stack.last().unwrap_or(&Load::neutral_wide()),
+                    &Load::widen(&node.get().transform(params, *offset)),
+                );
+                *offset += node.get().param_count();
+                let result = Load::narrow(&current);
+                stack.push(current);
+                Some((node, result))
+            },
+        )
+    }
 }
 
 #[cfg(test)]
@@ -109,9 +579,10 @@ mod tests {
     // The `ndarray` as a reference implementation is used for testing
 
     use super::*;
+    use crate::arena::iterables::OptimizedDirectionIterable;
     use crate::ndarray::robot::{Axis, Segment};
-    use crate::{DepthFirstArenaTree, DifferentiableModel, DirectedArenaTree, DirectionIterable};
-    use itertools::Itertools;
+    use crate::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable};
+    use approx::assert_abs_diff_eq;
     use ndarray::prelude::*;
 
     #[test]
@@ -136,7 +607,7 @@ mod tests {
 
         let tree: DepthFirstArenaTree<_, _> = tree.into();
 
-        fk.setup(&tree, &[&ref2, &ref3, &ref4]);
+        fk.setup(&tree, &[&ref2, &ref3, &ref4]).unwrap();
         let res = fk.solve(&tree, &[0.0, 0.0, std::f64::consts::FRAC_PI_2, 0.0]);
         let res = res.iter().map(|&el| el.to_owned()).collect_vec();
 
@@ -144,5 +615,540 @@ mod tests {
             res,
             vec![vec![20.0, 0.0, 0.0], vec![20.0, 0.0, 0.0], vec![20.0, 10.0, 0.0]]
         );
+
+        let poses = fk.poses(&tree, &[0.0, 0.0, std::f64::consts::FRAC_PI_2, 0.0]);
+        let ids = poses.iter().map(|(id, _)| id.as_str()).collect_vec();
+        assert_eq!(ids, ["link1", "link2", "link3", "link4"]);
+        let (_, tip) = &poses[3];
+        assert_abs_diff_eq!(tip.slice(s![..3, 3]), array![20.0, 10.0, 0.0], epsilon = 1e-12);
+        // The rotation of the bent joint
+        assert_abs_diff_eq!(tip.slice(s![..2, 0]), array![0.0, 1.0], epsilon = 1e-12);
+
+        let ids = HashSet::from(["link2".to_string(), "link4".to_string()]);
+        let selected = tree
+            .iter()
+            .world_poses_of(&[0.0, 0.0, std::f64::consts::FRAC_PI_2, 0.0], 3, &ids)
+            .map(|(node, pose)| (node.id().as_str(), pose))
+            .collect_vec();
+        assert_eq!(selected, [("link2", poses[1].1.clone()), ("link4", poses[3].1.clone())]);
+    }
+
+    /// Minimal rigid body whose transformation is the sequence of parameters consumed along the path
+    #[derive(Debug, PartialEq)]
+    struct Recorder {
+        param_count: usize,
+        /// Whether the effector (the path padded with zeros to four values) is defined
+        effector: bool,
+    }
+
+    impl Recorder {
+        fn new(param_count: usize) -> Self {
+            Self {
+                param_count,
+                effector: false,
+            }
+        }
+    }
+
+    impl Rigid for Recorder {
+        type Transformation = Vec<f64>;
+        type Point = Vec<f64>;
+        type FloatType = f64;
+        type NodeId = String;
+
+        fn transform(&self, params: &[f64], index: usize) -> Vec<f64> {
+            params[index..index + self.param_count].to_vec()
+        }
+
+        fn param_count(&self) -> usize {
+            self.param_count
+        }
+
+        fn globalize(&self, other: &Vec<f64>) -> Vec<f64> {
+            other.clone()
+        }
+
+        fn localize(&self, other: &Vec<f64>) -> Vec<f64> {
+            other.clone()
+        }
+
+        fn dim(&self) -> usize {
+            4
+        }
+
+        fn partial_derivative(&self, _: &Vec<f64>, _: &Self, _: &Vec<f64>, _: &mut [f64], _: usize) {
+            unimplemented!()
+        }
+
+        fn effector(&self, pose: &Vec<f64>, buffer: &mut [f64], offset: usize) {
+            let target = &mut buffer[offset..offset + 4];
+            target.fill(0.0);
+            target[..pose.len()].copy_from_slice(pose);
+        }
+
+        fn effector_count(&self) -> usize {
+            usize::from(self.effector)
+        }
+
+        fn neutral_element() -> Vec<f64> {
+            vec![]
+        }
+
+        fn invert(_: &Vec<f64>) -> Vec<f64> {
+            unimplemented!()
+        }
+
+        fn orientation_error(_: &Vec<f64>, _: &Vec<f64>) -> Vec<f64> {
+            unimplemented!()
+        }
+
+        fn rotation_vector_difference(_: &[f64], _: &[f64]) -> [f64; 3] {
+            unimplemented!()
+        }
+
+        fn concat(first: &Vec<f64>, second: &Vec<f64>) -> Vec<f64> {
+            [first.as_slice(), second].concat()
+        }
+
+        fn solve_linear(_: &[f64], _: usize, _: usize, _: &[f64], _: f64, _: f64, _: &mut [f64]) {
+            unimplemented!()
+        }
+
+        fn apply_pseudo_inverse(_: &[f64], _: usize, _: usize, _: &[f64], _: &mut [f64]) {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_accumulate_param_count() {
+        let mut tree = DirectedArenaTree::<Recorder, String>::new();
+        let root = tree.set_root(Recorder::new(1), "root".to_string());
+        tree.add(Recorder::new(3), "ball".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let params = [1.0, 2.0, 3.0, 4.0];
+        let result = tree.iter().accumulate(&params, 2).map(|(_, trafo)| trafo).collect_vec();
+
+        assert_eq!(result, vec![vec![1.0], vec![1.0, 2.0, 3.0, 4.0]]);
+    }
+
+    #[test]
+    fn test_accumulate_multi_dof() {
+        // A [1-DOF, 3-DOF, 1-DOF] chain and a sibling branch to the 3-DOF node
+        let mut tree = DirectedArenaTree::<Recorder, String>::new();
+        let root = tree.set_root(Recorder::new(1), "root".to_string());
+        let ball = tree.add(Recorder::new(3), "ball".to_string(), &root).unwrap();
+        tree.add(Recorder::new(1), "hinge".to_string(), &ball).unwrap();
+        tree.add(Recorder::new(1), "sibling".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let params = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let result = tree
+            .iter()
+            .accumulate(&params, 3)
+            .map(|(node, trafo)| (node.id().as_str(), trafo))
+            .collect_vec();
+
+        assert_eq!(
+            result,
+            vec![
+                ("root", vec![1.0]),
+                ("ball", vec![1.0, 2.0, 3.0, 4.0]),
+                ("hinge", vec![1.0, 2.0, 3.0, 4.0, 5.0]),
+                // The offset continues after the subtree of the ball joint
+                ("sibling", vec![1.0, 6.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_count() {
+        // A ball joint (3), a fixed joint (0) and two hinges (1) next to a universal joint (2)
+        let mut tree = DirectedArenaTree::<Recorder, String>::new();
+        let root = tree.set_root(Recorder::new(3), "root".to_string());
+        let fixed = tree.add(Recorder::new(0), "fixed".to_string(), &root).unwrap();
+        tree.add(Recorder::new(1), "hinge".to_string(), &fixed).unwrap();
+        tree.add(Recorder::new(1), "other".to_string(), &fixed).unwrap();
+        tree.add(Recorder::new(2), "universal".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        assert_eq!(parameter_count(&tree), 7);
+
+        // Single degrees of freedom: one parameter per node
+        let segment = || Segment::new(&Segment::neutral_element(), Axis::RotationZ, None);
+        let mut tree = DirectedArenaTree::new();
+        let root = tree.set_root(segment(), "root".to_string());
+        tree.add(segment(), "child".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        assert_eq!(parameter_count(&tree), tree.iter().count());
+    }
+
+    #[test]
+    fn test_param_offsets() {
+        // A [1-DOF, 2-DOF, fixed, 1-DOF] chain with effectors at the 2-DOF joint and at the tip
+        let recorder = |param_count, effector| Recorder { param_count, effector };
+        let mut tree = DirectedArenaTree::<Recorder, String>::new();
+        let root = tree.set_root(recorder(1, false), "root".to_string());
+        let universal = tree.add(recorder(2, true), "universal".to_string(), &root).unwrap();
+        let fixed = tree.add(recorder(0, false), "fixed".to_string(), &universal).unwrap();
+        let tip = tree.add(recorder(1, true), "tip".to_string(), &fixed).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let offsets = param_offsets(&tree);
+        assert_eq!(offsets, [0, 1, 3, 3]);
+        let params = [1.0, 2.0, 3.0, 4.0];
+        let slices = tree
+            .iter()
+            .zip(&offsets)
+            .map(|(node, offset)| node.get().transform(&params, *offset))
+            .collect_vec();
+        assert_eq!(slices, [vec![1.0], vec![2.0, 3.0], vec![], vec![4.0]]);
+
+        let expected = [1.0, 2.0, 3.0, 0.0, 1.0, 2.0, 3.0, 4.0];
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&universal, &tip]).unwrap();
+        assert_eq!(fk.solve(&tree, &params).concat(), expected);
+        let mut cached = CachedForwardModel::new();
+        cached.setup(&tree, &[&universal, &tip]).unwrap();
+        assert_eq!(cached.solve(&tree, &params).concat(), expected);
+    }
+
+    /// Planar rigid body in single precision: rotation followed by a translation along the rotated x-axis
+    #[derive(Debug, PartialEq)]
+    struct Planar {
+        length: f32,
+    }
+
+    fn multiply<F: Float>(first: &[[F; 3]; 3], second: &[[F; 3]; 3]) -> [[F; 3]; 3] {
+        let mut result = [[F::zero(); 3]; 3];
+        (0..3).cartesian_product(0..3).for_each(|(row, col)| {
+            result[row][col] = (0..3).fold(F::zero(), |sum, k| sum + first[row][k] * second[k][col]);
+        });
+        result
+    }
+
+    impl Rigid for Planar {
+        type Transformation = [[f32; 3]; 3];
+        type Point = [f32; 3];
+        type FloatType = f32;
+        type NodeId = String;
+
+        fn transform(&self, params: &[f32], index: usize) -> [[f32; 3]; 3] {
+            let (sin, cos) = params[index].sin_cos();
+            [
+                [cos, -sin, cos * self.length],
+                [sin, cos, sin * self.length],
+                [0.0, 0.0, 1.0],
+            ]
+        }
+
+        fn globalize(&self, _: &[f32; 3]) -> [f32; 3] {
+            unimplemented!()
+        }
+
+        fn localize(&self, _: &[f32; 3]) -> [f32; 3] {
+            unimplemented!()
+        }
+
+        fn dim(&self) -> usize {
+            2
+        }
+
+        fn partial_derivative(
+            &self,
+            _: &Self::Transformation,
+            _: &Self,
+            _: &Self::Transformation,
+            _: &mut [f32],
+            _: usize,
+        ) {
+            unimplemented!()
+        }
+
+        fn effector(&self, _: &Self::Transformation, _: &mut [f32], _: usize) {
+            unimplemented!()
+        }
+
+        fn effector_count(&self) -> usize {
+            0
+        }
+
+        fn neutral_element() -> [[f32; 3]; 3] {
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        }
+
+        fn invert(_: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+            unimplemented!()
+        }
+
+        fn orientation_error(_: &[[f32; 3]; 3], _: &[[f32; 3]; 3]) -> [f32; 3] {
+            unimplemented!()
+        }
+
+        fn rotation_vector_difference(_: &[f32], _: &[f32]) -> [f32; 3] {
+            unimplemented!()
+        }
+
+        fn concat(first: &[[f32; 3]; 3], second: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+            multiply(first, second)
+        }
+
+        fn solve_linear(_: &[f32], _: usize, _: usize, _: &[f32], _: f32, _: f32, _: &mut [f32]) {
+            unimplemented!()
+        }
+
+        fn apply_pseudo_inverse(_: &[f32], _: usize, _: usize, _: &[f32], _: &mut [f32]) {
+            unimplemented!()
+        }
+    }
+
+    impl Widen for Planar {
+        type Wide = [[f64; 3]; 3];
+
+        fn widen(trafo: &[[f32; 3]; 3]) -> [[f64; 3]; 3] {
+            trafo.map(|row| row.map(f64::from))
+        }
+
+        fn narrow(trafo: &[[f64; 3]; 3]) -> [[f32; 3]; 3] {
+            trafo.map(|row| row.map(|x| x as f32))
+        }
+
+        fn neutral_wide() -> [[f64; 3]; 3] {
+            Self::widen(&Self::neutral_element())
+        }
+
+        fn concat_wide(first: &[[f64; 3]; 3], second: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+            multiply(first, second)
+        }
+    }
+
+    #[test]
+    fn test_accumulate_wide() {
+        let mut tree = DirectedArenaTree::<Planar, String>::new();
+        let mut parent = tree.set_root(Planar { length: 0.7 }, "link_0".to_string());
+        for i in 1..50 {
+            let length = 0.3 + (i % 7) as f32 * 0.11;
+            parent = tree.add(Planar { length }, format!("link_{i}"), &parent).unwrap();
+        }
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let params = (0..50).map(|i| (i as f32 * 0.37).sin()).collect_vec();
+
+        // Reference: exact (double precision) product of the single precision transformations
+        let reference = tree
+            .iter()
+            .enumerate()
+            .fold(Planar::neutral_wide(), |product, (index, node)| {
+                multiply(&product, &Planar::widen(&node.get().transform(&params, index)))
+            });
+        let error = |result: &[[f32; 3]; 3]| {
+            (0..2)
+                .map(|row| (f64::from(result[row][2]) - reference[row][2]).abs())
+                .fold(0.0, f64::max)
+        };
+
+        let (_, naive) = tree.iter().accumulate(&params, 50).last().unwrap();
+        let (_, wide) = tree.iter().accumulate_wide(&params, 50).last().unwrap();
+
+        // Only rounded once (the tip is a few units away from the origin)
+        assert!(error(&wide) < 1e-6);
+        assert!(
+            error(&wide) * 2.0 < error(&naive),
+            "{} vs. {}",
+            error(&wide),
+            error(&naive)
+        );
+    }
+
+    #[test]
+    fn test_reach_envelope() {
+        // Planar arm with links of length 10 and 5 and the shoulder at the origin
+        let mut tree = DirectedArenaTree::<Segment, String>::new();
+        let mut upper = Segment::neutral_element();
+        upper.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let mut lower = Segment::neutral_element();
+        lower.slice_mut(s![..3, 3]).assign(&array![5.0, 0.0, 0.0]);
+
+        let root = tree.set_root(
+            Segment::new(&Segment::neutral_element(), Axis::RotationZ, None),
+            "shoulder".to_string(),
+        );
+        tree.add(
+            Segment::new(&upper, Axis::RotationZ, Some(lower)),
+            "elbow".to_string(),
+            &root,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let resolution = 72;
+        let full = (-std::f64::consts::PI, std::f64::consts::PI);
+        let boundary = reach_envelope(&tree, &"elbow".to_string(), &[full, full], resolution).unwrap();
+
+        // An annulus: the outer boundary followed by the hole
+        assert_eq!(boundary.len(), 2 * resolution);
+        let radius = |point: &[f64; 2]| point[0].hypot(point[1]);
+        boundary[..resolution]
+            .iter()
+            .for_each(|point| assert!((radius(point) - 15.0).abs() < 0.2));
+        boundary[resolution..]
+            .iter()
+            .for_each(|point| assert!((radius(point) - 5.0).abs() < 0.2));
+
+        // Counter-clockwise outside
+        let angles = boundary[..resolution].iter().map(|p| p[1].atan2(p[0])).collect_vec();
+        assert!(angles.iter().tuple_windows().all(|(a, b)| a < b));
+
+        // A limited shoulder range does not enclose a hole
+        let quarter = (0.0, std::f64::consts::FRAC_PI_2);
+        let boundary = reach_envelope(&tree, &"elbow".to_string(), &[quarter, full], resolution).unwrap();
+        assert!(boundary.len() <= resolution);
+        assert!(boundary.iter().all(|point| radius(point) < 15.0 + 1e-9));
+
+        assert!(matches!(
+            reach_envelope(&tree, &"shoulder".to_string(), &[full, full], resolution),
+            Err(MannequinError::UnknownNode(_))
+        ));
+        assert!(matches!(
+            reach_envelope(&tree, &"elbow".to_string(), &[full], resolution),
+            Err(MannequinError::DimensionMismatch(1))
+        ));
+    }
+
+    #[test]
+    fn test_solve_forward_variants() {
+        let arm = |length: f64| {
+            let mut tree = DirectedArenaTree::<Segment, String>::new();
+            let mut trafo = Segment::neutral_element();
+            trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+            let mut lower = Segment::neutral_element();
+            lower.slice_mut(s![..3, 3]).assign(&array![length, 0.0, 0.0]);
+
+            let root = tree.set_root(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "upper".to_string(),
+            );
+            tree.add(
+                Segment::new(&trafo, Axis::RotationZ, Some(lower)),
+                "lower".to_string(),
+                &root,
+            )
+            .unwrap();
+            let tree: DepthFirstArenaTree<_, _> = tree.into();
+            tree
+        };
+        let (short, long) = (arm(10.0), arm(15.0));
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&short, &[&"upper".to_string(), &"lower".to_string()]).unwrap();
+
+        let params = [0.0, std::f64::consts::FRAC_PI_2];
+        let result = solve_forward_variants(&mut fk, &[&short, &long], &params);
+
+        assert_eq!(result.len(), 2);
+        // The elbows coincide, the hands differ
+        assert_abs_diff_eq!(result[0][..3], result[1][..3]);
+        assert_abs_diff_eq!(result[0][3..], [20.0, 10.0, 0.0][..], epsilon = 1e-9);
+        assert_abs_diff_eq!(result[1][3..], [20.0, 15.0, 0.0][..], epsilon = 1e-9);
+        assert_eq!(result[1], fk.solve(&long, &params).concat());
+    }
+
+    #[test]
+    fn test_solve_batch() {
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let segment = || Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone()));
+
+        let mut tree = DirectedArenaTree::<Segment, String>::new();
+        let root = tree.set_root(segment(), "root".to_string());
+        let a = tree.add(segment(), "a".to_string(), &root).unwrap();
+        tree.add(segment(), "b".to_string(), &a).unwrap();
+        tree.add(segment(), "c".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let effectors = ["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&effectors[1], &effectors[2]]).unwrap();
+        let frames = [[0.1, 0.2, 0.3, 0.4], [-1.0, 0.5, 0.0, 2.0], [0.0; 4]];
+        let frames = frames.iter().map(|frame| frame.as_slice()).collect_vec();
+
+        let result = fk.solve_batch(&tree, &frames);
+        assert_eq!(result.len(), 3);
+        for (effectors, frame) in result.iter().zip(&frames) {
+            assert_eq!(effectors, &fk.solve(&tree, frame).concat());
+        }
+        assert_ne!(result[0], result[1]);
+    }
+
+    #[test]
+    fn test_cached_forward_model() {
+        //      root
+        //     /    \
+        //    a      c
+        //    |
+        //    b
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let segment = || Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone()));
+
+        let mut tree = DirectedArenaTree::<Segment, String>::new();
+        let root = tree.set_root(segment(), "root".to_string());
+        let a = tree.add(segment(), "a".to_string(), &root).unwrap();
+        tree.add(segment(), "b".to_string(), &a).unwrap();
+        tree.add(segment(), "c".to_string(), &root).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let effectors = ["b".to_string(), "c".to_string()];
+        let effectors = effectors.iter().collect_vec();
+
+        let mut reference = ForwardModel::new(DifferentiableModel::new());
+        reference.setup(&tree, &effectors).unwrap();
+        let mut cached = CachedForwardModel::new();
+        assert!(matches!(
+            cached.setup(&tree, &[&"unknown".to_string()]),
+            Err(MannequinError::UnknownNode(_))
+        ));
+        cached.setup(&tree, &effectors).unwrap();
+
+        let mut params = vec![0.1, 0.2, 0.3, 0.4];
+        assert_eq!(cached.solve(&tree, &params), reference.solve(&tree, &params));
+        assert_eq!(cached.recomputed_count(), 4);
+
+        // Nothing changed
+        assert_eq!(cached.solve(&tree, &params), reference.solve(&tree, &params));
+        assert_eq!(cached.recomputed_count(), 0);
+
+        // Only the subtree of `a` (depth-first index 1) is recomputed
+        params[1] = -0.5;
+        assert_eq!(cached.solve(&tree, &params), reference.solve(&tree, &params));
+        assert_eq!(cached.recomputed_count(), 2);
+
+        params[3] = 1.0;
+        assert_eq!(cached.solve(&tree, &params), reference.solve(&tree, &params));
+        assert_eq!(cached.recomputed_count(), 1);
+
+        cached.invalidate(&tree, &a).unwrap();
+        assert_eq!(cached.solve(&tree, &params), reference.solve(&tree, &params));
+        assert_eq!(cached.recomputed_count(), 2);
+        assert!(matches!(
+            cached.invalidate(&tree, &"d".to_string()),
+            Err(MannequinError::UnknownNode(_))
+        ));
+
+        // Setting up a different tree (e.g., `c` became fixed) recomputes everything
+        let mut fixed = DirectedArenaTree::<Segment, String>::new();
+        let root = fixed.set_root(segment(), "root".to_string());
+        let a = fixed.add(segment(), "a".to_string(), &root).unwrap();
+        fixed.add(segment(), "b".to_string(), &a).unwrap();
+        fixed
+            .add(
+                Segment::new(&trafo, Axis::Fixed, Some(trafo.clone())),
+                "c".to_string(),
+                &root,
+            )
+            .unwrap();
+        let fixed: DepthFirstArenaTree<_, _> = fixed.into();
+        reference.setup(&fixed, &effectors).unwrap();
+        cached.setup(&fixed, &effectors).unwrap();
+        let params = [0.5, 0.6, 0.7];
+        assert_eq!(cached.solve(&fixed, &params), reference.solve(&fixed, &params));
+        assert_eq!(cached.recomputed_count(), 4);
     }
 }
@@ -0,0 +1,545 @@
+//! Module for the implementations using the glam backend (single precision), e.g., for games and real-time
+//! applications. Mirrors the [Segment](crate::ndarray::robot::Segment) of the ndarray backend.
+use crate::{forward::Widen, Rigid};
+use core::fmt;
+use glam::{Affine3A, DMat4, Mat3, Mat4, Quat, Vec3, Vec4};
+
+/// Creates a homogeneous, 4x4 rotation matrix around the x axis.
+pub fn rotate_x_4x4(param: f32) -> Mat4 {
+    Affine3A::from_rotation_x(param).into()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the y axis.
+pub fn rotate_y_4x4(param: f32) -> Mat4 {
+    Affine3A::from_rotation_y(param).into()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the z axis.
+pub fn rotate_z_4x4(param: f32) -> Mat4 {
+    Affine3A::from_rotation_z(param).into()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around an arbitrary `axis` (normalized internally). A zero angle
+/// yields the identity for any axis.
+///
+/// # Panics
+/// If `axis` is zero (for a non-zero angle).
+pub fn rotate_axis_4x4(axis: Vec3, param: f32) -> Mat4 {
+    if param == 0.0 {
+        return Mat4::IDENTITY;
+    }
+    let axis = axis.try_normalize().expect("Rotation axis must not be zero");
+    Affine3A::from_axis_angle(axis, param).into()
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the x axis.
+pub fn translate_x_4x4(param: f32) -> Mat4 {
+    Affine3A::from_translation(Vec3::X * param).into()
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the y axis.
+pub fn translate_y_4x4(param: f32) -> Mat4 {
+    Affine3A::from_translation(Vec3::Y * param).into()
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the z axis.
+pub fn translate_z_4x4(param: f32) -> Mat4 {
+    Affine3A::from_translation(Vec3::Z * param).into()
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along an arbitrary `direction` (normalized internally) by the
+/// distance `param`.
+///
+/// # Panics
+/// If `direction` is zero (for a non-zero distance).
+pub fn translate_axis_4x4(direction: Vec3, param: f32) -> Mat4 {
+    if param == 0.0 {
+        return Mat4::IDENTITY;
+    }
+    let direction = direction.try_normalize().expect("Translation axis must not be zero");
+    Affine3A::from_translation(direction * param).into()
+}
+
+/// inverts a homogeneous, 4x4 transformation matrix.
+pub fn invert_transformation_4x4(trafo: &Mat4) -> Mat4 {
+    Affine3A::from_mat4(*trafo).inverse().into()
+}
+
+/// Shortest-path rotation (as rotation vector) from the orientation of the homogeneous transformation `current`
+/// to the one of `target`, i.e., the rotation vector of `target·currentᵀ`.
+pub fn orientation_error(current: &Mat4, target: &Mat4) -> Vec3 {
+    Quat::from_mat3(&(Mat3::from_mat4(*target) * Mat3::from_mat4(*current).transpose())).to_scaled_axis()
+}
+
+/// Solves the symmetric, positive definite system `matrix x = vector` (`size` x `size`) by Gaussian elimination
+/// with partial pivoting. Both buffers are overwritten, the solution is returned in `vector`.
+fn solve_in_place(matrix: &mut [f32], size: usize, vector: &mut [f32]) {
+    for col in 0..size {
+        let pivot = (col..size)
+            .max_by(|a, b| matrix[a * size + col].abs().total_cmp(&matrix[b * size + col].abs()))
+            .unwrap();
+        assert!(matrix[pivot * size + col] != 0.0, "Cannot solve equations");
+        if pivot != col {
+            for k in 0..size {
+                matrix.swap(pivot * size + k, col * size + k);
+            }
+            vector.swap(pivot, col);
+        }
+        for row in col + 1..size {
+            let factor = matrix[row * size + col] / matrix[col * size + col];
+            for k in col..size {
+                matrix[row * size + k] -= factor * matrix[col * size + k];
+            }
+            vector[row] -= factor * vector[col];
+        }
+    }
+    for row in (0..size).rev() {
+        let sum = (row + 1..size).fold(vector[row], |sum, k| sum - matrix[row * size + k] * vector[k]);
+        vector[row] = sum / matrix[row * size + row];
+    }
+}
+
+/// Entries `i, j` of `JᵀJ` (`cols` x `cols`) for the column-major `matrix` `J` with `damping` added to the diagonal
+fn gram_columns(matrix: &[f32], rows: usize, cols: usize, damping: f32) -> Vec<f32> {
+    let columns = matrix.chunks(rows.max(1)).take(cols).collect::<Vec<_>>();
+    let mut result = vec![0.0; cols * cols];
+    for i in 0..cols {
+        for j in 0..cols {
+            result[i * cols + j] = columns[i].iter().zip(columns[j]).map(|(a, b)| a * b).sum::<f32>();
+        }
+        result[i * cols + i] += damping;
+    }
+    result
+}
+
+/// Solves the damped normal equations `(JᵀJ + λI) x = Jᵀe` for the column-major `matrix` `J`. The update is
+/// scaled down such that its norm does not exceed `limit`.
+pub fn solve_linear(
+    matrix: &[f32],
+    rows: usize,
+    cols: usize,
+    vector: &[f32],
+    damping: f32,
+    limit: f32,
+    parameters: &mut [f32],
+) {
+    let mut normal = gram_columns(matrix, rows, cols, damping);
+    Segment::apply_transpose(matrix, rows, cols, vector, parameters);
+    solve_in_place(&mut normal, cols, parameters);
+    let norm = parameters.iter().map(|p| p * p).sum::<f32>().sqrt();
+    if norm > limit {
+        parameters.iter_mut().for_each(|p| *p *= limit / norm);
+    }
+}
+
+/// Computes `J⁺ e` by solving the normal equations of the smaller dimension: `(JᵀJ) x = Jᵀe` if the system is
+/// over-determined, and `x = Jᵀ y` with `(JJᵀ) y = e` otherwise. A tiny regularization keeps singular
+/// configurations solvable.
+pub fn apply_pseudo_inverse(matrix: &[f32], rows: usize, cols: usize, vector: &[f32], result: &mut [f32]) {
+    if rows >= cols {
+        solve_linear(matrix, rows, cols, vector, 1e-6, f32::INFINITY, result);
+    } else {
+        let mut normal = vec![0.0; rows * rows];
+        for col in matrix.chunks(rows.max(1)) {
+            for i in 0..rows {
+                for j in 0..rows {
+                    normal[i * rows + j] += col[i] * col[j];
+                }
+            }
+        }
+        (0..rows).for_each(|i| normal[i * rows + i] += 1e-6);
+        let mut multipliers = vector.to_vec();
+        solve_in_place(&mut normal, rows, &mut multipliers);
+        Segment::apply_transpose(matrix, rows, cols, &multipliers, result);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Axis {
+    RotationX,
+    RotationY,
+    #[default]
+    RotationZ,
+    Rotation(Vec3),
+    TranslationX,
+    TranslationY,
+    TranslationZ,
+    Translation(Vec3),
+    /// Rigid connection without a degree of freedom (consumes no parameter)
+    Fixed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Mode {
+    /// Position of the effector (3 values)
+    #[default]
+    Position,
+    /// Position and orientation as rotation vector (6 values)
+    Pose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    link: Mat4,
+    axis: Axis,
+    mode: Mode,
+    effector_local: Option<Mat4>,
+}
+
+impl Segment {
+    pub fn new(from_parent: &Mat4, axis: Axis, effector: Option<Mat4>) -> Self {
+        Self {
+            link: *from_parent,
+            axis,
+            mode: Mode::Position,
+            effector_local: effector,
+        }
+    }
+
+    /// Selects what the effector represents (and thus its dimension, see [Mode]).
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bone, link: {}, Axis: {:?}", self.link, self.axis)
+    }
+}
+
+impl Rigid for Segment {
+    type Transformation = Mat4;
+
+    type Point = Vec4;
+
+    type FloatType = f32;
+
+    type NodeId = String;
+
+    fn transform(&self, params: &[f32], index: usize) -> Self::Transformation {
+        let joint = match &self.axis {
+            Axis::RotationX => rotate_x_4x4(params[index]),
+            Axis::RotationY => rotate_y_4x4(params[index]),
+            Axis::RotationZ => rotate_z_4x4(params[index]),
+            Axis::Rotation(axis) => rotate_axis_4x4(*axis, params[index]),
+            Axis::TranslationX => translate_x_4x4(params[index]),
+            Axis::TranslationY => translate_y_4x4(params[index]),
+            Axis::TranslationZ => translate_z_4x4(params[index]),
+            Axis::Translation(axis) => translate_axis_4x4(*axis, params[index]),
+            Axis::Fixed => Mat4::IDENTITY,
+        };
+        self.link * joint
+    }
+
+    fn param_count(&self) -> usize {
+        if self.axis == Axis::Fixed {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_revolute(&self) -> bool {
+        matches!(
+            self.axis,
+            Axis::RotationX | Axis::RotationY | Axis::RotationZ | Axis::Rotation(_)
+        )
+    }
+
+    fn globalize(&self, other: &Self::Point) -> Self::Point {
+        self.link * *other
+    }
+
+    fn localize(&self, other: &Self::Point) -> Self::Point {
+        invert_transformation_4x4(&self.link) * *other
+    }
+
+    fn neutral_element() -> Self::Transformation {
+        Mat4::IDENTITY
+    }
+
+    fn concat(first: &Self::Transformation, second: &Self::Transformation) -> Self::Transformation {
+        *first * *second
+    }
+
+    fn invert(trafo: &Self::Transformation) -> Self::Transformation {
+        invert_transformation_4x4(trafo)
+    }
+
+    fn origin(trafo: &Self::Transformation) -> Option<[f32; 3]> {
+        Some(trafo.w_axis.truncate().to_array())
+    }
+
+    /// The rotation vector as a direction (i.e., the homogeneous coordinate is zero)
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point {
+        orientation_error(current, target).extend(0.0)
+    }
+
+    fn rotation_vector_difference(current: &[f32], target: &[f32]) -> [f32; 3] {
+        let rotation = |vector: &[f32]| Mat4::from_quat(Quat::from_scaled_axis(Vec3::from_slice(vector)));
+        orientation_error(&rotation(current), &rotation(target)).to_array()
+    }
+
+    fn dim(&self) -> usize {
+        match self.mode {
+            Mode::Position => 3,
+            Mode::Pose => 6,
+        }
+    }
+
+    fn effector_count(&self) -> usize {
+        if self.effector_local.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn partial_derivative(
+        &self,
+        pose: &Self::Transformation,
+        joint: &Self,
+        joint_pose: &Self::Transformation,
+        buffer: &mut [f32],
+        offset: usize,
+    ) {
+        // Formula: axis_in_world x (end_effector_world - pivod_in_world) for revolute joints and
+        // axis_in_world for prismatic joints
+        let local_axis = match &joint.axis {
+            Axis::RotationX | Axis::TranslationX => Vec3::X,
+            Axis::RotationY | Axis::TranslationY => Vec3::Y,
+            Axis::RotationZ | Axis::TranslationZ => Vec3::Z,
+            Axis::Rotation(axis) | Axis::Translation(axis) => axis.normalize(),
+            Axis::Fixed => Vec3::ZERO,
+        };
+        let axis_global = joint_pose.transform_vector3(local_axis);
+
+        let pose = match &self.effector_local {
+            Some(effector) => *pose * *effector,
+            None => *pose,
+        };
+        let lever = pose.w_axis.truncate() - joint_pose.w_axis.truncate();
+
+        // Linear and angular velocity of the effector
+        let (velocity, angular) = if joint.is_revolute() {
+            (axis_global.cross(lever), axis_global)
+        } else {
+            (axis_global, Vec3::ZERO)
+        };
+
+        let target = &mut buffer[offset..offset + self.effector_size()];
+        velocity.write_to_slice(&mut target[0..3]);
+        if self.mode == Mode::Pose {
+            // The angular velocity caused by a revolute joint is its axis
+            angular.write_to_slice(&mut target[3..6]);
+        }
+    }
+
+    /// Get the coordinates of the effector in the global (or an arbitrary) system.
+    fn effector(&self, pose: &Self::Transformation, buffer: &mut [f32], offset: usize) {
+        let target = &mut buffer[offset..offset + self.effector_size()];
+
+        if let Some(effector) = &self.effector_local {
+            let frame = *pose * *effector;
+            frame.w_axis.truncate().write_to_slice(&mut target[0..3]);
+            if self.mode == Mode::Pose {
+                Quat::from_mat4(&frame)
+                    .to_scaled_axis()
+                    .write_to_slice(&mut target[3..6]);
+            }
+        } else {
+            panic!("Should not call this method if no effector is defined")
+        }
+    }
+
+    fn solve_linear(
+        matrix: &[f32],
+        rows: usize,
+        cols: usize,
+        vector: &[f32],
+        damping: f32,
+        limit: f32,
+        parameters: &mut [f32],
+    ) {
+        solve_linear(matrix, rows, cols, vector, damping, limit, parameters);
+    }
+
+    fn apply_pseudo_inverse(matrix: &[f32], rows: usize, cols: usize, vector: &[f32], result: &mut [f32]) {
+        apply_pseudo_inverse(matrix, rows, cols, vector, result);
+    }
+}
+
+/// Accumulates the transformations in double precision
+impl Widen for Segment {
+    type Wide = DMat4;
+
+    fn widen(trafo: &Mat4) -> DMat4 {
+        trafo.as_dmat4()
+    }
+
+    fn narrow(trafo: &DMat4) -> Mat4 {
+        trafo.as_mat4()
+    }
+
+    fn neutral_wide() -> DMat4 {
+        DMat4::IDENTITY
+    }
+
+    fn concat_wide(first: &DMat4, second: &DMat4) -> DMat4 {
+        *first * *second
+    }
+}
+
+pub type LinkNodeId = <Segment as Rigid>::NodeId;
+
+#[cfg(test)]
+mod tests {
+
+    // The trees and expectations are the ones of the tests with the `ndarray` backend
+
+    use super::*;
+    use crate::{
+        arena::iterables::OptimizedDirectionIterable, differentiable::ComputeSelection, DepthFirstArenaTree,
+        Differentiable, DifferentiableModel, DirectedArenaTree, DirectionIterable, Forward, ForwardModel, NodeLike,
+    };
+    use approx::assert_abs_diff_eq;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_fk() {
+        let mut tree = DirectedArenaTree::new();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+
+        let trafo = translate_x_4x4(10.0);
+        let link1 = Segment::new(&trafo, Axis::RotationZ, None);
+        let link2 = Segment::new(&trafo, Axis::RotationZ, Some(Mat4::IDENTITY));
+        let link3 = Segment::new(&trafo, Axis::RotationZ, Some(Mat4::IDENTITY));
+        let link4 = Segment::new(&trafo, Axis::RotationZ, Some(Mat4::IDENTITY));
+
+        let ref1 = tree.set_root(link1, "link1".to_string());
+        let ref2 = tree.add(link2, "link2".to_string(), &ref1).unwrap();
+        let ref3 = tree.add(link3, "link3".to_string(), &ref1).unwrap();
+        let ref4 = tree.add(link4, "link4".to_string(), &ref3).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        fk.setup(&tree, &[&ref2, &ref3, &ref4]).unwrap();
+        let res = fk.solve(&tree, &[0.0, 0.0, std::f32::consts::FRAC_PI_2, 0.0]).concat();
+
+        assert_abs_diff_eq!(
+            &res[..],
+            &[20.0, 0.0, 0.0, 20.0, 0.0, 0.0, 20.0, 10.0, 0.0][..],
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn test_fk_wide() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut parent = tree.set_root(
+            Segment::new(&translate_x_4x4(0.7), Axis::RotationZ, None),
+            "link_0".to_string(),
+        );
+        for i in 1..200 {
+            let trafo = translate_x_4x4(0.3 + (i % 7) as f32 * 0.11);
+            let effector = (i == 199).then_some(Mat4::IDENTITY);
+            parent = tree
+                .add(
+                    Segment::new(&trafo, Axis::RotationZ, effector),
+                    format!("link_{i}"),
+                    &parent,
+                )
+                .unwrap();
+        }
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let params = (0..200).map(|i| (i as f32 * 0.37).sin() * 0.2).collect_vec();
+
+        // Reference: exact (double precision) product of the single precision transformations
+        let reference = tree
+            .iter()
+            .enumerate()
+            .fold(Segment::neutral_wide(), |product, (index, node)| {
+                product * Segment::widen(&node.get().transform(&params, index))
+            });
+        let error = |result: &[f32]| {
+            (0..3)
+                .map(|row| (f64::from(result[row]) - reference.w_axis[row]).abs())
+                .fold(0.0, f64::max)
+        };
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&"link_199".to_string()]).unwrap();
+        let naive = fk.solve(&tree, &params).concat();
+        let wide = fk.solve_wide(&tree, &params).concat();
+
+        assert!(error(&wide) < 1e-5);
+        assert!(
+            error(&wide) * 2.0 < error(&naive),
+            "{} vs. {}",
+            error(&wide),
+            error(&naive)
+        );
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+
+        let trafo = translate_x_4x4(10.0);
+        let link1 = Segment::new(&trafo, Axis::RotationZ, None);
+        let link2 = Segment::new(&trafo, Axis::RotationZ, Some(trafo));
+        let link3 = Segment::new(&trafo, Axis::RotationZ, None);
+        let link4 = Segment::new(&trafo, Axis::RotationZ, Some(trafo));
+        // Doesn't do anything, is at the end
+        let link5 = Segment::new(&trafo, Axis::RotationZ, Some(trafo));
+
+        let ref1 = tree.set_root(link1, "link1".to_string());
+        tree.add(link2, "link2".to_string(), &ref1).unwrap();
+        let ref3 = tree.add(link3, "link3".to_string(), &ref1).unwrap();
+        let ref4 = tree.add(link4, "link4".to_string(), &ref3).unwrap();
+        tree.add(link5, "link5".to_string(), &ref4).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let joints = ["link1", "link2", "link3", "link4"].map(String::from);
+        let effectors = ["link2", "link4"].map(String::from);
+        let mut jacobian = DifferentiableModel::<f32>::new();
+        jacobian
+            .setup(&tree, &joints.iter().collect_vec(), &effectors.iter().collect_vec())
+            .unwrap();
+        jacobian.compute(
+            &tree,
+            &[0.0, 0.0, std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2, 0.0],
+            ComputeSelection::JacobianOnly,
+        );
+        assert_eq!(jacobian.shape(), (6, 4));
+
+        // Column-major
+        let target = [
+            [0.0, 20.0, 0.0, -10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, -10.0, -10.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0, -10.0, 0.0],
+        ]
+        .concat();
+        assert_abs_diff_eq!(jacobian.jacobian(), &target[..], epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_solve_linear() {
+        // Column-major [[4, 1], [1, 3]] (symmetric), i.e., the normal equations are solved for its square
+        let matrix = [4.0, 1.0, 1.0, 3.0];
+        let mut result = [0.0; 2];
+        Segment::solve_linear(&matrix, 2, 2, &[1.0, 2.0], 0.0, f32::INFINITY, &mut result);
+        assert_abs_diff_eq!(&result[..], &[1.0 / 11.0, 7.0 / 11.0][..], epsilon = 1e-4);
+
+        // Damping shortens the update
+        let mut damped = [0.0; 2];
+        Segment::solve_linear(&matrix, 2, 2, &[1.0, 2.0], 10.0, f32::INFINITY, &mut damped);
+        assert!(damped[0].hypot(damped[1]) < result[0].hypot(result[1]));
+
+        // Minimum-norm solution of a wide system
+        let mut result = [0.0; 2];
+        Segment::apply_pseudo_inverse(&[1.0, 1.0], 1, 2, &[1.0], &mut result);
+        assert_abs_diff_eq!(&result[..], &[0.5, 0.5][..], epsilon = 1e-4);
+    }
+}
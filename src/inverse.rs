@@ -1,13 +1,21 @@
 //! Interface and basic implementor for the inverse kinematic model.
 
-use std::{fmt::Debug, iter::Sum};
+use std::{
+    any::Any,
+    fmt::Debug,
+    iter::Sum,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
 
 use itertools::{izip, Itertools};
 use num_traits::Float;
 
 use crate::{
-    differentiable::{ComputeSelection, Filterable},
-    DepthFirstIterable, Differentiable, Rigid,
+    differentiable::{jacobian_columns, parameter_count, ComputeSelection, TransformationCache},
+    forward::TransformationAccumulation,
+    pose::{joint_limits, rest_params},
+    DepthFirstIterable, Differentiable, MannequinError, NodeLike, Rigid,
 };
 
 /// Trait representing a stateful forward kinematics algorithm.
@@ -22,8 +30,14 @@ where
     /// The return type com [Inverse::solve] that carries information about the outcome
     type Info;
 
-    /// Preparation the computation (memory allocation, and joint/effector selection).
-    fn setup(&mut self, tree: &IT, selected_joints: &[&<RB as Rigid>::NodeId], selected_effectors: &[&RB::NodeId]);
+    /// Preparation the computation (memory allocation, and joint/effector selection). Fails with
+    /// [MannequinError::UnknownNode] if a selected joint or effector is not in the tree.
+    fn setup(
+        &mut self,
+        tree: &IT,
+        selected_joints: &[&<RB as Rigid>::NodeId],
+        selected_effectors: &[&RB::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>>;
 
     /// Compute the inverse kinematics
     fn solve(
@@ -34,6 +48,86 @@ where
     ) -> Self::Info;
 }
 
+/// Object-safe counterpart of [Inverse] that erases the solver-specific [Inverse::Info] such that different
+/// solvers (for the same tree and backend) can be stored as `Box<dyn DynInverse<IT, RB>>`, e.g., to switch
+/// them at runtime. Implemented for all implementers of [Inverse]. The methods are suffixed to avoid
+/// ambiguities when both traits are in scope.
+pub trait DynInverse<IT, RB>
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid,
+{
+    /// See [Inverse::setup]
+    fn setup_dyn(
+        &mut self,
+        tree: &IT,
+        selected_joints: &[&RB::NodeId],
+        selected_effectors: &[&RB::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>>;
+
+    /// See [Inverse::solve]. The returned information can be downcast to the solver's [Inverse::Info]
+    /// (e.g., [DiffIKInfo]).
+    fn solve_dyn(&mut self, tree: &IT, param: &mut [RB::FloatType], targets: &[RB::FloatType]) -> Box<dyn Any>;
+}
+
+impl<IT, RB, IK> DynInverse<IT, RB> for IK
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid,
+    IK: Inverse<IT, RB>,
+    IK::Info: 'static,
+{
+    fn setup_dyn(
+        &mut self,
+        tree: &IT,
+        selected_joints: &[&RB::NodeId],
+        selected_effectors: &[&RB::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>> {
+        Inverse::setup(self, tree, selected_joints, selected_effectors)
+    }
+
+    fn solve_dyn(&mut self, tree: &IT, param: &mut [RB::FloatType], targets: &[RB::FloatType]) -> Box<dyn Any> {
+        Box::new(Inverse::solve(self, tree, param, targets))
+    }
+}
+
+/// Fits the model to a sequence of target sets (e.g., motion capture markers) by solving the inverse
+/// kinematics frame by frame. Each frame is warm-started with the solution of the previous one (the
+/// first one with `initial`), which keeps the solutions temporally coherent and speeds up convergence.
+///
+/// Returns the fitted parameters and the solver's diagnostics (e.g., the residual) for each frame.
+pub fn fit_frames<IT, RB, IK>(
+    ik: &mut IK,
+    tree: &IT,
+    initial: &[RB::FloatType],
+    targets: &[Vec<RB::FloatType>],
+) -> (Vec<Vec<RB::FloatType>>, Vec<IK::Info>)
+where
+    IT: DepthFirstIterable<RB, RB::NodeId>,
+    RB: Rigid,
+    IK: Inverse<IT, RB>,
+{
+    let mut params = initial.to_vec();
+    targets
+        .iter()
+        .map(|frame| {
+            let info = ik.solve(tree, &mut params, frame);
+            (params.clone(), info)
+        })
+        .unzip()
+}
+
+/// Reason why [DifferentialInverseModel] stopped iterating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The squared error fell below the minimal error
+    MinError,
+    /// The norm of the joint update fell below the minimal step size (stalled)
+    MinStep,
+    /// The maximal number of iterations has been reached
+    MaxIterations,
+}
+
 /// Information about the solution for the dfault, differential IK solver
 #[derive(Debug, Clone)]
 pub struct DiffIKInfo<F: Float> {
@@ -41,6 +135,149 @@ pub struct DiffIKInfo<F: Float> {
     pub iteration_count: usize,
     /// The final squared error
     pub squared_error: F,
+    /// The criterion that terminated the solver
+    pub stop_reason: StopReason,
+    /// Whether the solver stopped because the error fell below the minimal error (as opposed to stalling or
+    /// running out of iterations)
+    pub converged: bool,
+    /// Squared error of each iteration (as [DifferentialInverseModel::error_history])
+    pub error_history: Vec<F>,
+    /// The final norm of the residual of each selected effector, i.e., the distance to its target for the default
+    /// [PositionTask] (same order as the effectors in the tree)
+    pub effector_residuals: Vec<F>,
+    /// Where the time was spent. Only measured if enabled with [DifferentialInverseModel::set_timing]
+    pub timing: Option<SolveTiming>,
+}
+
+/// Breakdown of the time spent in a call to [Inverse::solve] (see [DifferentialInverseModel::set_timing])
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolveTiming {
+    /// Forward kinematics and Jacobian matrix ([Differentiable::compute]), including the line search
+    pub compute: Duration,
+    /// Solving the systems of linear equations ([Rigid::solve_linear])
+    pub solve_linear: Duration,
+    /// The complete solve
+    pub total: Duration,
+}
+
+/// Time passed since `start` (zero if not measured)
+fn elapsed(start: Option<Instant>) -> Duration {
+    start.map(|start| start.elapsed()).unwrap_or_default()
+}
+
+impl<F: Float> DiffIKInfo<F> {
+    /// Whether every effector is within `tolerance` (a distance, unlike the squared error summed over all
+    /// effectors) of its target.
+    pub fn reached(&self, tolerance: F) -> bool {
+        self.effector_residuals.iter().all(|residual| *residual <= tolerance)
+    }
+}
+
+/// How strictly the target of an effector is to be met. Translates into the weight of the
+/// effector's rows in the weighted least-squares problem solved in each iteration, such that
+/// soft targets yield to hard ones when they conflict.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hardness<F> {
+    /// The target is only encouraged with the given (relative) weight
+    Soft(F),
+    /// The target is (effectively) met exactly
+    Hard,
+}
+
+impl<F: Float> Hardness<F> {
+    /// Weight used for [Hardness::Hard] targets
+    pub fn weight(&self) -> F {
+        match self {
+            Hardness::Soft(weight) => *weight,
+            Hardness::Hard => F::from(1e4).unwrap(),
+        }
+    }
+}
+
+/// How [DifferentialInverseModel] computes the joint update `Δθ` from the Jacobian matrix `J` and the (scaled and
+/// weighted) residual `e` in each iteration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateRule {
+    /// Least-squares solution of `J Δθ = e` as implemented by the backend ([Rigid::solve_linear])
+    PseudoInverse,
+    /// `Δθ = α Jᵀe` with the step `α = ⟨e, JJᵀe⟩ / ‖JJᵀe‖²` that is optimal for the linearized problem. No
+    /// system of linear equations is solved, which makes iterations cheap and robust near singularities, but
+    /// convergence is slower.
+    JacobianTranspose,
+    /// `Δθ = (JᵀJ + λ²I)⁻¹ Jᵀe` with `λ²` set by [DifferentialInverseModel::set_damping], which limits the update
+    /// near singular configurations at the cost of accuracy (larger values damp more). Solved as the least-squares
+    /// problem `[J; λI] Δθ = [e; 0]` with [Rigid::apply_pseudo_inverse], and limited like [UpdateRule::PseudoInverse].
+    DampedLeastSquares,
+}
+
+/// Definition of the residual that [DifferentialInverseModel] drives to zero given the (flattened) effectors
+/// and targets (e.g., position differences, or inequalities such as staying above a plane). The residual has
+/// the dimension of the effectors such that it can be weighted per effector coordinate. Coordinates that do not
+/// contribute to the task have a zero residual (and zero rows in the Jacobian).
+pub trait EffectorTask<F: Float> {
+    /// Write the residual (i.e., the desired change of the task) into `residual`
+    fn residual(&self, targets: &[F], effectors: &[F], residual: &mut [F]);
+
+    /// Transform the column-major Jacobian matrix of the effectors (with `rows` rows) in place into the
+    /// Jacobian of the task. Defaults to the identity, i.e., the residual changes like the effectors.
+    fn jacobian(&self, _targets: &[F], _effectors: &[F], _jacobian: &mut [F], _rows: usize) {}
+
+    /// Called by [Inverse::setup] with the size of each selected effector (same order as the effectors in the
+    /// tree), e.g., to find out which coordinates are orientations. Does nothing by default.
+    fn setup(&mut self, _effector_sizes: &[usize]) {}
+}
+
+/// The default task: effectors are moved onto their targets, i.e., the residual is `targets - effectors`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PositionTask;
+
+impl<F: Float> EffectorTask<F> for PositionTask {
+    fn residual(&self, targets: &[F], effectors: &[F], residual: &mut [F]) {
+        izip!(residual, targets, effectors).for_each(|(r, t, e)| *r = *t - *e);
+    }
+}
+
+/// Task for effectors with pose targets (e.g., for grasping): effectors with six coordinates (position and
+/// orientation as rotation vector, e.g., `Mode::Pose` of the ndarray backend) are moved onto the target position
+/// and rotated onto the target orientation along the shortest path, i.e., the orientation residual is the
+/// rotation vector of `target·currentᵀ` (see [Rigid::rotation_vector_difference] of the backend `RB`) instead of
+/// the difference of the rotation vectors (which is wrong for larger rotations). The residuals of all other
+/// effectors are `targets - effectors`.
+#[derive(Debug, Clone)]
+pub struct PoseTask<RB> {
+    /// Size of each selected effector
+    sizes: Vec<usize>,
+    _backend: PhantomData<RB>,
+}
+
+impl<RB> Default for PoseTask<RB> {
+    fn default() -> Self {
+        Self {
+            sizes: vec![],
+            _backend: PhantomData,
+        }
+    }
+}
+
+impl<RB: Rigid> EffectorTask<RB::FloatType> for PoseTask<RB> {
+    fn residual(&self, targets: &[RB::FloatType], effectors: &[RB::FloatType], residual: &mut [RB::FloatType]) {
+        PositionTask.residual(targets, effectors, residual);
+        let mut offset = 0;
+        for size in &self.sizes {
+            if *size == 6 {
+                let range = offset + 3..offset + 6;
+                residual[range.clone()].copy_from_slice(&RB::rotation_vector_difference(
+                    &effectors[range.clone()],
+                    &targets[range],
+                ));
+            }
+            offset += size;
+        }
+    }
+
+    fn setup(&mut self, effector_sizes: &[usize]) {
+        self.sizes = effector_sizes.to_vec();
+    }
 }
 
 /// Reference implementation of a differential IK solver that is agnostic of the backend.
@@ -57,7 +294,43 @@ where
     max_iterations_count: usize,
     min_error: F,
     differential_model: D,
+    /// Node and parameter of each column of the Jacobian matrix (see [jacobian_columns])
+    columns: Vec<(usize, Option<usize>)>,
     scale_difference: F,
+    min_step: Option<F>,
+    /// Maximal number of times a step is halved in the backtracking line search. Disabled if `None`
+    line_search: Option<usize>,
+    /// Weight for each row of the Jacobian matrix (i.e., effector coordinate). Empty if unweighted
+    weights: Vec<F>,
+    /// Buffer for the Jacobian of the task with rows scaled by the square root of the weights (and columns by the
+    /// inverse square root of the joint weights)
+    weighted_jacobian: Vec<F>,
+    /// Weight for each parameter in the weighted least-norm update. Empty if unweighted
+    joint_weights: Vec<F>,
+    task: Box<dyn EffectorTask<F>>,
+    /// Squared error of each iteration of the last solve
+    error_history: Vec<F>,
+    /// Unweighted residual of the last iteration of the last solve
+    residual: Vec<F>,
+    /// Whether to measure [SolveTiming]
+    timing: bool,
+    /// Tolerance of the parameter changes up to which the transformations are reused between iterations. Only
+    /// unchanged parameters if `None`
+    incremental: Option<F>,
+    /// Whether to project unreachable targets onto the boundary of the workspace
+    clamp_to_reach: bool,
+    update_rule: UpdateRule,
+    /// Damping passed to [Rigid::solve_linear]
+    damping: F,
+    /// Maximal norm of an update passed to [Rigid::solve_linear]
+    step_limit: F,
+    /// Lower and upper bound of each parameter. Empty if unbounded
+    limits: Vec<(F, F)>,
+    /// Pose the redundant joints are pulled towards (in the null space of the task). Empty if disabled
+    rest_pose: Vec<F>,
+    rest_gain: F,
+    /// Whether [Inverse::setup] reads the rest pose from the metadata (see [Self::set_rest_pose])
+    rest_from_metadata: bool,
 }
 
 impl<F, D> DifferentialInverseModel<F, D>
@@ -71,13 +344,482 @@ where
         min_error: F,
         differential_model: D,
         scale_difference: F,
+        update_rule: UpdateRule,
     ) -> Self {
         Self {
             _max_depth,
             max_iterations_count,
             min_error,
             differential_model,
+            columns: vec![],
             scale_difference,
+            min_step: None,
+            line_search: None,
+            weights: vec![],
+            weighted_jacobian: vec![],
+            joint_weights: vec![],
+            task: Box::new(PositionTask),
+            error_history: vec![],
+            residual: vec![],
+            timing: false,
+            incremental: None,
+            clamp_to_reach: false,
+            update_rule,
+            damping: F::from(1e-5).unwrap(),
+            step_limit: F::from(std::f64::consts::PI / 180.0 * 40.0).unwrap(),
+            limits: vec![],
+            rest_pose: vec![],
+            rest_gain: F::zero(),
+            rest_from_metadata: false,
+        }
+    }
+
+    /// Squared error of each iteration of the last call to [Inverse::solve]
+    pub fn error_history(&self) -> &[F] {
+        &self.error_history
+    }
+
+    /// Residual (e.g., `targets - effectors`) in the last iteration of the last call to [Inverse::solve]
+    pub fn residual(&self) -> &[F] {
+        &self.residual
+    }
+
+    /// Clear the diagnostics retained from previous solves ([Self::error_history] and [Self::residual]).
+    /// Called at the start of each solve such that they only reflect the latest one.
+    pub fn reset_diagnostics(&mut self) {
+        self.error_history.clear();
+        self.residual.clear();
+    }
+
+    /// Replace the definition of the residual ([PositionTask] by default)
+    pub fn set_task(&mut self, mut task: impl EffectorTask<F> + 'static) {
+        task.setup(&self.effector_sizes());
+        self.task = Box::new(task);
+    }
+
+    /// Size of each selected effector
+    fn effector_sizes(&self) -> Vec<usize> {
+        self.differential_model
+            .effectors()
+            .iter()
+            .map(|effector| effector.len())
+            .collect()
+    }
+
+    /// Measure where the time is spent in each solve (reported in [DiffIKInfo::timing]), e.g., to find out whether
+    /// the forward pass or the linear solve dominates for a rig. Disabled by default to avoid the overhead.
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timing = enabled;
+    }
+
+    /// Reuse the world transformations between the iterations of a solve (see [TransformationCache]) such that only
+    /// the subtrees of joints whose parameters changed by more than `epsilon` are recomputed, which pays off if few
+    /// joints are active. With `None` (the default), only the transformations of unchanged parameters are reused,
+    /// which yields the exact result.
+    pub fn set_incremental(&mut self, epsilon: Option<F>) {
+        self.incremental = epsilon;
+    }
+
+    /// Project position targets beyond the reach of their effector onto the boundary of the workspace before
+    /// solving, such that the effector gets as close as possible instead of stalling. The reach is measured from
+    /// the most proximal active joint along the chain to the effector and assumes revolute joints (and a backend
+    /// supporting [Rigid::origin]). The error in [DiffIKInfo] refers to the projected targets. Disabled by default.
+    pub fn set_clamp_to_reach(&mut self, clamp_to_reach: bool) {
+        self.clamp_to_reach = clamp_to_reach;
+    }
+
+    /// Damping (Levenberg-Marquardt parameter `λ²`) added to the diagonal of the normal equations `JᵀJ` solved by
+    /// [UpdateRule::PseudoInverse] (see [Rigid::solve_linear]) and [UpdateRule::DampedLeastSquares]. Increase it if
+    /// the solver becomes unstable near singular configurations, decrease it if convergence is slow. Defaults to
+    /// `1e-5`.
+    pub fn set_damping(&mut self, damping: F) {
+        self.damping = damping;
+    }
+
+    /// Limit the (L2) norm of each update of [UpdateRule::PseudoInverse] (see [Rigid::solve_linear]) and
+    /// [UpdateRule::DampedLeastSquares] such that large errors do not cause huge steps that overshoot and oscillate,
+    /// particularly near singular configurations. Defaults to `40°` in radians (the kinematics are assumed to be near linear in that range),
+    /// infinity disables the limit.
+    pub fn set_step_limit(&mut self, limit: F) {
+        self.step_limit = limit;
+    }
+
+    /// Bound each parameter (same order as the parameters passed to [Inverse::solve]) to `(min, max)`. After each
+    /// update, parameters are clamped into their range. Joints saturated at a limit are excluded from the next
+    /// update as long as the residual pulls them beyond, such that they stay clamped while the others compensate.
+    /// The limits stored as metadata can be collected with [crate::pose::limits_from_metadata]. No limits are
+    /// applied if empty (the default).
+    pub fn set_limits(&mut self, limits: Vec<(F, F)>) {
+        self.limits = limits;
+    }
+
+    /// Pull the joints towards `rest_pose` (same order as the parameters passed to [Inverse::solve]) as a secondary
+    /// objective of redundant chains: in each iteration, the step `gain · (rest_pose - params)` is projected onto the
+    /// null space of the task with `I - J⁺J` such that it does not disturb reaching the targets. An empty
+    /// `rest_pose` defaults to the rest values stored as metadata (see [crate::pose::rest_params]) of the tree
+    /// passed to the next [Inverse::setup]. Disabled if `gain` is zero (the default).
+    pub fn set_rest_pose(&mut self, rest_pose: Vec<F>, gain: F) {
+        self.rest_from_metadata = rest_pose.is_empty() && !gain.is_zero();
+        self.rest_pose = rest_pose;
+        self.rest_gain = gain;
+    }
+
+    /// Weight each parameter (same order as the parameters passed to [Inverse::solve], positive values) such that
+    /// the update is the weighted least-norm solution `Δθ = W⁻¹Jᵀ(JW⁻¹Jᵀ + λ²I)⁻¹e` with the diagonal matrix `W` of
+    /// the weights and the damping `λ²` (see [Self::set_damping]). Among the updates achieving the task, joints
+    /// with a large weight move less, e.g., to keep them away from their limits. The update rule is applied to the
+    /// weighted update `W^½Δθ` (which the step limit refers to). All parameters are weighted equally if empty (the
+    /// default).
+    pub fn set_joint_weights(&mut self, weights: Vec<F>) {
+        debug_assert!(weights.iter().all(|w| *w > F::zero()));
+        self.joint_weights = weights;
+    }
+
+    /// Additionally stop when the norm of the joint update falls below `min_step` (i.e., the solver
+    /// has stalled in a local minimum). Disabled (`None`) by default.
+    pub fn set_min_step(&mut self, min_step: Option<F>) {
+        self.min_step = min_step;
+    }
+
+    /// Enable a backtracking line search with at most `max_halvings` retries (disabled with `None`, the default).
+    /// In each iteration, the full step is tried first and halved until the (weighted) squared error decreases.
+    /// This makes convergence robust against a too large `scale_difference` at the cost of additional
+    /// evaluations of the effectors.
+    pub fn set_line_search(&mut self, max_halvings: Option<usize>) {
+        self.line_search = max_halvings;
+    }
+
+    /// Clamp the parameters into their limits (see [Self::set_limits])
+    fn clamp(&self, params: &mut [F]) {
+        debug_assert!(self.limits.is_empty() || self.limits.len() == params.len());
+        params
+            .iter_mut()
+            .zip(&self.limits)
+            .for_each(|(p, (min, max))| *p = p.max(*min).min(*max));
+    }
+
+    /// Scale `1 / √w` of each column of the Jacobian matrix given the joint weights `w` (see
+    /// [Self::set_joint_weights]). Empty if unweighted
+    fn joint_scales(&self) -> Vec<F> {
+        if self.joint_weights.is_empty() {
+            return vec![];
+        }
+        self.columns
+            .iter()
+            .map(|(_, param)| param.map_or(F::one(), |param| F::one() / self.joint_weights[param].sqrt()))
+            .collect()
+    }
+
+    /// Parameter (index) of each column of the Jacobian matrix with the corresponding entry of the joint update
+    /// `result`. Columns without parameters (fixed joints) are skipped.
+    fn parameters_of<'a>(&'a self, result: &'a [F]) -> impl Iterator<Item = (usize, F)> + 'a {
+        izip!(&self.columns, result).filter_map(|((_, param), r)| Some(((*param)?, *r)))
+    }
+
+    /// Weighted squared residual of the effectors from the last computation with respect to `targets`
+    fn objective(&self, targets: &[F]) -> F
+    where
+        F: Sum,
+    {
+        let mut residual = vec![F::zero(); targets.len()];
+        self.task
+            .residual(targets, self.differential_model.flat_effectors(), &mut residual);
+        let residuals = residual.iter().map(|r| *r * *r);
+        if self.weights.is_empty() {
+            residuals.sum()
+        } else {
+            residuals.zip(&self.weights).map(|(r, w)| r * *w).sum()
+        }
+    }
+
+    /// Squared norm of the (unweighted) task residual, e.g., `sum((target - effector)²)`, at `params`. Unlike
+    /// [Inverse::solve], the solver's state is not changed, which allows evaluating candidate poses or monitoring
+    /// convergence externally. Uses the selection of the last call to [Inverse::setup].
+    pub fn task_error<IT, RB>(&self, tree: &IT, params: &[F], targets: &[F]) -> F
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+        F: Sum,
+    {
+        let mut effectors = vec![F::zero(); self.differential_model.rows()];
+        tree.iter()
+            .accumulate(params, 42)
+            .enumerate()
+            .for_each(|(index, (node, pose))| {
+                if let Some(range) = self.differential_model.effector_range(index) {
+                    node.get().effector(&pose, &mut effectors, range.start);
+                }
+            });
+
+        let mut residual = vec![F::zero(); targets.len()];
+        self.task.residual(targets, &effectors, &mut residual);
+        residual.iter().map(|r| *r * *r).sum()
+    }
+
+    /// Copy of `targets` in which the positions (first three coordinates) of effectors are moved towards the
+    /// base of their chain until they are within reach (see [Self::set_clamp_to_reach]).
+    fn project_to_reach<IT, RB>(&self, tree: &IT, params: &[F], targets: &[F]) -> Vec<F>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+        F: Sum,
+    {
+        let mut result = targets.to_vec();
+        let poses = tree.iter().accumulate(params, 42).collect_vec();
+        // Index of the parent of each node
+        let mut path = vec![];
+        let parents = poses
+            .iter()
+            .enumerate()
+            .map(|(index, (node, _))| {
+                path.truncate(node.depth());
+                let parent = path.last().copied();
+                path.push(index);
+                parent
+            })
+            .collect_vec();
+        let active = self.differential_model.active();
+        let distance = |a: &[F; 3], b: &[F]| a.iter().zip(b).map(|(x, y)| (*x - *y) * (*x - *y)).sum::<F>().sqrt();
+
+        poses.iter().enumerate().for_each(|(index, (node, pose))| {
+            let Some(range) = self.differential_model.effector_range(index) else {
+                return;
+            };
+            if range.len() < 3 {
+                return;
+            }
+            // Chain from the root to the effector and the pivots from the first active joint on
+            let chain = std::iter::successors(Some(index), |child| parents[*child])
+                .collect_vec()
+                .into_iter()
+                .rev()
+                .skip_while(|joint| !active[*joint]);
+            let Some(pivots) = chain
+                .map(|joint| RB::origin(&poses[joint].1))
+                .collect::<Option<Vec<_>>>()
+            else {
+                return;
+            };
+            let Some(base) = pivots.first() else {
+                return;
+            };
+            let mut effector = vec![F::zero(); node.get().effector_size()];
+            node.get().effector(pose, &mut effector, 0);
+
+            let reach = pivots.iter().tuple_windows().map(|(a, b)| distance(a, b)).sum::<F>()
+                + distance(pivots.last().unwrap(), &effector[..3]);
+            let target = &mut result[range.start..range.start + 3];
+            let span = distance(base, target);
+            if span > reach {
+                target
+                    .iter_mut()
+                    .zip(base)
+                    .for_each(|(t, b)| *t = *b + (*t - *b) * reach / span);
+            }
+        });
+        result
+    }
+
+    /// Set how strictly each selected effector's target has to be met (same order as the
+    /// effectors in the tree). Call after [Inverse::setup] which resets the weighting.
+    pub fn set_hardness(&mut self, hardness: &[Hardness<F>]) {
+        let effectors = self.differential_model.effectors();
+        debug_assert_eq!(hardness.len(), effectors.len());
+
+        self.weights = izip!(effectors, hardness)
+            .flat_map(|(effector, hardness)| std::iter::repeat(hardness.weight()).take(effector.len()))
+            .collect();
+    }
+
+    /// Set the weight of each effector coordinate (i.e., row of the Jacobian matrix, length [Differentiable::rows])
+    /// in the weighted least-squares problem, e.g., to prioritize the position of the hand over the orientation
+    /// of the foot. Finer grained than (and replaces) [Self::set_hardness]. An empty vector weights all coordinates
+    /// equally (the default). Call after [Inverse::setup] which resets the weighting.
+    pub fn set_weights(&mut self, weights: Vec<F>) {
+        debug_assert!(weights.is_empty() || weights.len() == self.differential_model.rows());
+        self.weights = weights;
+    }
+
+    /// Diagnoses which active joint limits reaching `targets` most at `params` (e.g., after a solve stalled).
+    /// A joint that sits at one of its limits (see [joint_limits]) while the residual pulls it beyond is reported
+    /// first (the one with the strongest pull if several are saturated). Otherwise, the joint contributing
+    /// least to reducing the residual (the smallest component of the gradient `Jᵀe`) is reported. `None` if no
+    /// joint is active. Uses the selection of the last call to [Inverse::setup].
+    pub fn limiting_joint<IT, RB>(&mut self, tree: &IT, params: &[F], targets: &[F]) -> Option<RB::NodeId>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+    {
+        self.differential_model.compute(tree, params, ComputeSelection::All);
+        let mut residual = vec![F::zero(); targets.len()];
+        self.task
+            .residual(targets, self.differential_model.flat_effectors(), &mut residual);
+        if !self.weights.is_empty() {
+            residual.iter_mut().zip(&self.weights).for_each(|(r, w)| *r = *r * *w);
+        }
+        let (rows, cols) = self.differential_model.shape();
+        let mut gradient = vec![F::zero(); cols];
+        RB::apply_transpose(self.differential_model.jacobian(), rows, cols, &residual, &mut gradient);
+
+        let tolerance = F::epsilon().sqrt();
+        let nodes = tree.iter().collect_vec();
+        // Joints without parameters (fixed) cannot be limiting
+        let joints = izip!(&self.columns, gradient)
+            .filter_map(|((index, param), gradient)| {
+                let (node, param) = (nodes[*index], params[(*param)?]);
+                let (min, max) = joint_limits(node);
+                let blocked = (param <= min + tolerance && gradient < F::zero())
+                    || (param >= max - tolerance && gradient > F::zero());
+                Some((node.id(), blocked, gradient.abs()))
+            })
+            .collect_vec();
+
+        let by_gradient = |a: &&(_, _, F), b: &&(_, _, F)| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal);
+        joints
+            .iter()
+            .filter(|(_, blocked, _)| *blocked)
+            .max_by(by_gradient)
+            .or_else(|| joints.iter().min_by(by_gradient))
+            .map(|(id, _, _)| (*id).clone())
+    }
+
+    /// Moves `effector` relative to its current position by `delta` (e.g., when dragging in a UI). The
+    /// current effector positions are computed with the forward kinematics, all other selected effectors
+    /// are held in place. Uses the selection of the last call to [Inverse::setup] which has to include `effector`.
+    pub fn solve_delta<IT, RB>(
+        &mut self,
+        tree: &IT,
+        params: &mut [F],
+        effector: &RB::NodeId,
+        delta: &[F],
+    ) -> Result<DiffIKInfo<F>, MannequinError<RB::NodeId>>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+        F: Sum + Debug,
+    {
+        let range = tree
+            .iter()
+            .position(|node| node.id() == effector)
+            .and_then(|index| self.differential_model.effector_range(index))
+            .ok_or_else(|| MannequinError::UnknownNode(effector.clone()))?;
+        if range.len() != delta.len() {
+            return Err(MannequinError::DimensionMismatch(delta.len()));
+        }
+
+        self.differential_model
+            .compute(tree, params, ComputeSelection::EffectorsOnly);
+        let mut targets = self.differential_model.flat_effectors().to_vec();
+        targets[range].iter_mut().zip(delta).for_each(|(t, d)| *t = *t + *d);
+
+        Ok(Inverse::<IT, RB>::solve(self, tree, params, &targets))
+    }
+
+    /// Moves `effector` to `target` using only `from_joint` and its descendants, that is, all joints closer to
+    /// the root are frozen (e.g., for solving from the elbow down). Replaces the selection of the last call to
+    /// [Inverse::setup] (which also resets the hardness).
+    pub fn solve_from<IT, RB>(
+        &mut self,
+        tree: &IT,
+        params: &mut [F],
+        from_joint: &RB::NodeId,
+        effector: &RB::NodeId,
+        target: &[F],
+    ) -> Result<DiffIKInfo<F>, MannequinError<RB::NodeId>>
+    where
+        IT: DepthFirstIterable<RB, RB::NodeId>,
+        RB: Rigid<FloatType = F>,
+        F: Sum + Debug,
+    {
+        let from = tree.node_by_id_or_err(from_joint)?;
+        tree.node_by_id_or_err(effector)?;
+        let active = tree.iter_sub(from).map(|node| node.id()).collect_vec();
+
+        Inverse::<IT, RB>::setup(self, tree, &active, &[effector])?;
+        if self.differential_model.rows() != target.len() {
+            return Err(MannequinError::DimensionMismatch(target.len()));
+        }
+        Ok(Inverse::<IT, RB>::solve(self, tree, params, target))
+    }
+}
+
+/// Product `J x` of the column-major `matrix` `J` with `rows` rows and `vector` `x`
+fn multiply<F: Float>(matrix: &[F], rows: usize, vector: &[F]) -> Vec<F> {
+    let mut result = vec![F::zero(); rows];
+    matrix.chunks(rows.max(1)).zip(vector).for_each(|(col, x)| {
+        result.iter_mut().zip(col).for_each(|(r, j)| *r = *r + *j * *x);
+    });
+    result
+}
+
+impl<F, D> DifferentialInverseModel<F, D>
+where
+    F: Float + Sum,
+    D: Differentiable<F>,
+{
+    /// Add the step towards the rest pose projected onto the null space of the task, i.e., `(I - J⁺J) z`, to the
+    /// joint update `result` (see [Self::set_rest_pose]). The step is weighted like the update with the column
+    /// `scales` of the Jacobian matrix (see [Self::joint_scales]).
+    fn add_rest_step<RB: Rigid<FloatType = F>>(&self, params: &[F], scales: &[F], result: &mut [F]) {
+        let (rows, cols) = self.differential_model.shape();
+        let step = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, (_, param))| {
+                param.map_or(F::zero(), |param| {
+                    (self.rest_pose[param] - params[param]) * self.rest_gain
+                        / scales.get(index).copied().unwrap_or(F::one())
+                })
+            })
+            .collect_vec();
+        // The part of the step that changes the task: J⁺J z
+        let change = multiply(&self.weighted_jacobian, rows, &step);
+        let mut projection = vec![F::zero(); cols];
+        RB::apply_pseudo_inverse(&self.weighted_jacobian, rows, cols, &change, &mut projection);
+        izip!(result, step, projection).for_each(|(r, z, p)| *r = *r + z - p);
+    }
+
+    /// Joint update for the residual `diff` according to the [UpdateRule] (using the weighted Jacobian matrix)
+    fn update<RB: Rigid<FloatType = F>>(&self, diff: &[F], result: &mut [F]) {
+        let (rows, cols) = self.differential_model.shape();
+        let jacobian = &self.weighted_jacobian;
+        match self.update_rule {
+            UpdateRule::PseudoInverse => {
+                RB::solve_linear(jacobian, rows, cols, diff, self.damping, self.step_limit, result)
+            }
+            UpdateRule::JacobianTranspose => {
+                RB::apply_transpose(jacobian, rows, cols, diff, result);
+                // Change of the residual caused by the update: J Jᵀe
+                let change = multiply(jacobian, rows, result);
+                let norm = change.iter().map(|c| *c * *c).sum::<F>();
+                let step = if norm > F::zero() {
+                    diff.iter().zip(&change).map(|(d, c)| *d * *c).sum::<F>() / norm
+                } else {
+                    F::zero()
+                };
+                result.iter_mut().for_each(|r| *r = *r * step);
+            }
+            UpdateRule::DampedLeastSquares => {
+                // Least-squares solution of the system augmented by the damping: [J; λI] Δθ = [e; 0]
+                let lambda = self.damping.sqrt();
+                let mut augmented = Vec::with_capacity((rows + cols) * cols);
+                jacobian
+                    .chunks(rows.max(1))
+                    .take(cols)
+                    .enumerate()
+                    .for_each(|(index, col)| {
+                        augmented.extend_from_slice(col);
+                        augmented.extend((0..cols).map(|row| if row == index { lambda } else { F::zero() }));
+                    });
+                let mut vector = diff.to_vec();
+                vector.resize(rows + cols, F::zero());
+                RB::apply_pseudo_inverse(&augmented, rows + cols, cols, &vector, result);
+                let norm = result.iter().map(|r| *r * *r).sum::<F>().sqrt();
+                if norm > self.step_limit {
+                    result.iter_mut().for_each(|r| *r = *r * self.step_limit / norm);
+                }
+            }
         }
     }
 }
@@ -96,54 +838,170 @@ where
         tree: &IT,
         selected_joints: &[&<RB as Rigid>::NodeId],
         selected_effectors: &[&<RB as Rigid>::NodeId],
-    ) {
-        self.differential_model.setup(tree, selected_joints, selected_effectors);
+    ) -> Result<(), MannequinError<RB::NodeId>> {
+        self.differential_model
+            .setup(tree, selected_joints, selected_effectors)?;
+        self.columns = jacobian_columns(tree, self.differential_model.active());
+        debug_assert_eq!(self.columns.len(), self.differential_model.cols());
+        self.weights.clear();
+        let sizes = self.effector_sizes();
+        self.task.setup(&sizes);
+        if self.rest_from_metadata {
+            self.rest_pose = rest_params(tree)?;
+        }
+        Ok(())
     }
 
     // TODO Think about turning this into an iterator ...
     fn solve(&mut self, tree: &IT, params: &mut [F], targets: &[F]) -> Self::Info {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        let solve_start = self.timing.then(Instant::now);
+        let mut timing = SolveTiming::default();
+        self.reset_diagnostics();
+        let projected;
+        let targets = if self.clamp_to_reach {
+            projected = self.project_to_reach(tree, params, targets);
+            &projected
+        } else {
+            targets
+        };
         let mut counter = 0;
         let mut error: F;
-        let mut result = vec![F::zero(); self.differential_model.active().iter().filter(|i| **i).count()];
+        let stop_reason;
+        let mut effector_residuals = vec![];
+        // An update per column of the Jacobian matrix, i.e., per parameter of the active joints
+        let mut result = vec![F::zero(); self.differential_model.cols()];
+        // Holds the transformations across iterations (see [Self::set_incremental])
+        let mut cache = TransformationCache::<RB>::new(self.incremental.unwrap_or_else(F::zero));
         loop {
-            dbg!(counter);
-            self.differential_model.compute(tree, params, ComputeSelection::All);
+            tracing::trace!(counter, "iteration");
+            let start = self.timing.then(Instant::now);
+            self.differential_model
+                .compute_cached(tree, params, ComputeSelection::All, &mut cache);
+            timing.compute += elapsed(start);
             // dbg!(&params);
-            dbg!(self.differential_model.flat_effectors());
+            tracing::trace!(effectors = ?self.differential_model.flat_effectors());
             // dbg!(self.differential_model.effectors());
-            let mut diff = izip!(targets, self.differential_model.flat_effectors())
-                .map(|(x, y)| (*x - *y))
-                .collect_vec();
+            let mut diff = vec![F::zero(); targets.len()];
+            self.task
+                .residual(targets, self.differential_model.flat_effectors(), &mut diff);
 
             // dbg!(&self.differential_model.jacobian());
             error = diff.iter().map(|x| *x * *x).sum();
-            dbg!(&error);
+            tracing::trace!(?error);
+            self.error_history.push(error);
+            self.residual.clone_from(&diff);
+            effector_residuals.clear();
+            let mut offset = 0;
+            for effector in self.differential_model.effectors() {
+                let residual = &diff[offset..offset + effector.len()];
+                effector_residuals.push(residual.iter().map(|x| *x * *x).sum::<F>().sqrt());
+                offset += effector.len();
+            }
             // dbg!(&diff);
 
             diff.iter_mut().for_each(|x| *x = *x * self.scale_difference);
 
-            RB::solve_linear(
-                self.differential_model.jacobian(),
-                self.differential_model.rows(),
-                self.differential_model.cols(),
-                &diff,
-                &mut result,
+            let rows = self.differential_model.rows();
+            self.weighted_jacobian.clear();
+            self.weighted_jacobian
+                .extend_from_slice(self.differential_model.jacobian());
+            self.task.jacobian(
+                targets,
+                self.differential_model.flat_effectors(),
+                &mut self.weighted_jacobian,
+                rows,
             );
 
+            // Weighted least squares: scale the rows of the equations by the root of the weights
+            if !self.weights.is_empty() {
+                let scales = self.weights.iter().map(|w| w.sqrt()).collect_vec();
+                diff.iter_mut().zip(&scales).for_each(|(x, s)| *x = *x * *s);
+                self.weighted_jacobian
+                    .chunks_mut(rows)
+                    .for_each(|col| col.iter_mut().zip(&scales).for_each(|(x, s)| *x = *x * *s));
+            }
+
+            // Joints saturated at a limit that the residual pulls beyond are excluded from the update
+            if !self.limits.is_empty() {
+                let cols = self.differential_model.cols();
+                let mut gradient = vec![F::zero(); cols];
+                RB::apply_transpose(&self.weighted_jacobian, rows, cols, &diff, &mut gradient);
+                let tolerance = F::epsilon().sqrt();
+                izip!(&self.columns, gradient, self.weighted_jacobian.chunks_mut(rows.max(1)))
+                    .filter(|((_, param), gradient, _)| {
+                        param.map_or(false, |param| {
+                            let (min, max) = self.limits[param];
+                            (params[param] <= min + tolerance && *gradient < F::zero())
+                                || (params[param] >= max - tolerance && *gradient > F::zero())
+                        })
+                    })
+                    .for_each(|(_, _, col)| col.iter_mut().for_each(|x| *x = F::zero()));
+            }
+
+            // Weighted least norm: solve for W^½Δθ with the columns scaled by W^-½
+            let scales = self.joint_scales();
+            self.weighted_jacobian
+                .chunks_mut(rows.max(1))
+                .zip(&scales)
+                .for_each(|(col, s)| col.iter_mut().for_each(|x| *x = *x * *s));
+
+            let start = self.timing.then(Instant::now);
+            self.update::<RB>(&diff, &mut result);
+            if !self.rest_pose.is_empty() {
+                self.add_rest_step::<RB>(params, &scales, &mut result);
+            }
+            result.iter_mut().zip(&scales).for_each(|(r, s)| *r = *r * *s);
+            timing.solve_linear += elapsed(start);
+
             // dbg!(&result);
             // dbg!(&params);
 
-            params
-                .iter_mut()
-                .filter_active(self.differential_model.active())
-                .zip(&result)
-                .for_each(|(p, r)| *p = *p + *r);
+            let previous = params.to_vec();
+            if let Some(max_halvings) = self.line_search {
+                let objective = self.objective(targets);
+                let start = params.to_vec();
+                let mut step = F::one();
+                for halvings in 0..=max_halvings {
+                    self.parameters_of(&result)
+                        .for_each(|(param, r)| params[param] = start[param] + r * step);
+                    self.clamp(params);
+                    let start = self.timing.then(Instant::now);
+                    self.differential_model
+                        .compute_cached(tree, params, ComputeSelection::EffectorsOnly, &mut cache);
+                    timing.compute += elapsed(start);
+                    if halvings == max_halvings || self.objective(targets) < objective {
+                        break;
+                    }
+                    step = step / (F::one() + F::one());
+                }
+                // The actual step is relevant for the stopping criterion
+                result.iter_mut().for_each(|r| *r = *r * step);
+            } else {
+                self.parameters_of(&result)
+                    .for_each(|(param, r)| params[param] = params[param] + r);
+                self.clamp(params);
+            }
+            if !self.limits.is_empty() {
+                // The actual (clamped) step is relevant for the stopping criterion
+                izip!(&self.columns, result.iter_mut())
+                    .filter_map(|((_, param), r)| Some(((*param)?, r)))
+                    .for_each(|(param, r)| *r = params[param] - previous[param]);
+            }
 
             if error < self.min_error {
+                stop_reason = StopReason::MinError;
                 break;
             }
+            if let Some(min_step) = self.min_step {
+                if result.iter().map(|r| *r * *r).sum::<F>().sqrt() < min_step {
+                    stop_reason = StopReason::MinStep;
+                    break;
+                }
+            }
             counter += 1;
             if counter >= self.max_iterations_count {
+                stop_reason = StopReason::MaxIterations;
                 break;
             }
         }
@@ -151,6 +1009,14 @@ where
         Self::Info {
             iteration_count: counter,
             squared_error: error,
+            stop_reason,
+            converged: stop_reason == StopReason::MinError,
+            error_history: self.error_history.clone(),
+            effector_residuals,
+            timing: solve_start.map(|start| SolveTiming {
+                total: start.elapsed(),
+                ..timing
+            }),
         }
     }
 }
@@ -161,8 +1027,11 @@ mod test {
 
     use super::*;
     use crate::arena::iterables::OptimizedDirectionIterable;
-    use crate::ndarray::robot::{Axis, LinkNodeId, Segment};
-    use crate::{DepthFirstArenaTree, DifferentiableModel, DirectedArenaTree, DirectionIterable};
+    use crate::ndarray::robot::{Axis, LinkNodeId, Mode, Segment};
+    use crate::pose::REST;
+    use crate::{
+        DepthFirstArenaTree, DifferentiableModel, DirectedArenaTree, DirectionIterable, Forward, ForwardModel,
+    };
     use ndarray::prelude::*;
 
     #[cfg(feature = "ndarray")]
@@ -192,7 +1061,14 @@ mod test {
 
         // let mut ik = DifferentialInverseModel::new(42, 10, 0.01, DifferentiableModel::new());
         let n_iterations = 13;
-        let mut ik = DifferentialInverseModel::new(42, n_iterations, 0.01, DifferentiableModel::new(), 1.0);
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            n_iterations,
+            0.01,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
 
         ik.setup(
             &tree,
@@ -203,7 +1079,8 @@ mod test {
                 &"link4".to_string(),
             ],
             &[&"link2".to_string(), &"link4".to_string()],
-        );
+        )
+        .unwrap();
 
         let effectors = vec![vec![20.0, 0.0, 0.0], vec![20.0, 10.0, 0.0]];
         let effectors = effectors.into_iter().flatten().collect_vec();
@@ -221,8 +1098,8 @@ mod test {
         // assert_abs_diff_eq!(result, target, epsilon = 1e-6);
     }
 
-    #[test]
-    fn test_tentacle() {
+    /// Chain of ten links (length 10) rotating around z with an effector at the tip of `link_9`
+    fn tentacle() -> DepthFirstArenaTree<Segment, LinkNodeId> {
         let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
 
         let mut trafo = Segment::neutral_element();
@@ -248,21 +1125,32 @@ mod test {
         .unwrap();
 
         // finalize tree
-        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        tree.into()
+    }
+
+    #[test]
+    fn test_tentacle() {
+        let tree = tentacle();
         tree.iter().for_each(|n| {
             dbg!(&n);
         });
 
         let n_iterations = 13;
-        let mut ik = DifferentialInverseModel::new(42, n_iterations, 0.01, DifferentiableModel::new(), 0.001);
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            n_iterations,
+            0.01,
+            DifferentiableModel::new(),
+            0.001,
+            UpdateRule::PseudoInverse,
+        );
 
-        ik.setup(&tree, &[], &[&"link_9".to_string()]);
+        ik.setup(&tree, &[], &[&"link_9".to_string()]).unwrap();
 
         let effectors = vec![vec![00.0, 20.0, 0.0]];
 
         let effectors = effectors.into_iter().flatten().collect_vec();
-        // let mut param = vec![0.0; 5];
-        let mut param = vec![0.0; 10];
+        let mut param = vec![0.0; parameter_count(&tree)];
 
         let result = ik.solve(&tree, &mut param, &effectors);
 
@@ -272,4 +1160,987 @@ mod test {
         // assert!(x.abs_diff_eq(&array![1., -2., -2.], 1e-9));
         // assert_abs_diff_eq!(result, target, epsilon = 1e-6);
     }
+
+    #[test]
+    fn test_step_limit() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        // Norm of the first update towards a target far from the effector
+        let first_step = |limit: f64, rule: UpdateRule| {
+            let mut ik = DifferentialInverseModel::new(42, 1, 1e-6, DifferentiableModel::new(), 1.0, rule);
+            ik.set_step_limit(limit);
+            ik.setup(&tree, &[], &[&tip]).unwrap();
+            let mut param = vec![0.1; 10];
+            ik.solve(&tree, &mut param, &[0.0, 20.0, 0.0]);
+            param.iter().map(|p| (p - 0.1).powi(2)).sum::<f64>().sqrt()
+        };
+
+        for rule in [UpdateRule::PseudoInverse, UpdateRule::DampedLeastSquares] {
+            assert!(first_step(f64::INFINITY, rule) > 1.0, "{rule:?}");
+            assert!((first_step(0.2, rule) - 0.2).abs() < 1e-9, "{rule:?}");
+        }
+    }
+
+    /// A normal forward and inverse kinematics run (see [test_no_output])
+    #[test]
+    fn test_quiet_run() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        fk.solve(&tree, &[0.1; 10]);
+
+        let mut ik =
+            DifferentialInverseModel::new(42, 50, 1e-6, DifferentiableModel::new(), 0.5, UpdateRule::PseudoInverse);
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        ik.solve(&tree, &mut [0.1; 10], &[40.0, 50.0, 0.0]);
+    }
+
+    #[test]
+    fn test_no_output() {
+        // The test harness captures the output, so run [test_quiet_run] in a separate process
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "inverse::test::test_quiet_run",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+        // Only the summary of the test harness
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("1 passed"), "{stdout}");
+        assert!(
+            stdout
+                .lines()
+                .all(|line| line.is_empty() || line.starts_with("running") || line.starts_with("test ")),
+            "{stdout}"
+        );
+    }
+
+    #[test]
+    fn test_convergence_diagnostics() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        let mut ik =
+            DifferentialInverseModel::new(42, 50, 1e-6, DifferentiableModel::new(), 0.5, UpdateRule::PseudoInverse);
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        let mut param = vec![0.1; 10];
+        let info = ik.solve(&tree, &mut param, &[40.0, 50.0, 0.0]);
+        assert!(info.converged);
+        assert_eq!(info.error_history, ik.error_history());
+        assert_eq!(info.error_history.len(), info.iteration_count + 1);
+        assert!(
+            info.error_history.windows(2).all(|pair| pair[1] <= pair[0]),
+            "{:?}",
+            info.error_history
+        );
+
+        // Out of reach (the tentacle is 100 long)
+        let mut param = vec![0.1; 10];
+        let info = ik.solve(&tree, &mut param, &[200.0, 0.0, 0.0]);
+        assert!(!info.converged);
+        assert_ne!(info.stop_reason, StopReason::MinError);
+    }
+
+    #[test]
+    fn test_update_rules() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        for rule in [
+            UpdateRule::PseudoInverse,
+            UpdateRule::JacobianTranspose,
+            UpdateRule::DampedLeastSquares,
+        ] {
+            let mut ik = DifferentialInverseModel::new(42, 200, 1e-6, DifferentiableModel::new(), 0.5, rule);
+            if rule == UpdateRule::DampedLeastSquares {
+                ik.set_damping(1.0);
+            }
+            ik.setup(&tree, &[], &[&tip]).unwrap();
+            let mut param = vec![0.1; 10];
+            let info = ik.solve(&tree, &mut param, &[40.0, 50.0, 0.0]);
+            assert_eq!(info.stop_reason, StopReason::MinError, "{rule:?}");
+            assert!(
+                ik.error_history().windows(2).all(|pair| pair[1] < pair[0]),
+                "{rule:?}: {:?}",
+                ik.error_history()
+            );
+        }
+    }
+
+    #[test]
+    fn test_rest_pose() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        // The rest pose (a slight curl) reaches the target
+        let rest = vec![0.1; 10];
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let target = fk.solve(&tree, &rest)[0].to_vec();
+        let distance = |param: &[f64]| izip!(param, &rest).map(|(p, r)| (p - r).powi(2)).sum::<f64>().sqrt();
+
+        // Iterate beyond reaching the target such that the null space is explored
+        let solve = |gain: Option<f64>| {
+            let mut ik =
+                DifferentialInverseModel::new(42, 50, 0.0, DifferentiableModel::new(), 1.0, UpdateRule::PseudoInverse);
+            if let Some(gain) = gain {
+                ik.set_rest_pose(rest.clone(), gain);
+            }
+            ik.setup(&tree, &[], &[&tip]).unwrap();
+            let mut param = vec![0.0; 10];
+            param[0] = 0.5;
+            let info = ik.solve(&tree, &mut param, &target);
+            (param, info)
+        };
+
+        let (plain, info) = solve(None);
+        assert!(info.squared_error < 1e-8);
+        let (projected, info) = solve(Some(0.5));
+        // The target is still hit but the redundancy is used to reach the rest pose
+        assert!(info.squared_error < 1e-8);
+        assert!(distance(&plain) > 0.1);
+        assert!(distance(&projected) < 1e-6);
+
+        // Same with the rest pose stored as metadata
+        let mut tree = tree;
+        (0..10).for_each(|i| tree.set_metadata(&format!("link_{i}"), REST, "0.1").unwrap());
+        let mut ik =
+            DifferentialInverseModel::new(42, 50, 0.0, DifferentiableModel::new(), 1.0, UpdateRule::PseudoInverse);
+        ik.set_rest_pose(vec![], 0.5);
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        let mut param = vec![0.0; 10];
+        param[0] = 0.5;
+        assert!(ik.solve(&tree, &mut param, &target).squared_error < 1e-8);
+        assert!(distance(&param) < 1e-6);
+
+        tree.set_metadata(&"link_3".to_string(), REST, "curled").unwrap();
+        assert!(matches!(
+            ik.setup(&tree, &[], &[&tip]),
+            Err(MannequinError::InvalidMetadata(id)) if id == "link_3"
+        ));
+    }
+
+    #[test]
+    fn test_incremental() {
+        let tree = tentacle();
+        let tip = "link_9".to_string();
+        let joints = ["link_7".to_string(), "link_8".to_string(), tip.clone()];
+
+        let solve = |incremental: Option<f64>| {
+            let mut ik = DifferentialInverseModel::new(
+                42,
+                13,
+                0.01,
+                DifferentiableModel::new(),
+                0.001,
+                UpdateRule::PseudoInverse,
+            );
+            ik.set_incremental(incremental);
+            // Only the distal joints move such that the transformations of the others can be reused
+            ik.setup(&tree, &joints.iter().collect_vec(), &[&tip]).unwrap();
+            let mut param = vec![0.1; 10];
+            ik.solve(&tree, &mut param, &[60.0, 40.0, 0.0]);
+            param
+        };
+        let (full, cached) = (solve(None), solve(Some(0.0)));
+        assert!(full[7..].iter().all(|param| *param != 0.1));
+        izip!(&full, &cached).for_each(|(a, b)| assert!((a - b).abs() < 1e-9));
+
+        // Changing a joint recomputes its subtree only
+        let mut model = DifferentiableModel::new();
+        let mut cache = TransformationCache::new(0.0);
+        model.setup(&tree, &[], &[&tip]).unwrap();
+        let mut param = vec![0.1; 10];
+        model.compute_cached(&tree, &param, ComputeSelection::All, &mut cache);
+        assert_eq!(cache.recomputed_count(), 10);
+        param[7] = 0.2;
+        model.compute_cached(&tree, &param, ComputeSelection::All, &mut cache);
+        assert_eq!(cache.recomputed_count(), 3);
+        let cached = model.flat_effectors().to_vec();
+        cache.set_dirty_all();
+        model.compute_cached(&tree, &param, ComputeSelection::All, &mut cache);
+        assert_eq!(cache.recomputed_count(), 10);
+        assert_eq!(model.flat_effectors(), cached);
+        // Same as without a cache
+        model.compute(&tree, &param, ComputeSelection::All);
+        assert_eq!(model.flat_effectors(), cached);
+    }
+
+    /// Planar two-link arm rotating around z with an effector at the tip. The effector is at
+    /// `[30.0, 0.0, 0.0]` in the neutral pose and cannot leave the `z = 0` plane.
+    fn planar_arm() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+
+        let root = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "upper".to_string());
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "lower".to_string(),
+            &root,
+        )
+        .unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_min_step() {
+        let tree = planar_arm();
+
+        let n_iterations = 100;
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            n_iterations,
+            0.01,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.set_min_step(Some(1e-6));
+        ik.setup(&tree, &[], &[&"lower".to_string()]).unwrap();
+
+        // Reachable in the plane (pose [0.2, 0.4]) but one unit above it
+        let targets = [28.05407, 7.63313, 1.0];
+        let mut param = vec![0.5, 0.5];
+
+        let result = ik.solve(&tree, &mut param, &targets);
+
+        assert_eq!(result.stop_reason, StopReason::MinStep);
+        assert!(result.iteration_count < n_iterations);
+        // The error plateaus at the (squared) distance to the plane
+        assert!((result.squared_error - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_joint_weights() {
+        // Two redundant prismatic joints contributing equally to the position along the x-axis
+        let identity = Segment::neutral_element();
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let root = tree.set_root(Segment::new(&identity, Axis::TranslationX, None), "first".to_string());
+        tree.add(
+            Segment::new(&identity, Axis::TranslationX, Some(identity.clone())),
+            "second".to_string(),
+            &root,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let targets = [0.5, 0.0, 0.0];
+
+        let mut ik =
+            DifferentialInverseModel::new(42, 1, 0.0, DifferentiableModel::new(), 1.0, UpdateRule::PseudoInverse);
+        ik.setup(&tree, &[], &[&"second".to_string()]).unwrap();
+        let mut solve = |weights: Vec<f64>| {
+            ik.set_joint_weights(weights);
+            let mut param = vec![0.0, 0.0];
+            ik.solve(&tree, &mut param, &targets);
+            param
+        };
+
+        // Equal weights share the motion
+        let param = solve(vec![1.0, 1.0]);
+        izip!(&param, [0.25, 0.25]).for_each(|(a, b)| assert!((a - b).abs() < 1e-4));
+        assert_eq!(solve(vec![]), param);
+        // Penalizing the second joint shifts the motion to the first one while still achieving the task
+        let param = solve(vec![1.0, 4.0]);
+        izip!(&param, [0.4, 0.1]).for_each(|(a, b)| assert!((a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_weights() {
+        // Planar arm with an additional effector at the elbow
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let root = tree.set_root(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "upper".to_string(),
+        );
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "lower".to_string(),
+            &root,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let effectors = [&"upper".to_string(), &"lower".to_string()];
+
+        // Both targets are reachable individually but not at the same time
+        let elbow = [10.0, 10.0, 0.0];
+        let hand = [28.05407, 7.63313, 0.0];
+        let targets = [elbow, hand].concat();
+
+        let distance = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors).unwrap();
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-9,
+            DifferentiableModel::new(),
+            0.5,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &effectors).unwrap();
+        let mut solve = |weights: Vec<f64>| {
+            ik.set_weights(weights);
+            let mut param = vec![0.5, 0.5];
+            ik.solve(&tree, &mut param, &targets);
+            let result = fk.solve(&tree, &param);
+            (distance(result[0], &elbow), distance(result[1], &hand))
+        };
+
+        // Equal weights compromise
+        let (elbow_equal, hand_equal) = solve(vec![]);
+        // The hand is favored if the elbow is down-weighted
+        let (elbow_hand, hand_hand) = solve(vec![1e-4, 1e-4, 1e-4, 1.0, 1.0, 1.0]);
+        assert!(hand_hand < 0.1 && hand_hand < hand_equal);
+        assert!(elbow_hand > elbow_equal);
+        // And vice versa
+        let (elbow_elbow, hand_elbow) = solve(vec![1.0, 1.0, 1.0, 1e-4, 1e-4, 1e-4]);
+        assert!(elbow_elbow < 0.1 && elbow_elbow < elbow_equal);
+        assert!(hand_elbow > hand_equal);
+    }
+
+    #[test]
+    fn test_limits() {
+        let tree = planar_arm();
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.set_min_step(Some(1e-9));
+        ik.set_limits(vec![(-0.5, 0.5), (-0.3, 0.3)]);
+        ik.setup(&tree, &[], &[&"lower".to_string()]).unwrap();
+
+        // Straight up from the shoulder, which requires bending the shoulder by 90°
+        let mut param = vec![0.2, 0.2];
+        let info = ik.solve(&tree, &mut param, &[10.0, 20.0, 0.0]);
+
+        // Both joints saturate and the error plateaus
+        assert_eq!(param, [0.5, 0.3]);
+        assert_eq!(info.stop_reason, StopReason::MinStep);
+        assert!(info.squared_error > 1.0);
+        // Solving again does not move the saturated joints
+        let again = ik.solve(&tree, &mut param, &[10.0, 20.0, 0.0]);
+        assert_eq!(param, [0.5, 0.3]);
+        assert_eq!(again.squared_error, info.squared_error);
+        assert_eq!(again.iteration_count, 0);
+    }
+
+    #[test]
+    fn test_fixed_joint() {
+        // A fixed base (e.g., the root of a URDF) carrying a planar arm
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let base = tree.set_root(Segment::new(&trafo, Axis::Fixed, None), "base".to_string());
+        let upper = tree
+            .add(Segment::new(&trafo, Axis::RotationZ, None), "upper".to_string(), &base)
+            .unwrap();
+        let tip = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "lower".to_string(),
+                &upper,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let targets = fk.solve(&tree, &[0.3, -0.6])[0].to_vec();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        // The fixed joint has a zero column
+        assert_eq!(ik.differential_model.shape(), (3, 3));
+        let mut params = vec![0.0, 0.0];
+        let info = ik.solve(&tree, &mut params, &targets);
+
+        assert!(info.converged, "{info:?}");
+        assert!(ik.task_error(&tree, &params, &targets) < 1e-10);
+        // The fixed joint is never reported (its zero gradient would be the smallest)
+        assert_ne!(ik.limiting_joint(&tree, &params, &targets), Some(base));
+    }
+
+    #[test]
+    fn test_pose_task() {
+        // Three links with perpendicular axes and a pose effector at the tip
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let mut hand = Segment::new(&trafo, Axis::RotationX, Some(trafo.clone()));
+        hand.set_mode(Mode::Pose);
+        let root = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "shoulder".to_string());
+        let elbow = tree
+            .add(Segment::new(&trafo, Axis::RotationY, None), "elbow".to_string(), &root)
+            .unwrap();
+        let tip = tree.add(hand, "hand".to_string(), &elbow).unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        // A reachable pose
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let target = fk.solve(&tree, &[0.4, -0.6, 0.9])[0].to_vec();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            50,
+            1e-12,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        ik.set_task(PoseTask::<Segment>::default());
+        let mut param = vec![0.0; 3];
+        let info = ik.solve(&tree, &mut param, &target);
+        assert_eq!(info.stop_reason, StopReason::MinError);
+
+        let result = fk.solve(&tree, &param)[0].to_vec();
+        izip!(&result, &target).for_each(|(r, t)| assert!((r - t).abs() < 1e-4, "{result:?} vs. {target:?}"));
+    }
+
+    #[test]
+    fn test_hardness() {
+        // Planar arm with an additional effector at the elbow
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let root = tree.set_root(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "upper".to_string(),
+        );
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "lower".to_string(),
+            &root,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let effectors = [&"upper".to_string(), &"lower".to_string()];
+
+        // Both targets are reachable individually but not at the same time
+        let elbow = [10.0, 10.0, 0.0];
+        let hand = [28.05407, 7.63313, 0.0];
+        let targets = [elbow, hand].concat();
+
+        let distance = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors).unwrap();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-9,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &effectors).unwrap();
+
+        // The hand target is met, the elbow yields
+        ik.set_hardness(&[Hardness::Soft(1.0), Hardness::Hard]);
+        let mut param = vec![0.5, 0.5];
+        ik.solve(&tree, &mut param, &targets);
+        let result = fk.solve(&tree, &param);
+        assert!(distance(result[1], &hand) < 0.1);
+        assert!(distance(result[0], &elbow) > 5.0);
+
+        // And vice versa
+        ik.set_hardness(&[Hardness::Hard, Hardness::Soft(1.0)]);
+        let mut param = vec![0.5, 0.5];
+        ik.solve(&tree, &mut param, &targets);
+        let result = fk.solve(&tree, &param);
+        assert!(distance(result[0], &elbow) < 0.1);
+        assert!(distance(result[1], &hand) > 5.0);
+    }
+
+    #[test]
+    fn test_solve_delta() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        let mut param = vec![0.3, 0.6];
+        let before = fk.solve(&tree, &param)[0].to_vec();
+
+        let delta = [0.5, -0.3, 0.0];
+        let result = ik.solve_delta(&tree, &mut param, &tip, &delta).unwrap();
+        assert_eq!(result.stop_reason, StopReason::MinError);
+
+        let after = fk.solve(&tree, &param)[0].to_vec();
+        izip!(&before, &after, &delta).for_each(|(b, a, d)| assert!((a - b - d).abs() < 1e-4));
+
+        assert!(matches!(
+            ik.solve_delta(&tree, &mut param, &"upper".to_string(), &delta),
+            Err(MannequinError::UnknownNode(_))
+        ));
+        assert!(matches!(
+            ik.solve_delta(&tree, &mut param, &tip, &[1.0]),
+            Err(MannequinError::DimensionMismatch(1))
+        ));
+    }
+
+    #[test]
+    fn test_solve_from() {
+        // Planar arm with shoulder, elbow and wrist
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let shoulder = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "shoulder".to_string());
+        let elbow = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, None),
+                "elbow".to_string(),
+                &shoulder,
+            )
+            .unwrap();
+        let hand = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "hand".to_string(),
+                &elbow,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        // Target reachable by the forearm alone (same shoulder angle)
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&hand]).unwrap();
+        let target = fk.solve(&tree, &[0.3, 0.4, -0.2])[0].to_vec();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        let mut param = vec![0.3, 0.0, 0.0];
+        let result = ik.solve_from(&tree, &mut param, &elbow, &hand, &target).unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::MinError);
+        // The shoulder is frozen, the forearm reaches the target (possibly bending the other way)
+        assert_eq!(param[0], 0.3);
+        izip!(fk.solve(&tree, &param)[0], &target).for_each(|(h, t)| assert!((h - t).abs() < 1e-4));
+
+        assert!(matches!(
+            ik.solve_from(&tree, &mut param, &"knee".to_string(), &hand, &target),
+            Err(MannequinError::UnknownNode(_))
+        ));
+        assert!(matches!(
+            ik.solve_from(&tree, &mut param, &elbow, &hand, &[1.0]),
+            Err(MannequinError::DimensionMismatch(1))
+        ));
+    }
+
+    #[test]
+    fn test_fit_frames() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+
+        // Synthetic markers of a known motion
+        let motion = (0..10)
+            .map(|frame| vec![0.3 + 0.05 * frame as f64, 0.6 - 0.04 * frame as f64])
+            .collect_vec();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let markers = motion
+            .iter()
+            .map(|params| fk.solve(&tree, params)[0].to_vec())
+            .collect_vec();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            50,
+            1e-12,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        let (fitted, infos) = fit_frames(&mut ik, &tree, &[0.2, 0.5], &markers);
+
+        assert_eq!(fitted.len(), motion.len());
+        izip!(&motion, &fitted, &infos).for_each(|(expected, actual, info)| {
+            assert!(info.squared_error < 1e-10);
+            izip!(expected, actual).for_each(|(e, a)| assert!((e - a).abs() < 1e-4));
+        });
+    }
+
+    #[test]
+    fn test_line_search() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        // Reachable (pose [0.2, 0.4])
+        let targets = [28.05407, 7.63313, 0.0];
+
+        // Doubling the Gauss-Newton step overshoots such that the solver oscillates around the target
+        let mut ik =
+            DifferentialInverseModel::new(42, 50, 1e-8, DifferentiableModel::new(), 2.0, UpdateRule::PseudoInverse);
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        let mut param = vec![0.5, 0.5];
+        let result = ik.solve(&tree, &mut param, &targets);
+        assert_eq!(result.stop_reason, StopReason::MaxIterations);
+
+        // Halving the step restores convergence
+        ik.set_line_search(Some(4));
+        let mut param = vec![0.5, 0.5];
+        let result = ik.solve(&tree, &mut param, &targets);
+        assert_eq!(result.stop_reason, StopReason::MinError);
+        assert!(result.iteration_count < 20);
+    }
+
+    #[test]
+    fn test_reached() {
+        // Planar arm with an additional effector at the elbow
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let root = tree.set_root(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "upper".to_string(),
+        );
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "lower".to_string(),
+            &root,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        // Pose [0.2, 0.4]
+        let targets = [19.80067, 1.98669, 0.0, 28.05407, 7.63313, 0.0];
+
+        // A loose squared error stops the solver early
+        let mut ik =
+            DifferentialInverseModel::new(42, 50, 0.5, DifferentiableModel::new(), 0.2, UpdateRule::PseudoInverse);
+        ik.setup(&tree, &[], &[&"upper".to_string(), &"lower".to_string()])
+            .unwrap();
+        let mut param = vec![0.5, 0.5];
+        let result = ik.solve(&tree, &mut param, &targets);
+
+        assert_eq!(result.stop_reason, StopReason::MinError);
+        assert_eq!(result.effector_residuals.len(), 2);
+        let squared = result.effector_residuals.iter().map(|r| r * r).sum::<f64>();
+        assert!((squared - result.squared_error).abs() < 1e-9);
+
+        // The aggregate is below 0.5 but the hand is still further away than that
+        assert!(result.squared_error < 0.5);
+        assert!(result.effector_residuals[0] < 0.5);
+        assert!(result.effector_residuals[1] > 0.5);
+        assert!(!result.reached(0.5));
+        assert!(result.reached(0.5_f64.sqrt()));
+    }
+
+    #[test]
+    fn test_dyn_inverse() {
+        type Tree = DepthFirstArenaTree<Segment, LinkNodeId>;
+
+        /// Trivial solver that returns to the neutral pose
+        struct Neutral;
+
+        impl Inverse<Tree, Segment> for Neutral {
+            type Info = ();
+
+            fn setup(
+                &mut self,
+                _: &Tree,
+                _: &[&LinkNodeId],
+                _: &[&LinkNodeId],
+            ) -> Result<(), MannequinError<LinkNodeId>> {
+                Ok(())
+            }
+
+            fn solve(&mut self, _: &Tree, param: &mut [f64], _: &[f64]) -> Self::Info {
+                param.iter_mut().for_each(|p| *p = 0.0);
+            }
+        }
+
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let targets = [28.05407, 7.63313, 0.0];
+
+        let mut solvers: Vec<Box<dyn DynInverse<Tree, Segment>>> = vec![
+            Box::new(DifferentialInverseModel::new(
+                42,
+                100,
+                1e-10,
+                DifferentiableModel::new(),
+                1.0,
+                UpdateRule::PseudoInverse,
+            )),
+            Box::new(Neutral),
+        ];
+        let results = solvers
+            .iter_mut()
+            .map(|solver| {
+                solver.setup_dyn(&tree, &[], &[&tip]).unwrap();
+                let mut param = vec![0.5, 0.5];
+                let info = solver.solve_dyn(&tree, &mut param, &targets);
+                (param, info)
+            })
+            .collect_vec();
+
+        let info = results[0].1.downcast_ref::<DiffIKInfo<f64>>().unwrap();
+        assert_eq!(info.stop_reason, StopReason::MinError);
+        izip!(&results[0].0, [0.2, 0.4]).for_each(|(actual, expected)| assert!((actual - expected).abs() < 1e-4));
+
+        assert!(results[1].1.downcast_ref::<()>().is_some());
+        assert_eq!(results[1].0, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_effector_task() {
+        /// Keeps the effectors above the plane `y = height` (inactive above)
+        struct AbovePlane {
+            height: f64,
+        }
+
+        impl EffectorTask<f64> for AbovePlane {
+            fn residual(&self, _: &[f64], effectors: &[f64], residual: &mut [f64]) {
+                residual.fill(0.0);
+                residual
+                    .chunks_mut(3)
+                    .zip(effectors.chunks(3))
+                    .for_each(|(r, e)| r[1] = (self.height - e[1]).max(0.0));
+            }
+
+            fn jacobian(&self, _: &[f64], effectors: &[f64], jacobian: &mut [f64], rows: usize) {
+                // Only the y rows of the violated constraints remain
+                jacobian.chunks_mut(rows).for_each(|col| {
+                    col.chunks_mut(3).zip(effectors.chunks(3)).for_each(|(j, e)| {
+                        j[0] = 0.0;
+                        j[2] = 0.0;
+                        if e[1] >= self.height {
+                            j[1] = 0.0;
+                        }
+                    })
+                });
+            }
+        }
+
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.set_task(AbovePlane { height: 5.0 });
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        // The tip starts below the plane and is lifted onto it
+        let mut param = vec![0.0, 0.0];
+        let result = ik.solve(&tree, &mut param, &[0.0; 3]);
+        assert_eq!(result.stop_reason, StopReason::MinError);
+        assert!((fk.solve(&tree, &param)[0][1] - 5.0).abs() < 1e-4);
+
+        // Above the plane, nothing changes
+        let mut param = vec![0.5, 0.0];
+        let result = ik.solve(&tree, &mut param, &[0.0; 3]);
+        assert_eq!(result.iteration_count, 0);
+        assert_eq!(param, vec![0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_limiting_joint() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let root = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "upper".to_string());
+        let tip = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "lower".to_string(),
+                &root,
+            )
+            .unwrap();
+        // The elbow cannot bend beyond straight
+        tree.set_metadata(&tip, crate::pose::LIMIT_MAX, "0.0").unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        // Reaching pose [0.2, 0.4] requires bending the clamped elbow
+        let targets = [28.05407, 7.63313, 0.0];
+        assert_eq!(ik.limiting_joint(&tree, &[0.3, 0.0], &targets), Some(tip.clone()));
+
+        // Away from the limit, the joint contributing least is reported. The shoulder (at [10, 0, 0]) cannot
+        // move the hand away from itself
+        let param = [0.0, -0.5];
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let hand = fk.solve(&tree, &param)[0].to_vec();
+        let targets = izip!(&hand, [10.0, 0.0, 0.0])
+            .map(|(h, s)| h + 0.01 * (h - s))
+            .collect_vec();
+        assert_eq!(ik.limiting_joint(&tree, &param, &targets), Some("upper".to_string()));
+    }
+
+    #[test]
+    fn test_task_error() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+        let targets = fk.solve(&tree, &[0.2, 0.4])[0].to_vec();
+
+        assert!(ik.task_error(&tree, &[0.2, 0.4], &targets) < 1e-20);
+        // The neutral pose reaches [30, 0, 0]
+        let expected = (30.0 - targets[0]).powi(2) + targets[1].powi(2);
+        assert!((ik.task_error(&tree, &[0.0, 0.0], &targets) - expected).abs() < 1e-9);
+
+        // The solver is not affected and computes the same error
+        assert!(ik.error_history().is_empty());
+        let error = ik.task_error(&tree, &[0.5, 0.5], &targets);
+        ik.solve(&tree, &mut [0.5, 0.5], &targets);
+        assert_eq!(ik.error_history()[0], error);
+    }
+
+    #[test]
+    fn test_timing() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let targets = [28.05407, 7.63313, 0.0];
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        // Not measured by default
+        let result = ik.solve(&tree, &mut [0.5, 0.5], &targets);
+        assert!(result.timing.is_none());
+
+        ik.set_timing(true);
+        let result = ik.solve(&tree, &mut [0.5, 0.5], &targets);
+        let timing = result.timing.unwrap();
+        assert!(timing.compute > Duration::ZERO && timing.solve_linear > Duration::ZERO);
+        // The remainder is spent in bookkeeping (e.g., computing the residual and logging)
+        assert!(timing.compute + timing.solve_linear <= timing.total);
+    }
+
+    #[test]
+    fn test_clamp_to_reach() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&tip]).unwrap();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+        ik.set_clamp_to_reach(true);
+
+        // The shoulder is at [10, 0, 0] and the arm is 20 long: the closest point to the target is [22, 16, 0]
+        let targets = [40.0, 40.0, 0.0];
+        let mut param = vec![0.3, 0.6];
+        let result = ik.solve(&tree, &mut param, &targets);
+        assert_eq!(result.stop_reason, StopReason::MinError);
+        let hand = fk.solve(&tree, &param)[0].to_vec();
+        izip!(&hand, [22.0, 16.0, 0.0]).for_each(|(h, e)| assert!((h - e).abs() < 1e-3));
+
+        // Reachable targets are not changed
+        let targets = fk.solve(&tree, &[0.2, 0.4])[0].to_vec();
+        let mut param = vec![0.3, 0.6];
+        ik.solve(&tree, &mut param, &targets);
+        izip!(fk.solve(&tree, &param)[0], &targets).for_each(|(h, t)| assert!((h - t).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_reset_diagnostics() {
+        let tree = planar_arm();
+        let tip = "lower".to_string();
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&tip]).unwrap();
+
+        let mut param = vec![0.5, 0.5];
+        let first = ik.solve(&tree, &mut param, &[28.05407, 7.63313, 0.0]);
+        assert_eq!(ik.error_history().len(), first.iteration_count + 1);
+
+        // Already at the target: a single iteration
+        let targets = [20.0, 10.0, 0.0];
+        let mut param = vec![0.0, std::f64::consts::FRAC_PI_2];
+        let second = ik.solve(&tree, &mut param, &targets);
+        assert_eq!(second.iteration_count, 0);
+        assert_eq!(ik.error_history(), &[second.squared_error]);
+        assert_eq!(ik.residual().len(), 3);
+        assert!(ik.residual().iter().all(|r| r.abs() < 1e-9));
+
+        ik.reset_diagnostics();
+        assert!(ik.error_history().is_empty() && ik.residual().is_empty());
+    }
 }
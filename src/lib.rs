@@ -28,17 +28,25 @@ pub mod errors;
 pub mod forward;
 pub mod inverse;
 pub mod mannequin;
+pub mod pose;
+pub mod simulation;
+pub mod trajectory;
 
 pub use arena::{
-    BreadthFirstIterable, DepthFirstArenaTree, DepthFirstIterable, DirectedArenaTree, DirectionIterable, NodeLike,
+    BreadthFirstArenaTree, BreadthFirstIterable, DepthFirstArenaTree, DepthFirstIterable, DirectedArenaTree,
+    DirectionIterable, NodeLike,
 };
-pub use differentiable::{Differentiable, DifferentiableModel};
+pub use differentiable::{Differentiable, DifferentiableModel, FiniteDifferenceModel};
 pub use errors::MannequinError;
-pub use forward::{Forward, ForwardModel};
-pub use inverse::{DifferentialInverseModel, Inverse};
-pub use mannequin::{Mannequin, Rigid};
+pub use forward::{CachedForwardModel, Forward, ForwardModel};
+pub use inverse::{DifferentialInverseModel, DynInverse, Inverse, UpdateRule};
+pub use mannequin::{Mannequin, MaybeSendSync, Rigid};
 // Backends
 #[cfg(feature = "faer")]
 pub mod faer;
+#[cfg(feature = "glam")]
+pub mod glam;
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra;
 #[cfg(feature = "ndarray")]
 pub mod ndarray;
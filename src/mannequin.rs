@@ -4,23 +4,36 @@
  * realistic joints, muscle simulation, or classical inverse kinematics and obstacle avoidance.
  */
 
-use num_traits::Float;
+use num_traits::{Float, Zero};
 
-use crate::{DepthFirstIterable, Forward, Inverse};
+use crate::{DepthFirstIterable, Forward, Inverse, MannequinError};
 use std::{fmt::Debug, hash::Hash, marker::PhantomData};
 
+/// Requires `Send + Sync` with the `rayon` feature such that computations (e.g., the columns of the Jacobian)
+/// can be distributed among threads. Without the feature, the trait is implemented for all types.
+#[cfg(feature = "rayon")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+/// Requires `Send + Sync` with the `rayon` feature such that computations (e.g., the columns of the Jacobian)
+/// can be distributed among threads. Without the feature, the trait is implemented for all types.
+#[cfg(not(feature = "rayon"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "rayon"))]
+impl<T: ?Sized> MaybeSendSync for T {}
+
 /// A Rigid Body represents a single, rigid link connected to other links via a joint.
 /// Synonyms: Bone
 ///
 /// Wraps all linear algebra transformations such that backends
 /// only need to implement this trait.
-pub trait Rigid: PartialEq {
+pub trait Rigid: PartialEq + MaybeSendSync {
     /// E.g., 4x4 matrix, (3x1, 3x3), quaternions ...
-    type Transformation: Clone + Debug;
+    type Transformation: Clone + Debug + MaybeSendSync;
     /// Vec, \[f64;4\], ...
     type Point;
 
-    type FloatType: Float;
+    type FloatType: Float + MaybeSendSync;
 
     // TODO Explain why this is defined on Rigid (the node) and not Mannequin (the tree) .. in short: otherwise this would be another generic and mannequin.rs would be unreadable because of trait bounds. This way it is quite elegant
     type NodeId: Eq + Hash + Clone + Debug;
@@ -32,6 +45,18 @@ pub trait Rigid: PartialEq {
     /// shoulder joint).
     fn transform(&self, params: &[Self::FloatType], index: usize) -> Self::Transformation;
 
+    /// Number of parameters consumed by [Rigid::transform] starting at `index` (e.g., 3 for a ball joint).
+    /// The parameters of all nodes are stored consecutively in depth-first order.
+    fn param_count(&self) -> usize {
+        1
+    }
+
+    /// Whether the parameters are angles (e.g., of revolute joints) such that they are compared on the circle
+    /// (see [crate::pose::pose_distance]).
+    fn is_revolute(&self) -> bool {
+        false
+    }
+
     /// Transform a point into the world coordinate system
     fn globalize(&self, other: &Self::Point) -> Self::Point;
 
@@ -58,6 +83,23 @@ pub trait Rigid: PartialEq {
         offset: usize,
     );
 
+    /// Same as [Rigid::partial_derivative] but with respect to the parameter `dof` of a `joint` that consumes
+    /// multiple parameters (see [Rigid::param_count]), whose axes generally depend on the `joint_params`. Unlike
+    /// in [Rigid::partial_derivative], the results are written to the start of `target_buffer`. The default
+    /// implementation is only valid for joints with a single parameter.
+    fn partial_derivative_dof(
+        &self,
+        pose: &Self::Transformation,
+        joint: &Self,
+        joint_pose: &Self::Transformation,
+        joint_params: &[Self::FloatType],
+        dof: usize,
+        target_buffer: &mut [Self::FloatType],
+    ) {
+        debug_assert!(dof == 0 && joint_params.len() <= 1);
+        self.partial_derivative(pose, joint, joint_pose, target_buffer, 0);
+    }
+
     fn effector(&self, pose: &Self::Transformation, target_buffer: &mut [Self::FloatType], offset: usize);
 
     /// number of effectors
@@ -78,6 +120,22 @@ pub trait Rigid: PartialEq {
     /// Concat two transformations
     fn concat(first: &Self::Transformation, second: &Self::Transformation) -> Self::Transformation;
 
+    /// Position (in 3d) of the origin of a transformation, e.g., the pivot of a joint given its world pose. Used
+    /// for geometric reasoning such as the reach of a chain. `None` (the default) if not supported by the backend.
+    fn origin(_trafo: &Self::Transformation) -> Option<[Self::FloatType; 3]> {
+        None
+    }
+
+    /// Shortest-path rotation from the orientation of `current` to the one of `target` as a rotation vector (axis
+    /// times angle, i.e., the logarithm of `target·currentᵀ` in so(3)), given in the world frame. Translations are
+    /// ignored. Serves as the residual of orientation tasks.
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point;
+
+    /// Shortest-path rotation from the orientation `current` to `target`, both given as rotation vectors (e.g., the
+    /// orientation of pose effectors), as a rotation vector (see [Rigid::orientation_error]). Serves as the residual
+    /// of [crate::inverse::PoseTask].
+    fn rotation_vector_difference(current: &[Self::FloatType], target: &[Self::FloatType]) -> [Self::FloatType; 3];
+
     /// Solve system of linear equations, and *update* (additive) the parameters.
     ///
     /// If the feature `faer` is enabled, a pure-rust implementation is
@@ -86,13 +144,57 @@ pub trait Rigid: PartialEq {
     /// [Blas/Lapack]() is available on the system. Note that adding a
     /// default implementation based on faer would have introduced too much
     /// additional code complexity.
+    ///
+    /// The `damping` (Levenberg-Marquardt parameter) is added to the diagonal of the normal equations: small values
+    /// yield accurate but possibly huge updates near singular configurations, large values yield smaller, more
+    /// conservative updates (see [crate::DifferentialInverseModel::set_damping]).
+    ///
+    /// The update is scaled down such that its (L2) norm does not exceed `limit`, e.g., to stay in the range where
+    /// the kinematics are near linear (see [crate::DifferentialInverseModel::set_step_limit]). Infinity disables
+    /// the limit.
     fn solve_linear(
         matrix: &[Self::FloatType],
         rows: usize,
         cols: usize,
         vector: &[Self::FloatType],
+        damping: Self::FloatType,
+        limit: Self::FloatType,
         parameters: &mut [Self::FloatType],
     );
+
+    /// Computes `J⁺ e` for the column-major `matrix` `J` and `vector` `e` and writes it to `result` (length `cols`).
+    /// The pseudo-inverse is not formed explicitly but a system of linear equations of the smaller dimension is solved.
+    /// Unlike [Rigid::solve_linear], the result is neither limited nor accumulated, which allows sharing this
+    /// implementation, e.g., in velocity control or null-space projections.
+    fn apply_pseudo_inverse(
+        matrix: &[Self::FloatType],
+        rows: usize,
+        cols: usize,
+        vector: &[Self::FloatType],
+        result: &mut [Self::FloatType],
+    );
+
+    /// Computes `Jᵀ v` for the column-major `matrix` `J` (`rows` x `cols`) and `vector` `v` (length `rows`), and
+    /// writes it to `result` (length `cols`), e.g., the gradient of the squared residual. Backends can override the
+    /// generic implementation with an optimized one.
+    fn apply_transpose(
+        matrix: &[Self::FloatType],
+        rows: usize,
+        cols: usize,
+        vector: &[Self::FloatType],
+        result: &mut [Self::FloatType],
+    ) {
+        debug_assert_eq!(matrix.len(), rows * cols);
+        debug_assert_eq!(vector.len(), rows);
+        debug_assert_eq!(result.len(), cols);
+        // Each column is contiguous and contributes one entry
+        result.iter_mut().zip(matrix.chunks(rows.max(1))).for_each(|(r, col)| {
+            *r = col
+                .iter()
+                .zip(vector)
+                .fold(Self::FloatType::zero(), |sum, (j, v)| sum + *j * *v)
+        });
+    }
 }
 
 /// Struct for holding the composition of character animation algorithms in a flat architecture for
@@ -127,20 +229,144 @@ where
         }
     }
 
-    /// Forward kinematics for the targets in `target_refs` and the joint positions in `param`.
-    #[allow(unused_variables)]
-    pub fn forward(
+    /// Forward kinematics for the joint positions in `param`, i.e., the (flat) effectors selected with
+    /// [Self::setup]. Unlike [Forward::solve], which borrows from the model, the effectors are
+    /// copied such that the mannequin can be used (e.g., for the inverse kinematics) while they are kept.
+    pub fn forward(&mut self, param: &[RB::FloatType]) -> Vec<Vec<RB::FloatType>> {
+        self.fk
+            .solve(&self.tree, param)
+            .into_iter()
+            .map(|effector| effector.to_vec())
+            .collect()
+    }
+
+    /// Select the joints that can be moved by the inverse kinematics (all if empty), and the effectors computed by
+    /// both the forward and inverse kinematics. Has to be called before [Self::forward] and [Self::inverse].
+    /// Fails with [MannequinError::UnknownNode] if a selected joint or effector is not in the tree.
+    pub fn setup(
         &mut self,
-        _param: &[RB::FloatType], /*, target_refs: &[RB::NodeId]*/
-    ) -> Vec<RB::Transformation> {
-        // self.fk.solve(&self.tree, param)
-        todo!()
+        selected_joints: &[&RB::NodeId],
+        selected_effectors: &[&RB::NodeId],
+    ) -> Result<(), MannequinError<RB::NodeId>> {
+        self.fk.setup(&self.tree, selected_effectors)?;
+        self.ik.setup(&self.tree, selected_joints, selected_effectors)
+    }
+
+    /// Inverse kinematics for the desired working space coordinates in `target_val` (one point per effector
+    /// selected with [Self::setup]) starting from (and updating) the joint positions in `param`.
+    pub fn inverse(&mut self, param: &mut [RB::FloatType], target_val: &[RB::Point]) -> IK::Info
+    where
+        for<'a> &'a RB::Point: IntoIterator<Item = &'a RB::FloatType>,
+    {
+        let targets = target_val.iter().flatten().copied().collect::<Vec<_>>();
+        self.ik.solve(&self.tree, param, &targets)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndarray::robot::{Axis, LinkNodeId, Segment};
+    use crate::{
+        DepthFirstArenaTree, DifferentiableModel, DifferentialInverseModel, DirectedArenaTree, DirectionIterable,
+        ForwardModel, UpdateRule,
+    };
+    use itertools::izip;
+    use ndarray::prelude::*;
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_forward() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let root = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "upper".to_string());
+        let tip = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "lower".to_string(),
+                &root,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-10,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        let mut mannequin = Mannequin::new(tree, ForwardModel::new(DifferentiableModel::new()), ik);
+        mannequin.setup(&[], &[&tip]).unwrap();
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&mannequin.tree, &[&tip]).unwrap();
+        let param = [0.3, -0.7];
+        let expected = fk.solve(&mannequin.tree, &param);
+
+        let effectors = mannequin.forward(&param);
+        assert_eq!(effectors, expected);
+        assert_eq!(effectors[0].len(), 3);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_inverse() {
+        // Same problem as in the inverse kinematics tests (two branches with an effector each)
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let ref1 = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "link1".to_string());
+        let ref2 = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "link2".to_string(),
+                &ref1,
+            )
+            .unwrap();
+        let ref3 = tree
+            .add(Segment::new(&trafo, Axis::RotationZ, None), "link3".to_string(), &ref1)
+            .unwrap();
+        let ref4 = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+                "link4".to_string(),
+                &ref3,
+            )
+            .unwrap();
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "link5".to_string(),
+            &ref4,
+        )
+        .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let n_iterations = 13;
+        let ik = DifferentialInverseModel::new(
+            42,
+            n_iterations,
+            0.01,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        let mut mannequin = Mannequin::new(tree, ForwardModel::new(DifferentiableModel::new()), ik);
+        mannequin.setup(&[&ref1, &ref2, &ref3, &ref4], &[&ref2, &ref4]).unwrap();
+
+        let mut param = vec![0.0, 0.0, std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2, 0.0];
+        let targets = [array![20.0, 0.0, 0.0], array![20.0, 10.0, 0.0]];
+        let info = mannequin.inverse(&mut param, &targets);
 
-    /// Inverse kinematics for the targets in `target_refs` and the desired working space coordinates in `target_val`.
-    #[allow(unused_variables)]
-    pub fn inverse(&mut self, _param: &mut [RB::FloatType], _target_val: &[RB::Point]) -> IK::Info {
-        // self.ik.solve(&self.tree, param, target_val)
-        todo!()
+        assert!(info.iteration_count <= n_iterations);
+        assert!(info.squared_error < 1e-2);
+        // The forward kinematics agree with the solution
+        let effectors = mannequin.forward(&param);
+        let error = izip!(effectors.iter().flatten(), targets.iter().flatten())
+            .map(|(e, t)| (e - t) * (e - t))
+            .sum::<f64>();
+        assert!(error < 1e-2, "{error}");
     }
 }
@@ -0,0 +1,500 @@
+//! Module for the implementations using the nalgebra backend. Mirrors the [Segment](crate::ndarray::robot::Segment)
+//! of the ndarray backend with statically sized, homogeneous 4x4 matrices.
+use crate::Rigid;
+use core::fmt;
+use nalgebra::{DMatrix, DVector, Matrix3, Matrix4, Rotation3, Unit, Vector3, Vector4};
+
+/// Creates a homogeneous, 4x4 rotation matrix around the x axis.
+pub fn rotate_x_4x4(param: f64) -> Matrix4<f64> {
+    Rotation3::from_axis_angle(&Vector3::x_axis(), param).to_homogeneous()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the y axis.
+pub fn rotate_y_4x4(param: f64) -> Matrix4<f64> {
+    Rotation3::from_axis_angle(&Vector3::y_axis(), param).to_homogeneous()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around the z axis.
+pub fn rotate_z_4x4(param: f64) -> Matrix4<f64> {
+    Rotation3::from_axis_angle(&Vector3::z_axis(), param).to_homogeneous()
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around an arbitrary `axis` (normalized internally). A zero angle
+/// yields the identity for any axis.
+///
+/// # Panics
+/// If `axis` is zero (for a non-zero angle).
+pub fn rotate_axis_4x4(axis: &Vector3<f64>, param: f64) -> Matrix4<f64> {
+    if param == 0.0 {
+        return Matrix4::identity();
+    }
+    let axis = Unit::try_new(*axis, 0.0).expect("Rotation axis must not be zero");
+    Rotation3::from_axis_angle(&axis, param).to_homogeneous()
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the x axis.
+pub fn translate_x_4x4(param: f64) -> Matrix4<f64> {
+    Matrix4::new_translation(&Vector3::new(param, 0.0, 0.0))
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the y axis.
+pub fn translate_y_4x4(param: f64) -> Matrix4<f64> {
+    Matrix4::new_translation(&Vector3::new(0.0, param, 0.0))
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along the z axis.
+pub fn translate_z_4x4(param: f64) -> Matrix4<f64> {
+    Matrix4::new_translation(&Vector3::new(0.0, 0.0, param))
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along an arbitrary `direction` (normalized internally) by the
+/// distance `param`.
+///
+/// # Panics
+/// If `direction` is zero (for a non-zero distance).
+pub fn translate_axis_4x4(direction: &Vector3<f64>, param: f64) -> Matrix4<f64> {
+    if param == 0.0 {
+        return Matrix4::identity();
+    }
+    let direction = Unit::try_new(*direction, 0.0).expect("Translation axis must not be zero");
+    Matrix4::new_translation(&(direction.into_inner() * param))
+}
+
+/// inverts a homogeneous, 4x4 transformation matrix.
+pub fn invert_transformation_4x4(trafo: &Matrix4<f64>) -> Matrix4<f64> {
+    let rotation = trafo.fixed_view::<3, 3>(0, 0).transpose();
+    let position = -(rotation * trafo.fixed_view::<3, 1>(0, 3));
+    let mut result = Matrix4::identity();
+    result.fixed_view_mut::<3, 3>(0, 0).copy_from(&rotation);
+    result.fixed_view_mut::<3, 1>(0, 3).copy_from(&position);
+    result
+}
+
+/// Converts a 3x3 rotation matrix into a rotation vector (axis times angle in `[0, π]`), i.e., the
+/// logarithmic map of SO(3).
+pub fn rotation_vector(rotation: &Matrix3<f64>) -> Vector3<f64> {
+    Rotation3::from_matrix_unchecked(*rotation).scaled_axis()
+}
+
+/// Shortest-path rotation (as rotation vector) from the orientation of the homogeneous transformation `current`
+/// to the one of `target`, i.e., the rotation vector of `target·currentᵀ`.
+pub fn orientation_error(current: &Matrix4<f64>, target: &Matrix4<f64>) -> Vector3<f64> {
+    rotation_vector(&(target.fixed_view::<3, 3>(0, 0) * current.fixed_view::<3, 3>(0, 0).transpose()))
+}
+
+/// Solves the damped normal equations `(JᵀJ + λI) x = Jᵀe` for the column-major `matrix` `J` with an LU
+/// decomposition. The update is scaled down such that its norm does not exceed `limit`.
+pub fn solve_linear(
+    matrix: &[f64],
+    rows: usize,
+    cols: usize,
+    vector: &[f64],
+    damping: f64,
+    limit: f64,
+    parameters: &mut [f64],
+) {
+    let matrix = DMatrix::from_column_slice(rows, cols, matrix);
+    let vector = DVector::from_column_slice(vector);
+    let normal = matrix.tr_mul(&matrix) + DMatrix::identity(cols, cols) * damping;
+    let update = normal
+        .lu()
+        .solve(&matrix.tr_mul(&vector))
+        .expect("Cannot solve equations");
+    let norm = update.norm();
+    let update = if norm > limit { update * (limit / norm) } else { update };
+    parameters.copy_from_slice(update.as_slice());
+}
+
+/// Computes `J⁺ e` by solving the normal equations of the smaller dimension: `(JᵀJ) x = Jᵀe` if the system is
+/// over-determined, and `x = Jᵀ y` with `(JJᵀ) y = e` otherwise. A tiny regularization keeps singular
+/// configurations solvable.
+pub fn apply_pseudo_inverse(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], result: &mut [f64]) {
+    let matrix = DMatrix::from_column_slice(rows, cols, matrix);
+    let vector = DVector::from_column_slice(vector);
+    let solution = if rows >= cols {
+        let normal = matrix.tr_mul(&matrix) + DMatrix::identity(cols, cols) * 1e-12;
+        normal.lu().solve(&matrix.tr_mul(&vector))
+    } else {
+        let normal = &matrix * matrix.transpose() + DMatrix::identity(rows, rows) * 1e-12;
+        normal
+            .lu()
+            .solve(&vector)
+            .map(|multipliers| matrix.tr_mul(&multipliers))
+    };
+    result.copy_from_slice(solution.expect("Cannot solve equations").as_slice());
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Axis {
+    RotationX,
+    RotationY,
+    #[default]
+    RotationZ,
+    Rotation(Vector3<f64>),
+    TranslationX,
+    TranslationY,
+    TranslationZ,
+    Translation(Vector3<f64>),
+    /// Rigid connection without a degree of freedom (consumes no parameter)
+    Fixed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Mode {
+    /// Position of the effector (3 values)
+    #[default]
+    Position,
+    /// Position and orientation as rotation vector (6 values)
+    Pose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    link: Matrix4<f64>,
+    axis: Axis,
+    mode: Mode,
+    effector_local: Option<Matrix4<f64>>,
+}
+
+impl Segment {
+    pub fn new(from_parent: &Matrix4<f64>, axis: Axis, effector: Option<Matrix4<f64>>) -> Self {
+        Self {
+            link: *from_parent,
+            axis,
+            mode: Mode::Position,
+            effector_local: effector,
+        }
+    }
+
+    /// Selects what the effector represents (and thus its dimension, see [Mode]).
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bone, link: {}, Axis: {:?}", self.link, self.axis)
+    }
+}
+
+impl Rigid for Segment {
+    type Transformation = Matrix4<f64>;
+
+    type Point = Vector4<f64>;
+
+    type FloatType = f64;
+
+    type NodeId = String;
+
+    fn transform(&self, params: &[f64], index: usize) -> Self::Transformation {
+        let joint = match &self.axis {
+            Axis::RotationX => rotate_x_4x4(params[index]),
+            Axis::RotationY => rotate_y_4x4(params[index]),
+            Axis::RotationZ => rotate_z_4x4(params[index]),
+            Axis::Rotation(axis) => rotate_axis_4x4(axis, params[index]),
+            Axis::TranslationX => translate_x_4x4(params[index]),
+            Axis::TranslationY => translate_y_4x4(params[index]),
+            Axis::TranslationZ => translate_z_4x4(params[index]),
+            Axis::Translation(axis) => translate_axis_4x4(axis, params[index]),
+            Axis::Fixed => Matrix4::identity(),
+        };
+        self.link * joint
+    }
+
+    fn param_count(&self) -> usize {
+        if self.axis == Axis::Fixed {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_revolute(&self) -> bool {
+        matches!(
+            self.axis,
+            Axis::RotationX | Axis::RotationY | Axis::RotationZ | Axis::Rotation(_)
+        )
+    }
+
+    fn globalize(&self, other: &Self::Point) -> Self::Point {
+        self.link * other
+    }
+
+    fn localize(&self, other: &Self::Point) -> Self::Point {
+        invert_transformation_4x4(&self.link) * other
+    }
+
+    fn neutral_element() -> Self::Transformation {
+        Matrix4::identity()
+    }
+
+    fn concat(first: &Self::Transformation, second: &Self::Transformation) -> Self::Transformation {
+        first * second
+    }
+
+    fn invert(trafo: &Self::Transformation) -> Self::Transformation {
+        invert_transformation_4x4(trafo)
+    }
+
+    fn origin(trafo: &Self::Transformation) -> Option<[f64; 3]> {
+        Some([trafo[(0, 3)], trafo[(1, 3)], trafo[(2, 3)]])
+    }
+
+    /// The rotation vector as a direction (i.e., the homogeneous coordinate is zero)
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point {
+        orientation_error(current, target).to_homogeneous()
+    }
+
+    fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+        let rotation = |vector: &[f64]| Rotation3::new(Vector3::from_column_slice(vector)).to_homogeneous();
+        orientation_error(&rotation(current), &rotation(target)).into()
+    }
+
+    fn dim(&self) -> usize {
+        match self.mode {
+            Mode::Position => 3,
+            Mode::Pose => 6,
+        }
+    }
+
+    fn effector_count(&self) -> usize {
+        if self.effector_local.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn partial_derivative(
+        &self,
+        pose: &Self::Transformation,
+        joint: &Self,
+        joint_pose: &Self::Transformation,
+        buffer: &mut [f64],
+        offset: usize,
+    ) {
+        // Formula: axis_in_world x (end_effector_world - pivod_in_world) for revolute joints and
+        // axis_in_world for prismatic joints
+        let local_axis = match &joint.axis {
+            Axis::RotationX | Axis::TranslationX => Vector3::x(),
+            Axis::RotationY | Axis::TranslationY => Vector3::y(),
+            Axis::RotationZ | Axis::TranslationZ => Vector3::z(),
+            Axis::Rotation(axis) | Axis::Translation(axis) => axis.normalize(),
+            Axis::Fixed => Vector3::zeros(),
+        };
+        let axis_global = joint_pose.fixed_view::<3, 3>(0, 0) * local_axis;
+
+        let pose = match &self.effector_local {
+            Some(effector) => pose * effector,
+            None => *pose,
+        };
+        let lever = pose.fixed_view::<3, 1>(0, 3) - joint_pose.fixed_view::<3, 1>(0, 3);
+
+        // Linear and angular velocity of the effector
+        let (velocity, angular) = if joint.is_revolute() {
+            (axis_global.cross(&lever), axis_global)
+        } else {
+            (axis_global, Vector3::zeros())
+        };
+
+        let target = &mut buffer[offset..offset + self.effector_size()];
+        target[0..3].copy_from_slice(velocity.as_slice());
+        if self.mode == Mode::Pose {
+            // The angular velocity caused by a revolute joint is its axis
+            target[3..6].copy_from_slice(angular.as_slice());
+        }
+    }
+
+    /// Get the coordinates of the effector in the global (or an arbitrary) system.
+    fn effector(&self, pose: &Self::Transformation, buffer: &mut [f64], offset: usize) {
+        let target = &mut buffer[offset..offset + self.effector_size()];
+
+        if let Some(effector) = &self.effector_local {
+            let frame = pose * effector;
+            target[0..3].copy_from_slice(frame.fixed_view::<3, 1>(0, 3).clone_owned().as_slice());
+            if self.mode == Mode::Pose {
+                let rotation = rotation_vector(&frame.fixed_view::<3, 3>(0, 0).clone_owned());
+                target[3..6].copy_from_slice(rotation.as_slice());
+            }
+        } else {
+            panic!("Should not call this method if no effector is defined")
+        }
+    }
+
+    fn solve_linear(
+        matrix: &[f64],
+        rows: usize,
+        cols: usize,
+        vector: &[f64],
+        damping: f64,
+        limit: f64,
+        parameters: &mut [f64],
+    ) {
+        solve_linear(matrix, rows, cols, vector, damping, limit, parameters);
+    }
+
+    fn apply_pseudo_inverse(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], result: &mut [f64]) {
+        apply_pseudo_inverse(matrix, rows, cols, vector, result);
+    }
+}
+
+pub type LinkNodeId = <Segment as Rigid>::NodeId;
+
+#[cfg(test)]
+mod tests {
+
+    // The trees and expectations are the ones of the tests with the `ndarray` backend (the reference
+    // implementation), which is also used for comparison.
+
+    use super::*;
+    use crate::{
+        differentiable::ComputeSelection, DepthFirstArenaTree, Differentiable, DifferentiableModel,
+        DifferentialInverseModel, DirectedArenaTree, DirectionIterable, Forward, ForwardModel, Inverse, UpdateRule,
+    };
+    use approx::assert_abs_diff_eq;
+    use itertools::Itertools;
+
+    /// The five-link tree of the ndarray tests. Links 2, 4 and 5 have an effector.
+    fn sample_tree() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let trafo = translate_x_4x4(10.0);
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let ref1 = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "link1".to_string());
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo)),
+            "link2".to_string(),
+            &ref1,
+        )
+        .unwrap();
+        let ref3 = tree
+            .add(Segment::new(&trafo, Axis::RotationZ, None), "link3".to_string(), &ref1)
+            .unwrap();
+        let ref4 = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, Some(trafo)),
+                "link4".to_string(),
+                &ref3,
+            )
+            .unwrap();
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo)),
+            "link5".to_string(),
+            &ref4,
+        )
+        .unwrap();
+        tree.into()
+    }
+
+    /// The same tree with the ndarray backend
+    fn reference_tree() -> DepthFirstArenaTree<crate::ndarray::robot::Segment, LinkNodeId> {
+        use crate::ndarray::robot::{Axis, Segment};
+
+        let trafo = crate::ndarray::translate_x_4x4(10.0);
+        let segment = |effector: bool| Segment::new(&trafo, Axis::RotationZ, effector.then(|| trafo.clone()));
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let ref1 = tree.set_root(segment(false), "link1".to_string());
+        tree.add(segment(true), "link2".to_string(), &ref1).unwrap();
+        let ref3 = tree.add(segment(false), "link3".to_string(), &ref1).unwrap();
+        let ref4 = tree.add(segment(true), "link4".to_string(), &ref3).unwrap();
+        tree.add(segment(true), "link5".to_string(), &ref4).unwrap();
+        tree.into()
+    }
+
+    fn ids(names: &[&str]) -> Vec<LinkNodeId> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_fk() {
+        let tree = sample_tree();
+        let effectors = ids(&["link2", "link4", "link5"]);
+        let effectors = effectors.iter().collect_vec();
+        let params = [0.0, 0.0, std::f64::consts::FRAC_PI_2, 0.0, 0.3];
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors).unwrap();
+        let result = fk.solve(&tree, &params).concat();
+        assert_abs_diff_eq!(&result[..6], &[30.0, 0.0, 0.0, 20.0, 20.0, 0.0][..], epsilon = 1e-12);
+
+        let reference_tree = reference_tree();
+        let mut reference = ForwardModel::new(DifferentiableModel::new());
+        reference.setup(&reference_tree, &effectors).unwrap();
+        let expected = reference.solve(&reference_tree, &params).concat();
+        assert_abs_diff_eq!(&result[..], &expected[..], epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let tree = sample_tree();
+        let joints = ids(&["link1", "link2", "link3", "link4"]);
+        let effectors = ids(&["link2", "link4"]);
+        let (joints, effectors) = (joints.iter().collect_vec(), effectors.iter().collect_vec());
+        let params = [0.0, 0.0, std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2, 0.0];
+
+        let mut jacobian = DifferentiableModel::<f64>::new();
+        jacobian.setup(&tree, &joints, &effectors).unwrap();
+        jacobian.compute(&tree, &params, ComputeSelection::JacobianOnly);
+        assert_eq!(jacobian.shape(), (6, 4));
+
+        // Column-major
+        let target = [
+            [0.0, 20.0, 0.0, -10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, -10.0, -10.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0, -10.0, 0.0],
+        ]
+        .concat();
+        assert_abs_diff_eq!(jacobian.jacobian(), &target[..], epsilon = 1e-6);
+
+        let reference_tree = reference_tree();
+        let mut reference = DifferentiableModel::<f64>::new();
+        reference.setup(&reference_tree, &joints, &effectors).unwrap();
+        reference.compute(&reference_tree, &params, ComputeSelection::JacobianOnly);
+        assert_abs_diff_eq!(jacobian.jacobian(), reference.jacobian(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_ik() {
+        let joints = ids(&["link1", "link2", "link3", "link4"]);
+        let effectors = ids(&["link2", "link4"]);
+        let (joints, effectors) = (joints.iter().collect_vec(), effectors.iter().collect_vec());
+        let targets = [20.0, 0.0, 0.0, 20.0, 10.0, 0.0];
+        let start = [0.0, 0.0, std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2, 0.0];
+        let new_ik = || {
+            DifferentialInverseModel::new(
+                42,
+                100,
+                1e-14,
+                DifferentiableModel::new(),
+                1.0,
+                UpdateRule::PseudoInverse,
+            )
+        };
+
+        let tree = sample_tree();
+        let mut ik = new_ik();
+        ik.setup(&tree, &joints, &effectors).unwrap();
+        let mut params = start;
+        let result = ik.solve(&tree, &mut params, &targets);
+        assert!(result.squared_error < 1e-12, "{result:?}");
+
+        // The joints are redundant (and the ndarray backend might limit the steps), so the effectors are compared
+        let reference_tree = reference_tree();
+        let mut reference = new_ik();
+        reference.setup(&reference_tree, &joints, &effectors).unwrap();
+        let mut reference_params = start;
+        reference.solve(&reference_tree, &mut reference_params, &targets);
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &effectors).unwrap();
+        let mut reference_fk = ForwardModel::new(DifferentiableModel::new());
+        reference_fk.setup(&reference_tree, &effectors).unwrap();
+        let result = fk.solve(&tree, &params).concat();
+        assert_abs_diff_eq!(&result[..], &targets[..], epsilon = 1e-6);
+        assert_abs_diff_eq!(
+            &result[..],
+            &reference_fk.solve(&reference_tree, &reference_params).concat()[..],
+            epsilon = 1e-6
+        );
+    }
+}
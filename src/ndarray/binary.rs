@@ -0,0 +1,150 @@
+//! Compact binary format for rigs made of [Segment]s (feature `binary`). Intended for loading large rigs
+//! (e.g., crowds with thousands of nodes) quickly in production, whereas a text format (e.g., JSON through the serde
+//! support of [DepthFirstArenaTree]) is preferable for tooling.
+//!
+//! The data starts with [MAGIC] and the [FORMAT_VERSION] (little-endian) such that files written by an incompatible
+//! version are detected instead of being misinterpreted. The tree follows, serialized with
+//! [postcard](https://docs.rs/postcard) in the same layout as the text format.
+
+use super::robot::{LinkNodeId, Segment};
+use crate::{DepthFirstArenaTree, MannequinError};
+
+/// Identifies data written by [to_binary].
+pub const MAGIC: [u8; 4] = *b"MNQN";
+/// Version of the binary layout. Increased whenever the layout changes; [from_binary] rejects other versions.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Serializes `tree` into the binary format (see the [module documentation](self)).
+pub fn to_binary(tree: &DepthFirstArenaTree<Segment, LinkNodeId>) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    postcard::to_extend(tree, header).expect("Serializing into memory does not fail")
+}
+
+/// Deserializes a tree written by [to_binary]. Fails with [MannequinError::UnsupportedVersion] for data of
+/// another format version and with [MannequinError::InvalidBinary] for corrupted data.
+pub fn from_binary(bytes: &[u8]) -> Result<DepthFirstArenaTree<Segment, LinkNodeId>, MannequinError<LinkNodeId>> {
+    let invalid = |message: String| MannequinError::InvalidBinary(message);
+    let data = bytes
+        .strip_prefix(&MAGIC)
+        .ok_or_else(|| invalid("not a mannequin rig".to_string()))?;
+    if data.len() < 2 {
+        return Err(invalid("unexpected end of data".to_string()));
+    }
+    let (version, payload) = data.split_at(2);
+    let version = u16::from_le_bytes([version[0], version[1]]);
+    if version != FORMAT_VERSION {
+        return Err(MannequinError::UnsupportedVersion(version));
+    }
+
+    let (tree, rest) = postcard::take_from_bytes(payload).map_err(|error| invalid(error.to_string()))?;
+    if !rest.is_empty() {
+        return Err(invalid("trailing data".to_string()));
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::iterables::{BaseDirectionIterable, OptimizedDirectionIterable};
+    use crate::ndarray::robot::{Axis, Mode};
+    use crate::ndarray::{rotate_z_4x4, translate_x_4x4};
+    use crate::{DirectedArenaTree, DirectionIterable, NodeLike};
+    use itertools::Itertools;
+    use ndarray::{array, Array2};
+
+    /// A crowd of `characters` identical rigs of 50 segments each attached to a common root.
+    fn crowd(characters: usize) -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let root = tree.set_root(Segment::new(&Array2::eye(4), Axis::Fixed, None), "world".to_string());
+        (0..characters).for_each(|character| {
+            let pelvis = tree
+                .add(
+                    Segment::new(&translate_x_4x4(character as f64 * 1.37), Axis::Fixed, None),
+                    format!("{character}/pelvis"),
+                    &root,
+                )
+                .unwrap();
+            // Five limbs of ten segments with full-precision offsets
+            (0..5).for_each(|limb| {
+                let mut parent = pelvis.clone();
+                (0..10).for_each(|index| {
+                    let angle = 0.1 * (limb * 10 + index) as f64 + 0.123456789;
+                    let link = rotate_z_4x4(angle).dot(&translate_x_4x4(0.3 + angle / 7.0));
+                    let axis = match index % 4 {
+                        0 => Axis::RotationX,
+                        1 => Axis::RotationY,
+                        2 => Axis::Rotation(array![0.0, 0.6, 0.8]),
+                        _ => Axis::TranslationZ,
+                    };
+                    let effector = (index == 9).then(|| translate_x_4x4(0.1));
+                    let mut segment = Segment::with_inertia(
+                        &link,
+                        axis,
+                        effector,
+                        1.0 + angle,
+                        array![angle / 3.0, 0.0, 0.0],
+                        Array2::from_diag(&array![0.01, 0.02, angle / 100.0]),
+                    );
+                    if index == 9 {
+                        segment.set_mode(Mode::LookAt {
+                            forward: array![1.0, 0.0, 0.0],
+                            point: array![0.0, angle, 2.0],
+                        });
+                    }
+                    let id = format!("{character}/{limb}/{index}");
+                    parent = tree.add(segment, id, &parent).unwrap();
+                    tree.set_metadata(&parent, crate::pose::LIMIT_MIN, &(-angle).to_string())
+                        .unwrap();
+                    tree.set_metadata(&parent, crate::pose::LIMIT_MAX, &angle.to_string())
+                        .unwrap();
+                });
+            });
+        });
+        tree.into()
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let tree = crowd(40);
+        assert_eq!(tree.len(), 2041);
+
+        let bytes = to_binary(&tree);
+        let loaded = from_binary(&bytes).unwrap();
+
+        assert_eq!(loaded.len(), tree.len());
+        tree.iter().zip(loaded.iter()).for_each(|(expected, actual)| {
+            assert_eq!(expected.id(), actual.id());
+            assert_eq!(expected.depth(), actual.depth());
+            assert_eq!(expected.get(), actual.get());
+            assert_eq!(
+                expected.metadata_entries().sorted().collect_vec(),
+                actual.metadata_entries().sorted().collect_vec()
+            );
+        });
+        // The output is deterministic
+        assert_eq!(to_binary(&loaded), bytes);
+
+        // Incompatible or corrupted data is detected
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(matches!(
+            from_binary(&newer),
+            Err(MannequinError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            from_binary(&bytes[..bytes.len() - 1]),
+            Err(MannequinError::InvalidBinary(_))
+        ));
+        assert!(matches!(
+            from_binary(b"{\"nodes\": []}"),
+            Err(MannequinError::InvalidBinary(_))
+        ));
+
+        // Compared to the JSON serialization, the binary format is considerably smaller (see the benchmark for
+        // the loading times)
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(bytes.len() * 3 < json.len() * 2, "{} vs. {}", bytes.len(), json.len());
+    }
+}
@@ -0,0 +1,578 @@
+//! Rigid body dynamics for the ndarray backend.
+//!
+//! Spatial quantities (motion subspaces, inertias) are expressed in the world frame with the angular part
+//! first (Featherstone's notation). Working in world coordinates avoids the transformations between
+//! the segment frames and keeps the recursions over the depth-first layout simple.
+//!
+//! The parameters, velocities, accelerations and torques have one entry per parameter (see [param_offsets]).
+//! Segments without parameters (fixed joints) move rigidly with their parent.
+
+use super::{
+    robot::{LinkNodeId, Segment},
+    skew_3x3,
+};
+use crate::{
+    differentiable::{param_offsets, parameter_count},
+    forward::TransformationAccumulation,
+    DepthFirstIterable, MannequinError, NodeLike, Rigid,
+};
+use ndarray::prelude::*;
+
+/// Index of the parent of every node in depth-first order (`None` for the root).
+fn parents<T>(tree: &T) -> Vec<Option<usize>>
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    let mut stack = Vec::<usize>::new();
+    tree.iter()
+        .enumerate()
+        .map(|(index, node)| {
+            stack.truncate(node.depth());
+            let parent = stack.last().copied();
+            stack.push(index);
+            parent
+        })
+        .collect()
+}
+
+/// Index of the parameter of every node in depth-first order (`None` for a node without parameters).
+fn parameters<T>(tree: &T) -> Vec<Option<usize>>
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    tree.iter()
+        .zip(param_offsets(tree))
+        .map(|(node, offset)| (node.get().param_count() > 0).then_some(offset))
+        .collect()
+}
+
+/// Spatial cross product operator `v×` for motion vectors (6x6, angular part first).
+fn cross_motion_6x6(velocity: &Array1<f64>) -> Array2<f64> {
+    let angular = skew_3x3(velocity.slice(s![..3]));
+    let mut result = Array2::<f64>::zeros((6, 6));
+    result.slice_mut(s![..3, ..3]).assign(&angular);
+    result.slice_mut(s![3.., 3..]).assign(&angular);
+    result
+        .slice_mut(s![3.., ..3])
+        .assign(&skew_3x3(velocity.slice(s![3..])));
+    result
+}
+
+/// Spatial cross product operator `v×*` for force vectors (the negative transpose of `v×`).
+fn cross_force_6x6(velocity: &Array1<f64>) -> Array2<f64> {
+    -cross_motion_6x6(velocity).t().to_owned()
+}
+
+/// Sum of the masses of all segments in `tree` (see [Segment::with_inertia]).
+pub fn total_mass<T>(tree: &T) -> f64
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    tree.iter().map(|node| node.get().inertia().0).sum()
+}
+
+/// Whole-body center of mass (world frame) of `tree` in the configuration `params`, i.e., the mass-weighted
+/// average of the centers of mass of the segments. Fails with [MannequinError::ZeroMass] if no segment has a mass.
+pub fn center_of_mass<T>(tree: &T, params: &[f64]) -> Result<Array1<f64>, MannequinError<LinkNodeId>>
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    let total = total_mass(tree);
+    if total == 0.0 {
+        return Err(MannequinError::ZeroMass);
+    }
+    let weighted = tree
+        .iter()
+        .accumulate(params, tree.len())
+        .fold(Array1::<f64>::zeros(3), |sum, (node, pose)| {
+            let (mass, com, _) = node.get().inertia();
+            let com = &pose.slice(s![..3, 3]) + &pose.slice(s![..3, ..3]).dot(com);
+            sum + mass * com
+        });
+    Ok(weighted / total)
+}
+
+/// Computes the joint-space inertia matrix `M(q)` with the composite-rigid-body algorithm.
+///
+/// The inertia of each subtree is accumulated in an inward pass, the entries of the matrix are then
+/// given by projecting the composite inertias onto the motion subspaces of a joint and its ancestors.
+pub fn mass_matrix<T>(tree: &T, params: &[f64]) -> Array2<f64>
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    debug_assert_eq!(params.len(), parameter_count(tree));
+
+    let parents = parents(tree);
+    let parameters = parameters(tree);
+    let (subspaces, mut composites): (Vec<_>, Vec<_>) = tree
+        .iter()
+        .accumulate(params, tree.len())
+        .map(|(node, pose)| (node.get().motion_subspace(&pose), node.get().spatial_inertia(&pose)))
+        .unzip();
+
+    // Inward pass: children come after their parents in depth-first order
+    (1..parents.len()).rev().for_each(|index| {
+        if let Some(parent) = parents[index] {
+            let composite = composites[index].clone();
+            composites[parent] += &composite;
+        }
+    });
+
+    let mut result = Array2::<f64>::zeros((params.len(), params.len()));
+    composites.iter().enumerate().for_each(|(index, composite)| {
+        let Some(row) = parameters[index] else {
+            return;
+        };
+        let force = composite.dot(&subspaces[index]);
+        result[[row, row]] = subspaces[index].dot(&force);
+
+        let mut ancestor = parents[index];
+        while let Some(other) = ancestor {
+            if let Some(column) = parameters[other] {
+                let value = subspaces[other].dot(&force);
+                result[[row, column]] = value;
+                result[[column, row]] = value;
+            }
+            ancestor = parents[other];
+        }
+    });
+    result
+}
+
+/// Computes the joint accelerations caused by the joint `torques` at the current configuration (`params`,
+/// `velocities`) under `gravity` (e.g., `[0.0, -9.81, 0.0]`) using the articulated-body algorithm.
+///
+/// Three passes are made over the depth-first layout: velocities and bias terms outwards, articulated
+/// inertias inwards, and finally accelerations outwards. Gravity is modelled as an acceleration of the base.
+/// Segments without parameters are merged into the articulated inertia of their parent. Fails with
+/// [MannequinError::SingularInertia] if a joint moves no mass (e.g., a massless leaf), since its acceleration
+/// is undetermined.
+pub fn forward_dynamics<T>(
+    tree: &T,
+    params: &[f64],
+    velocities: &[f64],
+    torques: &[f64],
+    gravity: &[f64],
+) -> Result<Array1<f64>, MannequinError<LinkNodeId>>
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    debug_assert_eq!(params.len(), parameter_count(tree));
+    debug_assert_eq!(velocities.len(), params.len());
+    debug_assert_eq!(torques.len(), params.len());
+
+    let parents = parents(tree);
+    let parameters = parameters(tree);
+    let (subspaces, mut inertias): (Vec<_>, Vec<_>) = tree
+        .iter()
+        .accumulate(params, tree.len())
+        .map(|(node, pose)| (node.get().motion_subspace(&pose), node.get().spatial_inertia(&pose)))
+        .unzip();
+
+    // Outward pass: spatial velocities, velocity-product accelerations and bias forces
+    let mut spatial_velocities = Vec::<Array1<f64>>::with_capacity(parents.len());
+    let mut biases = Vec::<Array1<f64>>::with_capacity(parents.len());
+    let mut forces = Vec::<Array1<f64>>::with_capacity(parents.len());
+    (0..parents.len()).for_each(|index| {
+        let joint_velocity = &subspaces[index] * parameters[index].map_or(0.0, |parameter| velocities[parameter]);
+        let velocity = match parents[index] {
+            Some(parent) => &spatial_velocities[parent] + &joint_velocity,
+            None => joint_velocity.clone(),
+        };
+        biases.push(cross_motion_6x6(&velocity).dot(&joint_velocity));
+        forces.push(cross_force_6x6(&velocity).dot(&inertias[index].dot(&velocity)));
+        spatial_velocities.push(velocity);
+    });
+
+    // Inward pass: articulated-body inertias and bias forces
+    let mut projected = vec![Array1::<f64>::zeros(6); parents.len()];
+    let mut divisors = vec![0.0; parents.len()];
+    let mut residuals = vec![0.0; parents.len()];
+    for index in (0..parents.len()).rev() {
+        let (articulated, force) = match parameters[index] {
+            // Rigidly attached to the parent
+            None => (inertias[index].clone(), forces[index].clone()),
+            Some(parameter) => {
+                let projection = inertias[index].dot(&subspaces[index]);
+                divisors[index] = subspaces[index].dot(&projection);
+                if divisors[index].abs() <= f64::EPSILON {
+                    let node = tree.iter().nth(index).unwrap();
+                    return Err(MannequinError::SingularInertia(node.id().clone()));
+                }
+                residuals[index] = torques[parameter] - subspaces[index].dot(&forces[index]);
+
+                let column = projection.view().insert_axis(Axis(1));
+                let articulated = &inertias[index] - &(&column.dot(&column.t()) / divisors[index]);
+                let force = &forces[index]
+                    + &articulated.dot(&biases[index])
+                    + &projection * (residuals[index] / divisors[index]);
+                projected[index] = projection;
+                (articulated, force)
+            }
+        };
+        if let Some(parent) = parents[index] {
+            inertias[parent] += &articulated;
+            forces[parent] += &force;
+        }
+    }
+
+    // Outward pass: accelerations
+    let mut base = Array1::<f64>::zeros(6);
+    base.slice_mut(s![3..]).assign(&(-&ArrayView1::from(gravity)));
+
+    let mut accelerations = Vec::<Array1<f64>>::with_capacity(parents.len());
+    let mut result = Array1::<f64>::zeros(params.len());
+    (0..parents.len()).for_each(|index| {
+        let parent_acceleration = parents[index].map_or(&base, |parent| &accelerations[parent]);
+        let acceleration = parent_acceleration + &biases[index];
+        match parameters[index] {
+            Some(parameter) => {
+                result[parameter] = (residuals[index] - projected[index].dot(&acceleration)) / divisors[index];
+                accelerations.push(acceleration + &subspaces[index] * result[parameter]);
+            }
+            None => accelerations.push(acceleration),
+        }
+    });
+    Ok(result)
+}
+
+/// Inverse dynamics with the recursive Newton-Euler algorithm, i.e., the joint torques that cause given joint
+/// accelerations (e.g., recorded motion) at the current configuration under gravity.
+#[derive(Debug, Clone)]
+pub struct InverseDynamics {
+    /// Acceleration due to gravity (world frame)
+    gravity: Array1<f64>,
+}
+
+impl Default for InverseDynamics {
+    fn default() -> Self {
+        Self {
+            gravity: Array1::zeros(3),
+        }
+    }
+}
+
+impl InverseDynamics {
+    /// Computes the torques under `gravity` (e.g., `[0.0, -9.81, 0.0]`).
+    pub fn new(gravity: &[f64]) -> Self {
+        let mut result = Self::default();
+        result.set_gravity(gravity);
+        result
+    }
+
+    pub fn set_gravity(&mut self, gravity: &[f64]) {
+        debug_assert_eq!(gravity.len(), 3);
+        self.gravity = Array1::from(gravity.to_vec());
+    }
+
+    /// Computes the joint torques for the joint `accelerations` at the current configuration (`params`,
+    /// `velocities`).
+    ///
+    /// Two passes are made over the depth-first layout: spatial velocities, accelerations and the forces
+    /// required to cause them outwards, and the sum of the forces of each subtree inwards, which is projected
+    /// onto the motion subspace of its joint. Gravity is modelled as an acceleration of the base.
+    pub fn solve<T>(&self, tree: &T, params: &[f64], velocities: &[f64], accelerations: &[f64]) -> Array1<f64>
+    where
+        T: DepthFirstIterable<Segment, LinkNodeId>,
+    {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        debug_assert_eq!(velocities.len(), params.len());
+        debug_assert_eq!(accelerations.len(), params.len());
+
+        let parents = parents(tree);
+        let parameters = parameters(tree);
+        let (subspaces, inertias): (Vec<_>, Vec<_>) = tree
+            .iter()
+            .accumulate(params, tree.len())
+            .map(|(node, pose)| (node.get().motion_subspace(&pose), node.get().spatial_inertia(&pose)))
+            .unzip();
+        // The motion subspace of a node without parameters is zero
+        let value = |values: &[f64], index: usize| parameters[index].map_or(0.0, |parameter| values[parameter]);
+
+        let mut base = Array1::<f64>::zeros(6);
+        base.slice_mut(s![3..]).assign(&(-&self.gravity));
+
+        // Outward pass: spatial velocities, accelerations and the forces causing them
+        let mut spatial_velocities = Vec::<Array1<f64>>::with_capacity(parents.len());
+        let mut spatial_accelerations = Vec::<Array1<f64>>::with_capacity(parents.len());
+        let mut forces = Vec::<Array1<f64>>::with_capacity(parents.len());
+        (0..parents.len()).for_each(|index| {
+            let joint_velocity = &subspaces[index] * value(velocities, index);
+            let (velocity, acceleration) = match parents[index] {
+                Some(parent) => (
+                    &spatial_velocities[parent] + &joint_velocity,
+                    spatial_accelerations[parent].clone(),
+                ),
+                None => (joint_velocity.clone(), base.clone()),
+            };
+            let acceleration = acceleration
+                + &subspaces[index] * value(accelerations, index)
+                + cross_motion_6x6(&velocity).dot(&joint_velocity);
+            let momentum = inertias[index].dot(&velocity);
+            forces.push(inertias[index].dot(&acceleration) + cross_force_6x6(&velocity).dot(&momentum));
+            spatial_velocities.push(velocity);
+            spatial_accelerations.push(acceleration);
+        });
+
+        // Inward pass: forces of the subtrees and their projection onto the joints
+        let mut result = Array1::<f64>::zeros(params.len());
+        (0..parents.len()).rev().for_each(|index| {
+            if let Some(parameter) = parameters[index] {
+                result[parameter] = subspaces[index].dot(&forces[index]);
+            }
+            if let Some(parent) = parents[index] {
+                let force = forces[index].clone();
+                forces[parent] += &force;
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndarray::robot::Axis;
+    use crate::{
+        arena::iterables::OptimizedDirectionIterable, DepthFirstArenaTree, DirectedArenaTree, DirectionIterable, Rigid,
+    };
+    use approx::assert_abs_diff_eq;
+
+    const LENGTH: f64 = 2.0;
+
+    /// Planar double pendulum rotating about z. Each link has its center of mass at `lc` along x.
+    fn double_pendulum(masses: [f64; 2], lc: [f64; 2], izz: [f64; 2]) -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+
+        let mut link = Segment::neutral_element();
+        let upper = Segment::with_inertia(
+            &link,
+            Axis::RotationZ,
+            None,
+            masses[0],
+            array![lc[0], 0.0, 0.0],
+            Array2::from_diag(&array![0.3, 0.4, izz[0]]),
+        );
+        let root = tree.set_root(upper, "upper".to_string());
+
+        link.slice_mut(s![..3, 3]).assign(&array![LENGTH, 0.0, 0.0]);
+        let lower = Segment::with_inertia(
+            &link,
+            Axis::RotationZ,
+            None,
+            masses[1],
+            array![lc[1], 0.0, 0.0],
+            Array2::from_diag(&array![0.2, 0.1, izz[1]]),
+        );
+        tree.add(lower, "lower".to_string(), &root).unwrap();
+        tree.into()
+    }
+
+    /// The [double_pendulum] with the translation of the lower link moved into a fixed (massless) segment and a
+    /// fixed, massless hand at the tip. Describes the same system.
+    fn double_pendulum_with_fixed(
+        masses: [f64; 2],
+        lc: [f64; 2],
+        izz: [f64; 2],
+    ) -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let inertia = |index: usize, diagonal: [f64; 2]| {
+            Segment::with_inertia(
+                &Segment::neutral_element(),
+                Axis::RotationZ,
+                None,
+                masses[index],
+                array![lc[index], 0.0, 0.0],
+                Array2::from_diag(&array![diagonal[0], diagonal[1], izz[index]]),
+            )
+        };
+        let root = tree.set_root(inertia(0, [0.3, 0.4]), "upper".to_string());
+
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![LENGTH, 0.0, 0.0]);
+        let elbow = tree
+            .add(Segment::new(&link, Axis::Fixed, None), "elbow".to_string(), &root)
+            .unwrap();
+        let lower = tree.add(inertia(1, [0.2, 0.1]), "lower".to_string(), &elbow).unwrap();
+        tree.add(Segment::new(&link, Axis::Fixed, None), "hand".to_string(), &lower)
+            .unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_total_mass() {
+        let tree = double_pendulum([3.0, 2.0], [1.0, 0.8], [0.5, 0.25]);
+        assert_abs_diff_eq!(total_mass(&tree), 5.0);
+
+        let (mass, com, inertia) = tree.iter().last().unwrap().get().inertia();
+        assert_eq!(mass, 2.0);
+        assert_eq!(com, array![0.8, 0.0, 0.0]);
+        assert_eq!(inertia[(2, 2)], 0.25);
+
+        // Massless segments (e.g., without inertial properties) do not contribute
+        let mut chain = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let root = chain.set_root(
+            Segment::new(&Segment::neutral_element(), Axis::RotationZ, None),
+            "a".to_string(),
+        );
+        chain
+            .add(
+                Segment::with_inertia(
+                    &Segment::neutral_element(),
+                    Axis::RotationZ,
+                    None,
+                    1.5,
+                    Array1::zeros(3),
+                    Array2::eye(3),
+                ),
+                "b".to_string(),
+                &root,
+            )
+            .unwrap();
+        let chain: DepthFirstArenaTree<_, _> = chain.into();
+        assert_abs_diff_eq!(total_mass(&chain), 1.5);
+    }
+
+    #[test]
+    fn test_center_of_mass() {
+        // Upper: 3 kg at (1, 0, 0). Lower: 1 kg starting at (2, 0, 0) with its center of mass 0.8 along
+        // its (rotated) x axis
+        let tree = double_pendulum([3.0, 1.0], [1.0, 0.8], [0.5, 0.25]);
+
+        let result = center_of_mass(&tree, &[0.0, 0.0]).unwrap();
+        assert_abs_diff_eq!(result, array![(3.0 * 1.0 + 2.8) / 4.0, 0.0, 0.0], epsilon = 1e-12);
+        let result = center_of_mass(&tree, &[0.0, std::f64::consts::FRAC_PI_2]).unwrap();
+        assert_abs_diff_eq!(result, array![(3.0 * 1.0 + 2.0) / 4.0, 0.8 / 4.0, 0.0], epsilon = 1e-12);
+
+        // Equal masses: the midpoint between both centers of mass
+        let tree = double_pendulum([2.0, 2.0], [1.0, 1.0], [0.5, 0.25]);
+        let result = center_of_mass(&tree, &[0.0, 0.0]).unwrap();
+        assert_abs_diff_eq!(result, array![2.0, 0.0, 0.0], epsilon = 1e-12);
+
+        let massless = double_pendulum([0.0, 0.0], [1.0, 0.8], [0.0, 0.0]);
+        assert!(matches!(
+            center_of_mass(&massless, &[0.0, 0.0]),
+            Err(MannequinError::ZeroMass)
+        ));
+    }
+
+    #[test]
+    fn test_inverse_dynamics() {
+        let (mass, lc) = (2.0, 0.75);
+        let gravity = 9.81;
+
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        tree.set_root(
+            Segment::with_inertia(
+                &Segment::neutral_element(),
+                Axis::RotationZ,
+                None,
+                mass,
+                array![lc, 0.0, 0.0],
+                Array2::from_diag(&array![0.3, 0.4, 0.1]),
+            ),
+            "pendulum".to_string(),
+        );
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+
+        // Hanging along the gravity (x axis) at rest, the angle is measured from the vertical
+        let dynamics = InverseDynamics::new(&[gravity, 0.0, 0.0]);
+        [0.0, 0.4, -1.2, 2.5].iter().for_each(|angle| {
+            let result = dynamics.solve(&tree, &[*angle], &[0.0], &[0.0]);
+            assert_abs_diff_eq!(result[0], mass * gravity * lc * angle.sin(), epsilon = 1e-9);
+        });
+
+        // Reproduces the torques given to the forward dynamics
+        let tree = double_pendulum([3.0, 2.0], [1.0, 0.8], [0.5, 0.25]);
+        let (params, velocities, torques) = ([0.3, 0.7], [1.3, -0.4], [1.0, -0.5]);
+        let gravity = [0.0, -9.81, 0.0];
+        let accelerations = forward_dynamics(&tree, &params, &velocities, &torques, &gravity).unwrap();
+        let result =
+            InverseDynamics::new(&gravity).solve(&tree, &params, &velocities, accelerations.as_slice().unwrap());
+        assert_abs_diff_eq!(result, array![1.0, -0.5], epsilon = 1e-9);
+
+        // Fixed segments have no torque
+        let fixed = double_pendulum_with_fixed([3.0, 2.0], [1.0, 0.8], [0.5, 0.25]);
+        let result =
+            InverseDynamics::new(&gravity).solve(&fixed, &params, &velocities, accelerations.as_slice().unwrap());
+        assert_abs_diff_eq!(result, array![1.0, -0.5], epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_mass_matrix() {
+        let (m1, m2) = (3.0, 2.0);
+        let (lc1, lc2) = (1.0, 0.8);
+        let (i1, i2) = (0.5, 0.25);
+        let tree = double_pendulum([m1, m2], [lc1, lc2], [i1, i2]);
+
+        let params = [0.3, 0.7];
+        let result = mass_matrix(&tree, &params);
+
+        // Textbook inertia matrix of the planar double pendulum
+        let cos = params[1].cos();
+        let m11 = i1 + i2 + m1 * lc1 * lc1 + m2 * (LENGTH * LENGTH + lc2 * lc2 + 2.0 * LENGTH * lc2 * cos);
+        let m12 = i2 + m2 * (lc2 * lc2 + LENGTH * lc2 * cos);
+        let m22 = i2 + m2 * lc2 * lc2;
+
+        assert_abs_diff_eq!(result, array![[m11, m12], [m12, m22]], epsilon = 1e-9);
+
+        // Independent of the absolute orientation of the chain
+        let rotated = mass_matrix(&tree, &[1.5, 0.7]);
+        assert_abs_diff_eq!(result, rotated, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_forward_dynamics() {
+        let (mass, lc, izz) = (2.0, 0.75, 0.1);
+        let gravity = [0.0, -9.81, 0.0];
+
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        tree.set_root(
+            Segment::with_inertia(
+                &Segment::neutral_element(),
+                Axis::RotationZ,
+                None,
+                mass,
+                array![lc, 0.0, 0.0],
+                Array2::from_diag(&array![0.3, 0.4, izz]),
+            ),
+            "pendulum".to_string(),
+        );
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+
+        // Pendulum equation (angle measured from the horizontal): (Izz + m·l²)·θ̈ = -m·g·l·cos(θ)
+        [0.0, 0.4, -1.2, 2.5].iter().for_each(|angle| {
+            let result = forward_dynamics(&tree, &[*angle], &[1.3], &[0.0], &gravity).unwrap();
+            let expected = -mass * 9.81 * lc * angle.cos() / (izz + mass * lc * lc);
+            assert_abs_diff_eq!(result[0], expected, epsilon = 1e-9);
+        });
+
+        // Without velocity and gravity, the accelerations are consistent with the inertia matrix: M·q̈ = τ
+        let tree = double_pendulum([3.0, 2.0], [1.0, 0.8], [0.5, 0.25]);
+        let (params, torques) = ([0.3, 0.7], array![1.0, -0.5]);
+        let result = forward_dynamics(&tree, &params, &[0.0, 0.0], torques.as_slice().unwrap(), &[0.0; 3]).unwrap();
+        assert_abs_diff_eq!(mass_matrix(&tree, &params).dot(&result), torques, epsilon = 1e-9);
+
+        // Fixed segments are merged into their parent
+        let fixed = double_pendulum_with_fixed([3.0, 2.0], [1.0, 0.8], [0.5, 0.25]);
+        assert_abs_diff_eq!(
+            mass_matrix(&fixed, &params),
+            mass_matrix(&tree, &params),
+            epsilon = 1e-9
+        );
+        let (velocities, gravity) = ([1.3, -0.4], [0.0, -9.81, 0.0]);
+        let torques = [1.0, -0.5];
+        assert_abs_diff_eq!(
+            forward_dynamics(&fixed, &params, &velocities, &torques, &gravity).unwrap(),
+            forward_dynamics(&tree, &params, &velocities, &torques, &gravity).unwrap(),
+            epsilon = 1e-9
+        );
+
+        // A joint that moves no mass has an undetermined acceleration
+        let massless = double_pendulum([3.0, 0.0], [1.0, 0.8], [0.5, 0.0]);
+        assert!(matches!(
+            forward_dynamics(&massless, &params, &velocities, &torques, &gravity),
+            Err(MannequinError::SingularInertia(id)) if id == "lower"
+        ));
+    }
+}
@@ -0,0 +1,429 @@
+//! FABRIK (forward and backward reaching inverse kinematics) for the ndarray backend.
+//!
+//! Instead of linearizing the forward kinematics, FABRIK moves the positions of the joints of a single chain:
+//! the chain is dragged onto the target starting at the effector (backward) and then re-attached to its base
+//! (forward), keeping the lengths of the links in both passes. The joint parameters are recovered by rotating
+//! each joint such that the next point of the chain points towards its new position. The solver is fast and
+//! stable but limited to the position of a single effector.
+//!
+//! The points of the chain are the revolute joints. Fixed joints are merged into the links, and prismatic joints
+//! are not supported (rejected by [Fabrik]'s setup) because they change the lengths of the links.
+//!
+//! See Aristidou & Lasenby, "FABRIK: A fast, iterative solver for the Inverse Kinematics problem" (2011).
+
+use super::{
+    cross_3d,
+    robot::{Axis, LinkNodeId, Segment},
+    rotate_axis_4x4,
+};
+use crate::{
+    differentiable::parameter_count, forward::TransformationAccumulation, DepthFirstIterable, Inverse, MannequinError,
+    NodeLike, Rigid,
+};
+use itertools::Itertools;
+use ndarray::{array, s, Array1, Array2, ArrayView1};
+
+/// Information about the solution of [Fabrik]
+#[derive(Debug, Clone)]
+pub struct FabrikInfo {
+    /// Number of required passes
+    pub iteration_count: usize,
+    /// The final distance of the effector to the target
+    pub distance: f64,
+    /// Whether the target is within the length of the chain. Otherwise, the chain is fully extended towards it.
+    pub reachable: bool,
+}
+
+/// FABRIK solver for a chain of revolute joints from the first selected joint (or the root) to a single
+/// effector. Fixed joints in the chain keep their (empty) parameters.
+pub struct Fabrik {
+    max_iterations_count: usize,
+    tolerance: f64,
+    /// Depth-first indices of the movable joints of the chain (from the base to the effector)
+    chain: Vec<usize>,
+    /// Rotation axis (unit length, local) of each joint in the chain
+    axes: Vec<Array1<f64>>,
+    /// Index of the first parameter of each joint in the chain
+    offsets: Vec<usize>,
+    /// Distance from each joint of the chain to the next one (the last one to the effector)
+    lengths: Vec<f64>,
+    /// Transformation from the last joint of the chain to the effector
+    effector: Array2<f64>,
+}
+
+impl Fabrik {
+    /// Stops after `max_iterations_count` passes or if the effector is closer than `tolerance` to the target.
+    pub fn new(max_iterations_count: usize, tolerance: f64) -> Self {
+        Self {
+            max_iterations_count,
+            tolerance,
+            chain: vec![],
+            axes: vec![],
+            offsets: vec![],
+            lengths: vec![],
+            effector: Segment::neutral_element(),
+        }
+    }
+
+    /// Positions of the joints of the chain followed by the position of the effector, and the world poses of the
+    /// joints.
+    fn points<T>(&self, tree: &T, params: &[f64]) -> (Vec<Array1<f64>>, Vec<Array2<f64>>)
+    where
+        T: DepthFirstIterable<Segment, LinkNodeId>,
+    {
+        let poses = tree.iter().accumulate(params, 42).map(|(_, pose)| pose).collect_vec();
+        let poses = self.chain.iter().map(|index| poses[*index].clone()).collect_vec();
+        (self.positions(&poses), poses)
+    }
+
+    /// Positions of the joints with the world `poses` followed by the position of the effector
+    fn positions(&self, poses: &[Array2<f64>]) -> Vec<Array1<f64>> {
+        let mut points = poses.iter().map(|pose| pose.slice(s![..3, 3]).to_owned()).collect_vec();
+        if let Some(last) = poses.last() {
+            points.push(last.dot(&self.effector).slice(s![..3, 3]).to_owned());
+        }
+        points
+    }
+}
+
+/// One pass of FABRIK moving the `points` of a chain with the given link `lengths` towards `target`. If the
+/// target is out of reach, the chain is fully extended towards it instead.
+fn reach(points: &mut [Array1<f64>], lengths: &[f64], target: ArrayView1<f64>) {
+    debug_assert_eq!(points.len(), lengths.len() + 1);
+    let base = points[0].clone();
+    // Position at `distance` from `from` towards `to`
+    let towards = |from: &Array1<f64>, to: &Array1<f64>, distance: f64| {
+        let direction = to - from;
+        let norm = length(direction.view());
+        if norm > 0.0 {
+            from + &(direction * (distance / norm))
+        } else {
+            from.clone()
+        }
+    };
+
+    if length((&target - &base).view()) > lengths.iter().sum::<f64>() {
+        for (index, distance) in lengths.iter().enumerate() {
+            points[index + 1] = towards(&points[index], &target.to_owned(), *distance);
+        }
+        return;
+    }
+
+    // Backward: from the effector (on the target) to the base
+    let last = points.len() - 1;
+    points[last] = target.to_owned();
+    for index in (0..last).rev() {
+        points[index] = towards(&points[index + 1], &points[index], lengths[index]);
+    }
+    // Forward: re-attach the base
+    points[0] = base;
+    for index in 0..last {
+        points[index + 1] = towards(&points[index], &points[index + 1], lengths[index]);
+    }
+}
+
+/// Rotation angle about `axis` (unit length) through `pivot` that turns `current` towards `desired`
+fn angle_about(axis: &Array1<f64>, pivot: ArrayView1<f64>, current: &Array1<f64>, desired: &Array1<f64>) -> f64 {
+    let project = |point: &Array1<f64>| {
+        let lever = point - &pivot;
+        &lever - &(axis * axis.dot(&lever))
+    };
+    let (from, to) = (project(current), project(desired));
+    let mut cross = Array1::<f64>::zeros(3);
+    cross_3d::<LinkNodeId>(from.view(), to.view(), cross.view_mut()).unwrap();
+    axis.dot(&cross).atan2(from.dot(&to))
+}
+
+/// Euclidean norm of a vector
+fn length(vector: ArrayView1<f64>) -> f64 {
+    vector.dot(&vector).sqrt()
+}
+
+/// Rigid transformation from `pose` to `next`, i.e., `pose⁻¹ · next`
+fn relative(pose: &Array2<f64>, next: &Array2<f64>) -> Array2<f64> {
+    let rotation = pose.slice(s![..3, ..3]).reversed_axes();
+    let mut result = Array2::eye(4);
+    result
+        .slice_mut(s![..3, ..3])
+        .assign(&rotation.dot(&next.slice(s![..3, ..3])));
+    result
+        .slice_mut(s![..3, 3])
+        .assign(&rotation.dot(&(&next.slice(s![..3, 3]) - &pose.slice(s![..3, 3]))));
+    result
+}
+
+impl<T> Inverse<T, Segment> for Fabrik
+where
+    T: DepthFirstIterable<Segment, LinkNodeId>,
+{
+    type Info = FabrikInfo;
+
+    /// Selects the chain from the first of `selected_joints` (or the root if empty) to the only effector. Fails with
+    /// [MannequinError::DimensionMismatch] if not exactly one effector is selected, with
+    /// [MannequinError::NotInChain] if the first joint is not an ancestor of the effector, with
+    /// [MannequinError::UnsupportedJoint] for a prismatic joint in the chain, and with
+    /// [MannequinError::NoMovableJoint] if the chain has no revolute joint.
+    fn setup(
+        &mut self,
+        tree: &T,
+        selected_joints: &[&LinkNodeId],
+        selected_effectors: &[&LinkNodeId],
+    ) -> Result<(), MannequinError<LinkNodeId>> {
+        let [effector_id] = selected_effectors else {
+            return Err(MannequinError::DimensionMismatch(selected_effectors.len()));
+        };
+        let nodes = tree.iter().collect_vec();
+        if let Some(unknown) = selected_joints.iter().find(|id| !tree.contains(id)) {
+            return Err(MannequinError::UnknownNode((*unknown).clone()));
+        }
+        let effector = nodes
+            .iter()
+            .position(|node| node.id() == *effector_id)
+            .ok_or_else(|| MannequinError::UnknownNode((*effector_id).clone()))?;
+
+        // Path from the root to the effector
+        let mut path = vec![];
+        for (index, node) in nodes.iter().enumerate().take(effector + 1) {
+            path.truncate(node.depth());
+            path.push(index);
+        }
+        let start = match selected_joints.first() {
+            Some(id) => path
+                .iter()
+                .position(|index| nodes[*index].id() == *id)
+                .ok_or_else(|| MannequinError::NotInChain((*id).clone()))?,
+            None => 0,
+        };
+
+        let mut offsets = vec![];
+        let mut offset = 0;
+        nodes.iter().for_each(|node| {
+            offsets.push(offset);
+            offset += node.get().param_count();
+        });
+
+        // Only joints that can move are points of the chain. The links of the others add up
+        self.chain.clear();
+        self.axes.clear();
+        self.offsets.clear();
+        self.lengths.clear();
+        let mut link = Segment::neutral_element();
+        for index in &path[start..] {
+            let segment = nodes[*index].get();
+            if !self.chain.is_empty() {
+                link = link.dot(&segment.link);
+            }
+            let axis = match &segment.axis {
+                Axis::Fixed => continue,
+                Axis::RotationX => array![1.0, 0.0, 0.0],
+                Axis::RotationY => array![0.0, 1.0, 0.0],
+                Axis::RotationZ => array![0.0, 0.0, 1.0],
+                Axis::Rotation(axis) => {
+                    let axis = axis.slice(s![..3]);
+                    &axis / length(axis)
+                }
+                _ => return Err(MannequinError::UnsupportedJoint(nodes[*index].id().clone())),
+            };
+            if !self.chain.is_empty() {
+                self.lengths.push(length(link.slice(s![..3, 3])));
+            }
+            self.chain.push(*index);
+            self.axes.push(axis);
+            self.offsets.push(offsets[*index]);
+            link = Segment::neutral_element();
+        }
+        if self.chain.is_empty() {
+            return Err(MannequinError::NoMovableJoint((*effector_id).clone()));
+        }
+        if let Some(effector) = &nodes[effector].get().effector_local {
+            link = link.dot(effector);
+        }
+        self.lengths.push(length(link.slice(s![..3, 3])));
+        self.effector = link;
+        Ok(())
+    }
+
+    fn solve(&mut self, tree: &T, params: &mut [f64], targets: &[f64]) -> Self::Info {
+        debug_assert_eq!(params.len(), parameter_count(tree));
+        debug_assert!(!self.chain.is_empty(), "Call setup first");
+        let target = ArrayView1::from(&targets[..3]);
+        let distance = |point: &Array1<f64>| length((point - &target).view());
+
+        let (mut points, mut poses) = self.points(tree, params);
+        let reachable = distance(&points[0]) <= self.lengths.iter().sum::<f64>();
+        let mut error = distance(points.last().unwrap());
+        let mut counter = 0;
+        while error > self.tolerance && counter < self.max_iterations_count {
+            reach(&mut points, &self.lengths, target);
+
+            // Transformation from each joint of the chain to the next one (the last one to the effector). They do
+            // not depend on the rotation of the joint itself
+            let links = poses
+                .iter()
+                .tuple_windows()
+                .map(|(pose, next)| relative(pose, next))
+                .chain([self.effector.clone()])
+                .collect_vec();
+            // Rotate each joint such that the next point of the chain points towards its new position. The poses
+            // are updated along the chain instead of recomputing the forward kinematics
+            for (index, (axis, offset)) in self.axes.iter().zip(&self.offsets).enumerate() {
+                if index > 0 {
+                    poses[index] = poses[index - 1].dot(&links[index - 1]);
+                }
+                let current = poses[index].dot(&links[index]).slice(s![..3, 3]).to_owned();
+                let angle = angle_about(
+                    &poses[index].slice(s![..3, ..3]).dot(axis),
+                    poses[index].slice(s![..3, 3]),
+                    &current,
+                    &points[index + 1],
+                );
+                params[*offset] += angle;
+                poses[index] = poses[index].dot(&rotate_axis_4x4(axis.view(), angle));
+            }
+            points = self.positions(&poses);
+            counter += 1;
+
+            // Stop if the effector does not get closer anymore (e.g., if the target is out of reach)
+            let previous = error;
+            error = distance(points.last().unwrap());
+            if previous - error < self.tolerance {
+                break;
+            }
+        }
+
+        FabrikInfo {
+            iteration_count: counter,
+            distance: error,
+            reachable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable};
+    use approx::assert_abs_diff_eq;
+
+    /// Chain of five links (length 10) rotating around z with an effector at the tip of `link_4`
+    fn chain() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+
+        let mut parent = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "link_0".to_string());
+        for i in 1..4 {
+            parent = tree
+                .add(
+                    Segment::new(&trafo, Axis::RotationZ, None),
+                    format!("link_{i}"),
+                    &parent,
+                )
+                .unwrap();
+        }
+        tree.add(
+            Segment::new(&trafo, Axis::RotationZ, Some(trafo.clone())),
+            "link_4".to_string(),
+            &parent,
+        )
+        .unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_fabrik() {
+        let tree = chain();
+        let mut ik = Fabrik::new(100, 1e-6);
+        ik.setup(&tree, &[], &[&"link_4".to_string()]).unwrap();
+        assert_eq!(ik.lengths, [10.0; 5]);
+
+        // Each pass keeps the lengths of the links
+        let (mut points, _) = ik.points(&tree, &[0.1; 5]);
+        let target = array![30.0, 20.0, 0.0];
+        for _ in 0..5 {
+            reach(&mut points, &ik.lengths, target.view());
+            points.windows(2).zip(&ik.lengths).for_each(|(pair, distance)| {
+                assert_abs_diff_eq!(length((&pair[1] - &pair[0]).view()), *distance, epsilon = 1e-9);
+            });
+        }
+
+        let mut params = vec![0.1; 5];
+        let info = ik.solve(&tree, &mut params, target.as_slice().unwrap());
+        assert!(info.reachable);
+        assert!(info.distance <= 1e-6, "{info:?}");
+        assert!(info.iteration_count < 100);
+        let (points, _) = ik.points(&tree, &params);
+        assert_abs_diff_eq!(points[5], target, epsilon = 1e-6);
+
+        // Out of reach: fully extended towards the target (from the base at (10, 0, 0))
+        let mut params = vec![0.1; 5];
+        let info = ik.solve(&tree, &mut params, &[10.0, 80.0, 0.0]);
+        assert!(!info.reachable);
+        assert_abs_diff_eq!(info.distance, 30.0, epsilon = 1e-6);
+        let (points, _) = ik.points(&tree, &params);
+        assert_abs_diff_eq!(points[5], array![10.0, 50.0, 0.0], epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fabrik_setup_errors() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let base = tree.set_root(Segment::new(&trafo, Axis::Fixed, None), "base".to_string());
+        let slider = tree
+            .add(
+                Segment::new(&trafo, Axis::TranslationX, None),
+                "slider".to_string(),
+                &base,
+            )
+            .unwrap();
+        let arm = tree
+            .add(Segment::new(&trafo, Axis::RotationZ, None), "arm".to_string(), &slider)
+            .unwrap();
+        let hand = tree
+            .add(
+                Segment::new(&trafo, Axis::Fixed, Some(trafo.clone())),
+                "hand".to_string(),
+                &arm,
+            )
+            .unwrap();
+        let grip = tree
+            .add(
+                Segment::new(&trafo, Axis::Fixed, Some(trafo.clone())),
+                "grip".to_string(),
+                &base,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let mut ik = Fabrik::new(100, 1e-6);
+
+        assert!(matches!(
+            ik.setup(&tree, &[], &[]),
+            Err(MannequinError::DimensionMismatch(0))
+        ));
+        assert!(matches!(
+            ik.setup(&tree, &[], &[&hand, &grip]),
+            Err(MannequinError::DimensionMismatch(2))
+        ));
+        assert!(matches!(
+            ik.setup(&tree, &[&arm], &[&grip]),
+            Err(MannequinError::NotInChain(id)) if id == arm
+        ));
+        assert!(matches!(
+            ik.setup(&tree, &[], &[&hand]),
+            Err(MannequinError::UnsupportedJoint(id)) if id == slider
+        ));
+        assert!(matches!(
+            ik.setup(&tree, &[], &[&grip]),
+            Err(MannequinError::NoMovableJoint(id)) if id == grip
+        ));
+
+        // From the arm on, the fixed hand is merged into the last link
+        ik.setup(&tree, &[&arm], &[&hand]).unwrap();
+        assert_eq!(ik.lengths, [20.0]);
+        let mut params = vec![0.0, 0.0];
+        let info = ik.solve(&tree, &mut params, &[30.0, 20.0, 0.0]);
+        assert!(info.distance <= 1e-6, "{info:?}");
+        assert_abs_diff_eq!(params[1], std::f64::consts::FRAC_PI_2, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,141 @@
+//! Importer for the skeletons of skinned characters in [glTF](https://www.khronos.org/gltf/) assets (feature
+//! `gltf`).
+//!
+//! The joints of the first skin become the nodes of the tree, named after the glTF nodes (or their indices if
+//! unnamed). The local transformation (translation, rotation and scale) of a joint becomes the link of its
+//! [Segment], i.e., the tree is in the rest pose of the asset. As glTF does not define degrees of freedom,
+//! all joints are [Axis::Fixed]. Joints without child joints (e.g., finger tips or the head) get an effector
+//! at their origin.
+
+use super::robot::{Axis, LinkNodeId, Segment};
+use crate::{arena::iterables::BaseDirectionIterable, DirectedArenaTree, DirectionIterable, MannequinError};
+use gltf::{buffer, Document, Node};
+use ndarray::Array2;
+use std::collections::{HashMap, HashSet};
+
+/// Builds a tree from the first skin of `doc` (see the [module documentation](self)). The `buffers` of the asset
+/// are not required for the hierarchy and the rest pose (which are part of the JSON document). Fails with
+/// [MannequinError::InvalidGltf] if there is no skin or if its joints do not form a single tree.
+pub fn skeleton_from_gltf(
+    doc: &Document,
+    _buffers: &[buffer::Data],
+) -> Result<DirectedArenaTree<Segment, LinkNodeId>, MannequinError<LinkNodeId>> {
+    let skin = doc
+        .skins()
+        .next()
+        .ok_or_else(|| MannequinError::InvalidGltf("no skin".to_string()))?;
+    let joints = skin.joints().map(|joint| joint.index()).collect::<HashSet<_>>();
+    let parents = doc
+        .nodes()
+        .flat_map(|node| node.children().map(move |child| (child.index(), node.index())))
+        .collect::<HashMap<_, _>>();
+
+    let mut roots = skin.joints().filter(|joint| {
+        parents
+            .get(&joint.index())
+            .map_or(true, |parent| !joints.contains(parent))
+    });
+    let root = roots
+        .next()
+        .ok_or_else(|| MannequinError::InvalidGltf("skin without joints".to_string()))?;
+    if let Some(other) = roots.next() {
+        return Err(MannequinError::InvalidGltf(format!(
+            "{} and {} are both roots",
+            node_id(&root),
+            node_id(&other)
+        )));
+    }
+
+    let segment = |node: &Node| {
+        let effector = child_joints(node, &joints).is_empty().then(|| Array2::eye(4));
+        // glTF matrices are column-major
+        let matrix = node.transform().matrix();
+        let link = Array2::from_shape_fn((4, 4), |(row, col)| matrix[col][row] as f64);
+        Segment::new(&link, Axis::Fixed, effector)
+    };
+
+    let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+    tree.set_root(segment(&root), node_id(&root));
+    // Depth-first such that parents are added before their children
+    let mut stack = child_joints(&root, &joints)
+        .into_iter()
+        .map(|child| (child, node_id(&root)))
+        .collect::<Vec<_>>();
+    stack.reverse();
+    while let Some((node, parent)) = stack.pop() {
+        let id = tree.add(segment(&node), node_id(&node), &parent)?;
+        stack.extend(
+            child_joints(&node, &joints)
+                .into_iter()
+                .rev()
+                .map(|child| (child, id.clone())),
+        );
+    }
+
+    if tree.len() != joints.len() {
+        return Err(MannequinError::InvalidGltf(
+            "joints are not connected to the root".to_string(),
+        ));
+    }
+    Ok(tree)
+}
+
+/// Children of `node` that are joints of the skin (in the order of the document)
+fn child_joints<'a>(node: &Node<'a>, joints: &HashSet<usize>) -> Vec<Node<'a>> {
+    node.children()
+        .filter(|child| joints.contains(&child.index()))
+        .collect()
+}
+
+/// Name of the glTF node or its index if it is unnamed
+fn node_id(node: &Node) -> LinkNodeId {
+    node.name().map_or_else(|| node.index().to_string(), str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arena::iterables::OptimizedDirectionIterable, DepthFirstArenaTree, NodeLike};
+    use gltf::Gltf;
+    use ndarray::array;
+
+    /// An armature (not a joint) with hips, spine, head and an unnamed leg
+    const SKELETON: &str = r#"{
+        "asset": { "version": "2.0" },
+        "nodes": [
+            { "name": "armature", "children": [1] },
+            { "name": "hips", "translation": [0.0, 1.0, 0.0], "children": [2, 3] },
+            { "name": "spine", "translation": [0.0, 0.2, 0.0], "children": [4] },
+            { "translation": [0.1, -0.1, 0.0] },
+            { "name": "head", "translation": [0.0, 0.5, 0.0] }
+        ],
+        "skins": [{ "joints": [1, 2, 3, 4] }]
+    }"#;
+
+    #[test]
+    fn test_skeleton_from_gltf() {
+        let gltf = Gltf::from_slice(SKELETON.as_bytes()).unwrap();
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = skeleton_from_gltf(&gltf.document, &[]).unwrap().into();
+
+        assert_eq!(tree.len(), 4);
+        let root = tree.iter().next().unwrap();
+        assert_eq!(root.id(), "hips");
+        assert_eq!(root.depth(), 0);
+        assert_eq!(root.get().link.column(3), array![0.0, 1.0, 0.0, 1.0]);
+
+        let ids = tree.iter().map(|node| node.id().as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["hips", "spine", "head", "3"]);
+        let effectors = tree
+            .iter()
+            .filter(|node| node.get().effector_local.is_some())
+            .map(|node| node.id().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(effectors, ["head", "3"]);
+
+        let without_skin = Gltf::from_slice(SKELETON.replace(r#""skins""#, r#""unused""#).as_bytes()).unwrap();
+        assert!(matches!(
+            skeleton_from_gltf(&without_skin.document, &[]),
+            Err(MannequinError::InvalidGltf(_))
+        ));
+    }
+}
@@ -1,9 +1,18 @@
 //! Module for the implementations using the ndarray backend. Coontains the basic calculus required
 use crate::MannequinError;
-use ndarray::{prelude::*, ErrorKind::IncompatibleShape, ShapeError};
+use ndarray::{prelude::*, ErrorKind::IncompatibleShape, Order, ShapeError};
 use ndarray_linalg::{Inverse, LeastSquaresSvd, Solve, QR};
 
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod dynamics;
+pub mod fabrik;
+#[cfg(feature = "gltf")]
+pub mod gltf;
 pub mod robot;
+pub mod spherical;
+#[cfg(feature = "urdf")]
+pub mod urdf;
 
 /// Creates a homogeneous, 4x4 rotation matrix around the x axis.
 pub fn rotate_x_4x4(param: f64) -> Array2<f64> {
@@ -99,8 +108,109 @@ pub fn cross_3d<T>(
     }
 }
 
+/// Creates the 3x3 skew-symmetric matrix `[v]ₓ` such that `[v]ₓ w = v x w`.
+pub fn skew_3x3(vector: ArrayView1<f64>) -> Array2<f64> {
+    array![
+        [0.0, -vector[2], vector[1]],
+        [vector[2], 0.0, -vector[0]],
+        [-vector[1], vector[0], 0.0]
+    ]
+}
+
+/// Creates a homogeneous, 4x4 rotation matrix around an arbitrary `axis` (3 components, normalized internally)
+/// using Rodrigues' formula `R = I + sin(θ) K + (1 - cos(θ)) K²` with `K = [axis]ₓ`. A zero angle yields the
+/// identity for any axis.
+///
+/// # Panics
+/// If `axis` does not have three components or is zero (for a non-zero angle).
+pub fn rotate_axis_4x4(axis: ArrayView1<f64>, param: f64) -> Array2<f64> {
+    if param == 0.0 {
+        return Array2::eye(4);
+    }
+    assert_eq!(axis.len(), 3, "Rotation axis needs 3 components");
+    let norm = axis.dot(&axis).sqrt();
+    assert!(norm > 0.0, "Rotation axis must not be zero");
+
+    let skew = skew_3x3((&axis / norm).view());
+    let rotation = Array2::<f64>::eye(3) + param.sin() * &skew + (1.0 - param.cos()) * skew.dot(&skew);
+    let mut result = Array2::<f64>::eye(4);
+    result.slice_mut(s![..3, ..3]).assign(&rotation);
+    result
+}
+
+/// Creates a homogeneous, 4x4 translation matrix along an arbitrary `direction` (3 components, normalized
+/// internally) by the distance `param`.
+///
+/// # Panics
+/// If `direction` does not have three components or is zero (for a non-zero distance).
+pub fn translate_axis_4x4(direction: ArrayView1<f64>, param: f64) -> Array2<f64> {
+    let mut result = Array2::<f64>::eye(4);
+    if param == 0.0 {
+        return result;
+    }
+    assert_eq!(direction.len(), 3, "Translation axis needs 3 components");
+    let norm = direction.dot(&direction).sqrt();
+    assert!(norm > 0.0, "Translation axis must not be zero");
+
+    result.slice_mut(s![..3, 3]).assign(&(&direction * (param / norm)));
+    result
+}
+
+/// Converts a 3x3 rotation matrix into a rotation vector (axis times angle in `[0, π]`), i.e., the
+/// logarithmic map of SO(3).
+pub fn rotation_vector(rotation: ArrayView2<f64>) -> Array1<f64> {
+    let cos = ((rotation[[0, 0]] + rotation[[1, 1]] + rotation[[2, 2]] - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+    // Twice the sine times the axis
+    let skew = array![
+        rotation[[2, 1]] - rotation[[1, 2]],
+        rotation[[0, 2]] - rotation[[2, 0]],
+        rotation[[1, 0]] - rotation[[0, 1]]
+    ];
+
+    if angle < 1e-6 {
+        // first-order approximation
+        skew / 2.0
+    } else if std::f64::consts::PI - angle < 1e-6 {
+        // The skew-symmetric part vanishes, the axis is the dominant column of `R + I`
+        let symmetric = &rotation + &Array2::<f64>::eye(3);
+        let column = (0..3)
+            .max_by(|a, b| symmetric[[*a, *a]].total_cmp(&symmetric[[*b, *b]]))
+            .unwrap();
+        let axis = symmetric.column(column).to_owned();
+        let norm = axis.dot(&axis).sqrt();
+        axis * (angle / norm)
+    } else {
+        skew * (angle / (2.0 * angle.sin()))
+    }
+}
+
+/// Shortest-path rotation (as rotation vector) from the orientation of the homogeneous transformation `current`
+/// to the one of `target`, i.e., the rotation vector of `target·currentᵀ`.
+pub fn orientation_error(current: ArrayView2<f64>, target: ArrayView2<f64>) -> Array1<f64> {
+    rotation_vector(target.slice(s![..3, ..3]).dot(&current.slice(s![..3, ..3]).t()).view())
+}
+
+/// Implementation of [crate::Rigid::rotation_vector_difference] shared by the loads of the ndarray backend
+pub(crate) fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+    let rotation = |vector: &[f64]| {
+        let vector = ArrayView1::from(vector);
+        rotate_axis_4x4(vector, vector.dot(&vector).sqrt())
+    };
+    let error = orientation_error(rotation(current).view(), rotation(target).view());
+    [error[0], error[1], error[2]]
+}
+
+/// Solves the damped normal equations `(JᵀJ + λI) x = Jᵀe` and writes `x` to `target`. The update is scaled down
+/// such that its norm does not exceed `limit` (as in [crate::faer::solve_linear]).
 #[allow(unused_variables)]
-pub fn solve_linear(matrix: ArrayView2<f64>, vector: ArrayView1<f64>, mut target: ArrayViewMut1<f64>) {
+pub fn solve_linear(
+    matrix: ArrayView2<f64>,
+    vector: ArrayView1<f64>,
+    damping: f64,
+    limit: f64,
+    mut target: ArrayViewMut1<f64>,
+) {
     // dbg!(&matrix);
     // dbg!(matrix.t().dot(&matrix));
     // dbg!(&vector);
@@ -109,10 +219,16 @@ pub fn solve_linear(matrix: ArrayView2<f64>, vector: ArrayView1<f64>, mut target
 
     let mut pseudo_inverse = matrix.t().dot(&matrix);
     // regularization
-    pseudo_inverse = &pseudo_inverse + 1e-5 * Array2::<f64>::eye(pseudo_inverse.nrows());
+    pseudo_inverse = &pseudo_inverse + damping * Array2::<f64>::eye(pseudo_inverse.nrows());
     pseudo_inverse = pseudo_inverse.inv().unwrap().dot(&matrix.t());
     target.assign(&pseudo_inverse.dot(&vector));
 
+    // limit the update (assuming that the function is near linear in that range)
+    let norm = target.dot(&target).sqrt();
+    if norm > limit {
+        target *= limit / norm;
+    }
+
     // let result = matrix.least_squares(&vector).unwrap();
     // target.assign(&result.solution);
     // if matrix.rank()
@@ -138,7 +254,179 @@ pub fn solve_linear(matrix: ArrayView2<f64>, vector: ArrayView1<f64>, mut target
     // target.iter_mut().for_each(|x| *x = 0.0);
 }
 
+/// Computes `J⁺ e` by solving the normal equations of the smaller dimension: `(JᵀJ) x = Jᵀe` if the system is
+/// over-determined, and `x = Jᵀ y` with `(JJᵀ) y = e` otherwise. A tiny regularization keeps singular
+/// configurations solvable.
+pub fn apply_pseudo_inverse(matrix: ArrayView2<f64>, vector: ArrayView1<f64>, mut target: ArrayViewMut1<f64>) {
+    let (rows, cols) = matrix.dim();
+    if rows >= cols {
+        let normal = matrix.t().dot(&matrix) + 1e-12 * Array2::<f64>::eye(cols);
+        target.assign(&normal.solve(&matrix.t().dot(&vector)).expect("Cannot solve equations"));
+    } else {
+        let normal = matrix.dot(&matrix.t()) + 1e-12 * Array2::<f64>::eye(rows);
+        let multipliers = normal.solve(&vector).expect("Cannot solve equations");
+        target.assign(&matrix.t().dot(&multipliers));
+    }
+}
+
+/// View of a column-major `matrix` buffer (as passed to [crate::Rigid::solve_linear]) with the shape `(rows, cols)`
+fn column_major(matrix: &[f64], rows: usize, cols: usize) -> ArrayView2<'_, f64> {
+    ArrayView1::from(matrix)
+        .into_shape_with_order(((rows, cols), Order::ColumnMajor))
+        .expect("Cannot convert buffer to matrix") // TODO error
+}
+
+/// Implementation of [crate::Rigid::solve_linear] shared by the loads of the ndarray backend. Uses
+/// [crate::faer::solve_linear] if the `faer` feature is enabled and [solve_linear] otherwise.
+#[cfg(not(feature = "faer"))]
+pub(crate) fn solve_linear_buffer(
+    matrix: &[f64],
+    rows: usize,
+    cols: usize,
+    vector: &[f64],
+    damping: f64,
+    limit: f64,
+    parameters: &mut [f64],
+) {
+    solve_linear(
+        column_major(matrix, rows, cols),
+        ArrayView1::from(vector),
+        damping,
+        limit,
+        ArrayViewMut1::from(parameters),
+    );
+}
+
+/// Implementation of [crate::Rigid::solve_linear] shared by the loads of the ndarray backend. Uses
+/// [crate::faer::solve_linear] if the `faer` feature is enabled and [solve_linear] otherwise.
+#[cfg(feature = "faer")]
+pub(crate) fn solve_linear_buffer(
+    matrix: &[f64],
+    rows: usize,
+    cols: usize,
+    vector: &[f64],
+    damping: f64,
+    limit: f64,
+    parameters: &mut [f64],
+) {
+    crate::faer::solve_linear(matrix, rows, cols, vector, damping, parameters, limit);
+}
+
+/// Implementation of [crate::Rigid::apply_pseudo_inverse] shared by the loads of the ndarray backend
+pub(crate) fn apply_pseudo_inverse_buffer(
+    matrix: &[f64],
+    rows: usize,
+    cols: usize,
+    vector: &[f64],
+    result: &mut [f64],
+) {
+    apply_pseudo_inverse(
+        column_major(matrix, rows, cols),
+        ArrayView1::from(vector),
+        ArrayViewMut1::from(result),
+    );
+}
+
 // TODO Move functions into `spatial.rs` module
 // Make a struct implementing Rigid that has a generic member `nested` with a trait NestedRigid
 // that delegates everything to `nested` but with ndarray types
 // then move the robot implementation to example (unless used in benchmarks, then leave it in (as a feature maybe)).
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_apply_pseudo_inverse() {
+        // Explicit pseudo-inverses of well-conditioned matrices with full rank
+        let tall = array![[1.0, 2.0], [0.5, -1.0], [3.0, 0.2]];
+        let vector = array![1.0, -2.0, 0.5];
+        let explicit = tall.t().dot(&tall).inv().unwrap().dot(&tall.t());
+        let mut result = Array1::<f64>::zeros(2);
+        apply_pseudo_inverse(tall.view(), vector.view(), result.view_mut());
+        assert_abs_diff_eq!(result, explicit.dot(&vector), epsilon = 1e-9);
+
+        let wide = tall.t().to_owned();
+        let vector = array![0.3, -0.7];
+        let explicit = wide.t().dot(&wide.dot(&wide.t()).inv().unwrap());
+        let mut result = Array1::<f64>::zeros(3);
+        apply_pseudo_inverse(wide.view(), vector.view(), result.view_mut());
+        assert_abs_diff_eq!(result, explicit.dot(&vector), epsilon = 1e-9);
+        // The minimum-norm solution solves the system exactly
+        assert_abs_diff_eq!(wide.dot(&result), vector, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_orientation_error() {
+        // Rotation by `angle` about a tilted `axis` (Rodrigues' formula)
+        let (axis, angle) = (array![1.0, 2.0, 2.0] / 3.0, 0.9_f64);
+        let skew = skew_3x3(axis.view());
+        let mut rotation = Array2::<f64>::eye(4);
+        rotation
+            .slice_mut(s![..3, ..3])
+            .assign(&(Array2::<f64>::eye(3) + angle.sin() * &skew + (1.0 - angle.cos()) * skew.dot(&skew)));
+
+        // Translations do not matter
+        let current = translate_y_4x4(2.0).dot(&rotate_z_4x4(0.3)).dot(&rotate_x_4x4(-1.1));
+        let target = translate_x_4x4(-1.0).dot(&rotation).dot(&current);
+
+        assert_abs_diff_eq!(
+            orientation_error(current.view(), target.view()),
+            &axis * angle,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            orientation_error(target.view(), current.view()),
+            &axis * -angle,
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(
+            orientation_error(current.view(), current.view()),
+            Array1::<f64>::zeros(3),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_rotation_vector() {
+        let rotation = rotate_x_4x4(0.5);
+        assert_abs_diff_eq!(
+            rotation_vector(rotation.slice(s![..3, ..3])),
+            array![0.5, 0.0, 0.0],
+            epsilon = 1e-9
+        );
+
+        let rotation = rotate_z_4x4(-1e-8);
+        assert_abs_diff_eq!(
+            rotation_vector(rotation.slice(s![..3, ..3])),
+            array![0.0, 0.0, -1e-8],
+            epsilon = 1e-12
+        );
+
+        let rotation = rotate_y_4x4(std::f64::consts::PI);
+        let result = rotation_vector(rotation.slice(s![..3, ..3]));
+        assert_abs_diff_eq!(
+            result.mapv(f64::abs),
+            array![0.0, std::f64::consts::PI, 0.0],
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_rotation_vector_difference() {
+        // Relative to the identity, the difference is the target itself
+        for vector in [[0.0, 0.0, 0.0], [0.3, -0.2, 0.1], [0.0, 3.0, 0.0]] {
+            let result = rotation_vector_difference(&[0.0; 3], &vector);
+            assert_abs_diff_eq!(ArrayView1::from(&result), ArrayView1::from(&vector), epsilon = 1e-9);
+        }
+
+        // Along the shortest path (the difference of the vectors would be -6 around z)
+        let result = rotation_vector_difference(&[0.0, 0.0, 3.0], &[0.0, 0.0, -3.0]);
+        assert_abs_diff_eq!(
+            ArrayView1::from(&result),
+            array![0.0, 0.0, 2.0 * std::f64::consts::PI - 6.0],
+            epsilon = 1e-9
+        );
+    }
+}
@@ -2,15 +2,19 @@
 #![allow(unused_variables)]
 
 use super::{
-    cross_3d, invert_transformation_4x4, rotate_x_4x4, rotate_y_4x4, rotate_z_4x4, solve_linear, translate_x_4x4,
-    translate_y_4x4, translate_z_4x4,
+    apply_pseudo_inverse_buffer, cross_3d, invert_transformation_4x4, orientation_error, rotate_axis_4x4, rotate_x_4x4,
+    rotate_y_4x4, rotate_z_4x4, rotation_vector, rotation_vector_difference, skew_3x3, solve_linear_buffer,
+    translate_axis_4x4, translate_x_4x4, translate_y_4x4, translate_z_4x4,
 };
-use crate::Rigid;
+use crate::{DirectedArenaTree, MannequinError, Rigid};
 use core::fmt;
-use ndarray::{prelude::*, Order};
+use ndarray::prelude::*;
 use ndarray::{Array1, Array2};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Axis {
     RotationX,
     RotationY,
@@ -21,21 +25,38 @@ pub enum Axis {
     TranslationY,
     TranslationZ,
     Translation(Array1<f64>),
+    /// Rigid connection without a degree of freedom (consumes no parameter)
+    Fixed,
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Mode {
+    /// Position of the effector (3 values)
     #[default]
     Position,
+    /// Position and orientation as rotation vector (6 values)
     Pose,
+    /// Position and the full rotation matrix in row-major order (12 values), i.e., the
+    /// affine part of the homogeneous frame
+    Frame,
+    /// Angle between the effector's `forward` axis (local) and the direction to the world `point`
+    /// (1 value). The corresponding target is zero for aiming at the point (e.g., head or eyes).
+    LookAt { forward: Array1<f64>, point: Array1<f64> },
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Segment {
-    link: Array2<f64>,
-    axis: Axis,
-    mode: Mode,
-    effector_local: Option<Array2<f64>>,
+    pub(super) link: Array2<f64>,
+    pub(super) axis: Axis,
+    pub(super) mode: Mode,
+    pub(super) effector_local: Option<Array2<f64>>,
+    /// Inertial properties (only used for dynamics): the mass, the center of mass and the
+    /// rotational inertia (about the center of mass), both in the local frame.
+    pub(super) mass: f64,
+    pub(super) com_local: Array1<f64>,
+    pub(super) inertia_local: Array2<f64>,
 }
 
 impl Segment {
@@ -45,8 +66,112 @@ impl Segment {
             axis,
             mode: Mode::Position,
             effector_local: effector,
+            mass: 0.0,
+            com_local: Array1::zeros(3),
+            inertia_local: Array2::zeros((3, 3)),
         }
     }
+
+    /// Creates a segment with inertial properties. `com` is the center of mass and `inertia` the 3x3
+    /// rotational inertia about the center of mass, both given in the local frame.
+    pub fn with_inertia(
+        from_parent: &Array2<f64>,
+        axis: Axis,
+        effector: Option<Array2<f64>>,
+        mass: f64,
+        com: Array1<f64>,
+        inertia: Array2<f64>,
+    ) -> Self {
+        Self {
+            mass,
+            com_local: com,
+            inertia_local: inertia,
+            ..Self::new(from_parent, axis, effector)
+        }
+    }
+
+    /// Inertial properties (mass, center of mass and rotational inertia, see [Segment::with_inertia]). They
+    /// are only used for dynamics and ignored by the kinematics.
+    pub fn inertia(&self) -> (f64, &Array1<f64>, &Array2<f64>) {
+        (self.mass, &self.com_local, &self.inertia_local)
+    }
+
+    /// Selects what the effector represents (and thus its dimension, see [Mode]).
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// World forward axis (unit length) of an effector `frame`, the unit direction from the effector to `point`,
+    /// and the distance to `point`.
+    fn aim(frame: &Array2<f64>, forward: &Array1<f64>, point: &Array1<f64>) -> (Array1<f64>, Array1<f64>, f64) {
+        let forward = frame.slice(s![0..3, 0..3]).dot(forward);
+        let forward = &forward / forward.dot(&forward).sqrt();
+        let difference: Array1<f64> = point - &frame.slice(s![0..3, 3]);
+        let distance = difference.dot(&difference).sqrt();
+        (forward, difference / distance, distance)
+    }
+
+    /// Whether the segment has no effect on the kinematics: a fixed joint without offset (identity link)
+    /// and without an effector. Such segments (e.g., padding in URDF chains) can be removed from the tree
+    /// with [Segment::collapse_fixed].
+    pub fn is_collapsible(&self) -> bool {
+        self.axis == Axis::Fixed && self.effector_local.is_none() && self.link == Array2::<f64>::eye(4)
+    }
+
+    /// Removes all collapsible segments ([Segment::is_collapsible]) from `tree`. Returns the number of removed nodes.
+    pub fn collapse_fixed(
+        tree: &mut DirectedArenaTree<Segment, LinkNodeId>,
+    ) -> Result<usize, MannequinError<LinkNodeId>> {
+        tree.collapse(Segment::is_collapsible)
+    }
+
+    /// Spatial inertia (6x6, angular part first) about the world origin given the world `pose` of the segment.
+    pub fn spatial_inertia(&self, pose: &Array2<f64>) -> Array2<f64> {
+        let mut result = Array2::<f64>::zeros((6, 6));
+        if self.mass == 0.0 {
+            // massless segment (e.g., default constructed)
+            return result;
+        }
+        let rotation = pose.slice(s![..3, ..3]);
+        let com = &pose.slice(s![..3, 3]) + &rotation.dot(&self.com_local);
+        let com_cross = skew_3x3(com.view());
+        let rotational = rotation.dot(&self.inertia_local).dot(&rotation.t());
+
+        result
+            .slice_mut(s![..3, ..3])
+            .assign(&(rotational + self.mass * com_cross.dot(&com_cross.t())));
+        result.slice_mut(s![..3, 3..]).assign(&(self.mass * &com_cross));
+        result.slice_mut(s![3.., ..3]).assign(&(self.mass * &com_cross.t()));
+        result
+            .slice_mut(s![3.., 3..])
+            .assign(&(self.mass * Array2::<f64>::eye(3)));
+        result
+    }
+
+    /// Motion subspace (6, angular part first) of the joint about the world origin given the world `pose` of the segment.
+    pub fn motion_subspace(&self, pose: &Array2<f64>) -> Array1<f64> {
+        let (local_axis, revolute) = match &self.axis {
+            Axis::RotationX => (array![1.0, 0.0, 0.0], true),
+            Axis::RotationY => (array![0.0, 1.0, 0.0], true),
+            Axis::RotationZ => (array![0.0, 0.0, 1.0], true),
+            Axis::Rotation(axis) => (&axis.slice(s![..3]) / axis.dot(axis).sqrt(), true),
+            Axis::TranslationX => (array![1.0, 0.0, 0.0], false),
+            Axis::TranslationY => (array![0.0, 1.0, 0.0], false),
+            Axis::TranslationZ => (array![0.0, 0.0, 1.0], false),
+            Axis::Translation(axis) => (&axis.slice(s![..3]) / axis.dot(axis).sqrt(), false),
+            Axis::Fixed => return Array1::<f64>::zeros(6),
+        };
+        let axis = pose.slice(s![..3, ..3]).dot(&local_axis);
+
+        let mut result = Array1::<f64>::zeros(6);
+        if revolute {
+            result.slice_mut(s![..3]).assign(&axis);
+            cross_3d::<LinkNodeId>(pose.slice(s![..3, 3]), axis.view(), result.slice_mut(s![3..])).unwrap();
+        } else {
+            result.slice_mut(s![3..]).assign(&axis);
+        }
+        result
+    }
 }
 
 impl fmt::Display for Segment {
@@ -65,20 +190,35 @@ impl Rigid for Segment {
     type NodeId = String;
 
     fn transform(&self, params: &[f64], index: usize) -> Self::Transformation {
-        let joint = match self.axis {
+        let joint = match &self.axis {
             Axis::RotationX => rotate_x_4x4(params[index]),
             Axis::RotationY => rotate_y_4x4(params[index]),
             Axis::RotationZ => rotate_z_4x4(params[index]),
-            // TODO implement arbitrary axis and translations
-            Axis::Rotation(_) => todo!(),
+            Axis::Rotation(axis) => rotate_axis_4x4(axis.view(), params[index]),
             Axis::TranslationX => translate_x_4x4(params[index]),
             Axis::TranslationY => translate_y_4x4(params[index]),
             Axis::TranslationZ => translate_z_4x4(params[index]),
-            Axis::Translation(_) => todo!(),
+            Axis::Translation(axis) => translate_axis_4x4(axis.view(), params[index]),
+            Axis::Fixed => Array2::<f64>::eye(4),
         };
         self.link.dot(&joint)
     }
 
+    fn param_count(&self) -> usize {
+        if self.axis == Axis::Fixed {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn is_revolute(&self) -> bool {
+        matches!(
+            self.axis,
+            Axis::RotationX | Axis::RotationY | Axis::RotationZ | Axis::Rotation(_)
+        )
+    }
+
     fn globalize(&self, other: &Self::Point) -> Self::Point {
         self.link.dot(other)
     }
@@ -99,10 +239,24 @@ impl Rigid for Segment {
         invert_transformation_4x4(trafo)
     }
 
+    fn origin(trafo: &Self::Transformation) -> Option<[f64; 3]> {
+        Some([trafo[[0, 3]], trafo[[1, 3]], trafo[[2, 3]]])
+    }
+
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point {
+        orientation_error(current.view(), target.view())
+    }
+
+    fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+        rotation_vector_difference(current, target)
+    }
+
     fn dim(&self) -> usize {
         match self.mode {
             Mode::Position => 3,
-            Mode::Pose => unimplemented!(), //6,
+            Mode::Pose => 6,
+            Mode::Frame => 12,
+            Mode::LookAt { .. } => 1,
         }
     }
 
@@ -122,17 +276,17 @@ impl Rigid for Segment {
         buffer: &mut [f64],
         offset: usize,
     ) {
-        // Formula: axis_in_world x (end_effector_world - pivod_in_world)
+        // Formula: axis_in_world x (end_effector_world - pivod_in_world) for revolute joints and
+        // axis_in_world for prismatic joints
 
         let local_axis = match &joint.axis {
-            Axis::RotationX => &array![1.0, 0.0, 0.0, 0.0],
-            Axis::RotationY => &array![0.0, 1.0, 0.0, 0.0],
-            Axis::RotationZ => &array![0.0, 0.0, 1.0, 0.0],
-            Axis::Rotation(array_base) => array_base,
-            Axis::TranslationX => unimplemented!(),
-            Axis::TranslationY => unimplemented!(),
-            Axis::TranslationZ => unimplemented!(),
-            Axis::Translation(array_base) => unimplemented!(),
+            Axis::RotationX | Axis::TranslationX => &array![1.0, 0.0, 0.0, 0.0],
+            Axis::RotationY | Axis::TranslationY => &array![0.0, 1.0, 0.0, 0.0],
+            Axis::RotationZ | Axis::TranslationZ => &array![0.0, 0.0, 1.0, 0.0],
+            Axis::Rotation(axis) | Axis::Translation(axis) => {
+                &(array![axis[0], axis[1], axis[2], 0.0] / axis.dot(axis).sqrt())
+            }
+            Axis::Fixed => &array![0.0, 0.0, 0.0, 0.0],
         };
         let axis_global = joint_pose.dot(local_axis);
 
@@ -144,12 +298,46 @@ impl Rigid for Segment {
         let lever = &pose.slice(s![0..3, 3]) - &joint_pose.slice(s![0..3, 3]);
 
         let target_buffer = &mut buffer[offset..offset + self.effector_size()];
-        cross_3d::<Self::NodeId>(
-            axis_global.slice(s![0..3]),
-            lever.view(),
-            ArrayViewMut1::from(target_buffer),
-        )
-        .unwrap();
+        let mut target = ArrayViewMut1::from(target_buffer);
+        // Linear and angular velocity of the effector
+        let mut velocity = Array1::<f64>::zeros(3);
+        let mut angular = Array1::<f64>::zeros(3);
+        if joint.is_revolute() {
+            angular.assign(&axis_global.slice(s![0..3]));
+            cross_3d::<Self::NodeId>(angular.view(), lever.view(), velocity.view_mut()).unwrap();
+        } else {
+            velocity.assign(&axis_global.slice(s![0..3]));
+        }
+
+        if let Mode::LookAt { forward, point } = &self.mode {
+            // d/dq acos(f·d) = -(ḟ·d + f·ḋ) / sin(angle) with ḟ = axis x f and ḋ = -(I - ddᵀ) ṗ / distance
+            let (forward, direction, distance) = Segment::aim(&pose, forward, point);
+            let angle = forward.dot(&direction).clamp(-1.0, 1.0).acos();
+            let mut forward_velocity = Array1::<f64>::zeros(3);
+            cross_3d::<Self::NodeId>(angular.view(), forward.view(), forward_velocity.view_mut()).unwrap();
+            let direction_velocity = (&direction * direction.dot(&velocity) - &velocity) / distance;
+
+            target[0] = if angle.sin() < 1e-9 {
+                // aligned (or opposite): the gradient vanishes
+                0.0
+            } else {
+                -(forward_velocity.dot(&direction) + forward.dot(&direction_velocity)) / angle.sin()
+            };
+            return;
+        }
+        target.slice_mut(s![0..3]).assign(&velocity);
+
+        match self.mode {
+            Mode::Position | Mode::LookAt { .. } => {}
+            // The angular velocity caused by a revolute joint is its axis
+            Mode::Pose => target.slice_mut(s![3..6]).assign(&angular),
+            // Derivative of the rotation matrix: axis x R (row-major)
+            Mode::Frame => target
+                .slice_mut(s![3..12])
+                .iter_mut()
+                .zip(&skew_3x3(angular.view()).dot(&pose.slice(s![0..3, 0..3])))
+                .for_each(|(t, d)| *t = *d),
+        }
     }
 
     /// Get the coordinates of the effenctor in the global (or an arbitatry) system.
@@ -159,29 +347,44 @@ impl Rigid for Segment {
         let mut target = ArrayViewMut1::from(target_buffer);
 
         if let Some(effector) = &self.effector_local {
-            target.assign(&(pose.dot(effector)).slice(s![0..3, 3]));
+            let frame = pose.dot(effector);
+            if let Mode::LookAt { forward, point } = &self.mode {
+                let (forward, direction, _) = Segment::aim(&frame, forward, point);
+                target[0] = forward.dot(&direction).clamp(-1.0, 1.0).acos();
+                return;
+            }
+            target.slice_mut(s![0..3]).assign(&frame.slice(s![0..3, 3]));
+            // Orientation (same order as in the partial derivatives)
+            match self.mode {
+                Mode::Position | Mode::LookAt { .. } => {}
+                Mode::Pose => target
+                    .slice_mut(s![3..6])
+                    .assign(&rotation_vector(frame.slice(s![0..3, 0..3]))),
+                Mode::Frame => target
+                    .slice_mut(s![3..12])
+                    .iter_mut()
+                    .zip(frame.slice(s![0..3, 0..3]))
+                    .for_each(|(t, f)| *t = *f),
+            }
         } else {
             panic!("Should not call this method if no effector is defined")
         }
     }
 
-    #[cfg(not(feature = "faer"))]
-    fn solve_linear(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], parameters: &mut [f64]) {
-        let matrix = ArrayView1::from(matrix)
-            .into_shape_with_order(((rows, cols), Order::ColumnMajor))
-            .expect("Cannot convert buffer to matrix"); // TODO error
-        let vector = ArrayView1::from(vector);
-        let parameters = ArrayViewMut1::from(parameters);
-        solve_linear(matrix, vector, parameters);
+    fn solve_linear(
+        matrix: &[f64],
+        rows: usize,
+        cols: usize,
+        vector: &[f64],
+        damping: f64,
+        limit: f64,
+        parameters: &mut [f64],
+    ) {
+        solve_linear_buffer(matrix, rows, cols, vector, damping, limit, parameters);
     }
 
-    #[cfg(feature = "faer")]
-    fn solve_linear(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], parameters: &mut [f64]) {
-        use std::f64::consts::PI;
-
-        use crate::faer::solve_linear;
-
-        solve_linear(matrix, rows, cols, vector, parameters, PI / 180.0 * 40.0);
+    fn apply_pseudo_inverse(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], result: &mut [f64]) {
+        apply_pseudo_inverse_buffer(matrix, rows, cols, vector, result);
     }
 }
 
@@ -220,4 +423,345 @@ pub struct DifferentialIK {
 // pub type BasicMannequin = Mannequin<DirectedArenaTree<Bone, LinkNodeId>, Bone, ForwardsKinematics, DifferentialIK>;
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_apply_transpose() {
+        // Naive reference with explicit indices into the column-major storage
+        let reference = |matrix: &[f64], rows: usize, cols: usize, vector: &[f64]| {
+            (0..cols)
+                .map(|col| (0..rows).map(|row| matrix[col * rows + row] * vector[row]).sum::<f64>())
+                .collect::<Vec<_>>()
+        };
+
+        // Square, tall and wide
+        for (rows, cols) in [(3, 3), (4, 2), (2, 5)] {
+            let matrix = (0..rows * cols).map(|i| (i as f64 * 0.7).sin()).collect::<Vec<_>>();
+            let vector = (0..rows).map(|i| 1.0 - i as f64 * 0.3).collect::<Vec<_>>();
+            let mut result = vec![f64::NAN; cols];
+            Segment::apply_transpose(&matrix, rows, cols, &vector, &mut result);
+
+            assert_eq!(result, reference(&matrix, rows, cols, &vector));
+            let expected = Array2::from_shape_vec((rows, cols).f(), matrix)
+                .unwrap()
+                .t()
+                .dot(&Array1::from_vec(vector));
+            assert_abs_diff_eq!(Array1::from_vec(result), expected, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_solve_linear_damping() {
+        // Nearly singular Jacobian (column-major) with a residual in the direction of the tiny singular value
+        let matrix = [1.0, 1.0, 1.0, 1.0 + 1e-6];
+        let vector = [0.01, -0.01];
+        let norm = |damping: f64| {
+            let mut update = [f64::NAN; 2];
+            Segment::solve_linear(&matrix, 2, 2, &vector, damping, f64::INFINITY, &mut update);
+            update.iter().map(|u| u * u).sum::<f64>().sqrt()
+        };
+        let (small, large) = (norm(1e-5), norm(1.0));
+        assert!(large > 0.0);
+        assert!(large * 100.0 < small, "{large} vs. {small}");
+    }
+
+    #[test]
+    fn test_rotation_axis() {
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![1.0, 2.0, 3.0]);
+        let effector = Some(translate_x_4x4(10.0));
+        let reference = Segment::new(&link, Axis::RotationZ, effector.clone());
+        // Not normalized
+        let arbitrary = Segment::new(&link, Axis::Rotation(array![0.0, 0.0, 2.0]), effector);
+
+        for angle in [0.0, 0.3, -1.2, 2.5, std::f64::consts::PI] {
+            assert_abs_diff_eq!(
+                arbitrary.transform(&[angle], 0),
+                reference.transform(&[angle], 0),
+                epsilon = 1e-12
+            );
+
+            let pose = reference.transform(&[angle], 0);
+            let (mut expected, mut actual) = ([0.0; 3], [0.0; 3]);
+            reference.partial_derivative(&pose, &reference, &pose, &mut expected, 0);
+            arbitrary.partial_derivative(&pose, &arbitrary, &pose, &mut actual, 0);
+            assert_abs_diff_eq!(&actual[..], &expected[..], epsilon = 1e-12);
+        }
+
+        // Rotating about the diagonal by 120° permutes the axes
+        let rotation = rotate_axis_4x4(array![1.0, 1.0, 1.0].view(), 2.0 * std::f64::consts::FRAC_PI_3);
+        assert_abs_diff_eq!(
+            rotation.dot(&array![1.0, 0.0, 0.0, 1.0]),
+            array![0.0, 1.0, 0.0, 1.0],
+            epsilon = 1e-12
+        );
+        // The axis does not matter without rotation
+        assert_eq!(
+            rotate_axis_4x4(array![0.0, 0.0, 0.0].view(), 0.0),
+            Array2::<f64>::eye(4)
+        );
+    }
+
+    #[test]
+    fn test_prismatic_joint() {
+        // Rotated link such that the world axes differ from the local ones
+        let mut link = rotate_z_4x4(std::f64::consts::FRAC_PI_2);
+        link.slice_mut(s![..3, 3]).assign(&array![1.0, 2.0, 3.0]);
+        let effector = Some(translate_z_4x4(5.0));
+
+        for (axis, expected) in [
+            (Axis::TranslationX, array![0.0, 1.0, 0.0]),
+            (Axis::TranslationY, array![-1.0, 0.0, 0.0]),
+            (Axis::TranslationZ, array![0.0, 0.0, 1.0]),
+            // Not normalized
+            (Axis::Translation(array![3.0, 0.0, 4.0]), array![0.0, 0.6, 0.8]),
+        ] {
+            let mut segment = Segment::new(&link, axis, effector.clone());
+            segment.set_mode(Mode::Pose);
+            let position = |param: f64| {
+                let mut buffer = [0.0; 6];
+                segment.effector(&segment.transform(&[param], 0), &mut buffer, 0);
+                array![buffer[0], buffer[1], buffer[2]]
+            };
+
+            // The effector moves linearly along the axis
+            for param in [-2.0, 0.5, 3.0] {
+                assert_abs_diff_eq!(position(param), position(0.0) + param * &expected, epsilon = 1e-12);
+            }
+
+            // The Jacobian column is the axis, the orientation does not change
+            let pose = segment.transform(&[0.7], 0);
+            let mut column = [1.0; 6];
+            segment.partial_derivative(&pose, &segment, &pose, &mut column, 0);
+            assert_abs_diff_eq!(&column[..3], expected.as_slice().unwrap(), epsilon = 1e-12);
+            assert_abs_diff_eq!(&column[3..], &[0.0; 3][..], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_pose_partial_derivative() {
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        // Effector with an arbitrary orientation
+        let effector = rotate_x_4x4(0.3).dot(&rotate_z_4x4(-0.2));
+        let mut segment = Segment::new(&link, Axis::RotationY, Some(effector));
+        segment.set_mode(Mode::Pose);
+        let joint = Segment::new(&link, Axis::RotationZ, None);
+        assert_eq!(segment.effector_size(), 6);
+
+        // Chain: joint (parameter 0) -> segment (parameter 1)
+        let poses = |params: &[f64]| {
+            let joint_pose = joint.transform(params, 0);
+            let pose = joint_pose.dot(&segment.transform(params, 1));
+            (joint_pose, pose)
+        };
+        let frame = |params: &[f64]| poses(params).1.dot(segment.effector_local.as_ref().unwrap());
+
+        let params = [0.4, -0.7];
+        let (joint_pose, pose) = poses(&params);
+        let step = 1e-6;
+        let before = frame(&params);
+
+        // Columns of both joints (the segment's own joint rotates about its pivot, i.e., its pose)
+        for (index, (moving, moving_pose)) in [(&joint, &joint_pose), (&segment, &pose)].into_iter().enumerate() {
+            let mut analytic = [0.0; 6];
+            segment.partial_derivative(&pose, moving, moving_pose, &mut analytic, 0);
+
+            let mut shifted = params;
+            shifted[index] += step;
+            let after = frame(&shifted);
+
+            // Linear rows: derivative of the position
+            let linear = (&after.slice(s![..3, 3]) - &before.slice(s![..3, 3])) / step;
+            assert_abs_diff_eq!(ArrayView1::from(&analytic[..3]), linear, epsilon = 1e-4);
+
+            // Angular rows: angular velocity, i.e., the rotation between both frames per step
+            let angular = Segment::orientation_error(&before, &after) / step;
+            assert_abs_diff_eq!(ArrayView1::from(&analytic[3..]), angular, epsilon = 1e-4);
+        }
+
+        // The effector writes position and orientation in the same order
+        let mut buffer = [0.0; 6];
+        segment.effector(&pose, &mut buffer, 0);
+        assert_abs_diff_eq!(
+            ArrayView1::from(&buffer[..3]),
+            before.slice(s![..3, 3]),
+            epsilon = 1e-12
+        );
+        assert_abs_diff_eq!(
+            ArrayView1::from(&buffer[3..]),
+            rotation_vector(before.slice(s![..3, ..3])),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_collapse_fixed() {
+        use crate::arena::iterables::BaseDirectionIterable;
+        use crate::{
+            differentiable::ComputeSelection, Differentiable, DifferentiableModel, DirectionIterable, NodeLike,
+        };
+
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let padding = || Segment::new(&Segment::neutral_element(), Axis::Fixed, None);
+
+        // upper -> pad1 -> pad2 -> lower (effector), the padding nodes are collapsible
+        let build = || {
+            let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+            let upper = tree.set_root(Segment::new(&link, Axis::RotationZ, None), "upper".to_string());
+            let pad1 = tree.add(padding(), "pad1".to_string(), &upper).unwrap();
+            let pad2 = tree.add(padding(), "pad2".to_string(), &pad1).unwrap();
+            // fixed but with an offset, has to be kept
+            tree.add(Segment::new(&link, Axis::Fixed, None), "offset".to_string(), &pad2)
+                .unwrap();
+            tree.add(
+                Segment::new(&link, Axis::RotationZ, Some(link.clone())),
+                "lower".to_string(),
+                &pad2,
+            )
+            .unwrap();
+            tree
+        };
+        let effectors = |tree: DirectedArenaTree<Segment, LinkNodeId>| {
+            let tree = tree.depth_first();
+            let mut model = DifferentiableModel::<f64>::new();
+            model.setup(&tree, &[], &[&"lower".to_string()]).unwrap();
+            model.compute(&tree, &[0.3, -0.5], ComputeSelection::EffectorsOnly);
+            model.flat_effectors().to_vec()
+        };
+
+        let mut collapsed = build();
+        collapsed.set_metadata(&"lower".to_string(), "name", "forearm").unwrap();
+        assert_eq!(Segment::collapse_fixed(&mut collapsed).unwrap(), 2);
+        assert_eq!(collapsed.len(), 3);
+        assert!(collapsed.node_by_id(&"pad1".to_string()).is_none());
+        let upper = collapsed.node_by_id(&"upper".to_string()).unwrap();
+        assert_eq!(collapsed.children(upper).unwrap().len(), 2);
+        let lower = collapsed.node_by_id(&"lower".to_string()).unwrap();
+        assert_eq!(lower.metadata("name"), Some("forearm"));
+
+        assert_abs_diff_eq!(
+            Array1::from(effectors(collapsed)),
+            Array1::from(effectors(build())),
+            epsilon = 1e-12
+        );
+    }
+    #[test]
+    fn test_clone_tree() {
+        use crate::arena::iterables::OptimizedDirectionIterable;
+        use crate::{DepthFirstArenaTree, DirectionIterable, Forward, ForwardModel, NodeLike};
+
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let upper = tree.set_root(Segment::new(&link, Axis::RotationZ, None), "upper".to_string());
+        let lower = tree
+            .add(
+                Segment::new(&link, Axis::RotationZ, Some(link.clone())),
+                "lower".to_string(),
+                &upper,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        // Fork the skeleton and lengthen the upper link of the copy
+        let mut fork = tree.clone();
+        fork.iter_mut()
+            .filter(|node| node.id() == &upper)
+            .for_each(|node| node.get_mut().link[[0, 3]] = 20.0);
+
+        let mut fk = ForwardModel::new(crate::DifferentiableModel::new());
+        fk.setup(&tree, &[&lower]).unwrap();
+        assert_eq!(fk.solve(&tree, &[0.0, 0.0])[0], [30.0, 0.0, 0.0]);
+        assert_eq!(fk.solve(&fork, &[0.0, 0.0])[0], [40.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_effector_frame() {
+        let mut link = Segment::neutral_element();
+        link.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let effector = rotate_x_4x4(0.3).dot(&translate_y_4x4(2.0));
+        let segment = Segment {
+            mode: Mode::Frame,
+            ..Segment::new(&link, Axis::RotationY, Some(effector.clone()))
+        };
+        let joint = Segment::new(&link, Axis::RotationZ, None);
+        assert_eq!(segment.effector_size(), 12);
+
+        let frame = |params: &[f64]| {
+            let joint_pose = joint.transform(params, 0);
+            let pose = joint_pose.dot(&segment.transform(params, 1));
+            let mut buffer = [0.0; 12];
+            segment.effector(&pose, &mut buffer, 0);
+            (joint_pose, pose, Array1::from(buffer.to_vec()))
+        };
+
+        let params = [0.4, -0.7];
+        let (joint_pose, pose, result) = frame(&params);
+
+        // Position followed by the rotation matrix of the accumulated pose times the local effector frame
+        let expected = pose.dot(&effector);
+        assert_abs_diff_eq!(result.slice(s![..3]), expected.slice(s![..3, 3]), epsilon = 1e-12);
+        assert_abs_diff_eq!(
+            result.slice(s![3..]),
+            Array1::from_iter(expected.slice(s![..3, ..3]).iter().copied()),
+            epsilon = 1e-12
+        );
+
+        // Partial derivatives are consistent with the layout
+        let mut analytic = [0.0; 12];
+        segment.partial_derivative(&pose, &joint, &joint_pose, &mut analytic, 0);
+        let step = 1e-6;
+        let (_, _, shifted) = frame(&[params[0] + step, params[1]]);
+        assert_abs_diff_eq!(
+            Array1::from(analytic.to_vec()),
+            (shifted - result) / step,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn test_look_at() {
+        use crate::{
+            DepthFirstArenaTree, DifferentiableModel, DifferentialInverseModel, DirectionIterable, Inverse, UpdateRule,
+        };
+
+        // A single bone (e.g., the head) rotating about z at the origin, looking along x
+        let mut head = Segment::new(
+            &Segment::neutral_element(),
+            Axis::RotationZ,
+            Some(Segment::neutral_element()),
+        );
+        let point = array![3.0, 4.0, 0.0];
+        head.set_mode(Mode::LookAt {
+            forward: array![1.0, 0.0, 0.0],
+            point: point.clone(),
+        });
+        assert_eq!(head.effector_size(), 1);
+
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let id = tree.set_root(head, "head".to_string());
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            20,
+            1e-12,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&id]).unwrap();
+        let mut params = [0.0];
+        // The angle between the forward axis and the point is to vanish
+        let result = ik.solve(&tree, &mut params, &[0.0]);
+
+        assert!(result.squared_error < 1e-12);
+        assert_abs_diff_eq!(params[0], 4.0f64.atan2(3.0), epsilon = 1e-6);
+        let forward = rotate_z_4x4(params[0]).dot(&array![1.0, 0.0, 0.0, 0.0]);
+        assert_abs_diff_eq!(forward.slice(s![..3]), point / 5.0, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,255 @@
+//! Spherical (ball) joints with three degrees of freedom in a single node, e.g., for shoulders and hips or for
+//! ropes and tails made of many short links.
+//!
+//! The three parameters are Euler angles about the x-, y- and z-axis of the moving frame (in this order), that
+//! is, the transformation from the parent is `link · Rx(α) · Ry(β) · Rz(γ)`. The effectors are positions.
+
+use super::{
+    apply_pseudo_inverse_buffer, cross_3d, invert_transformation_4x4, orientation_error, rotate_x_4x4, rotate_y_4x4,
+    rotate_z_4x4, rotation_vector_difference, solve_linear_buffer,
+};
+use crate::Rigid;
+use ndarray::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SphericalJoint {
+    link: Array2<f64>,
+    effector_local: Option<Array2<f64>>,
+}
+
+impl SphericalJoint {
+    pub fn new(from_parent: &Array2<f64>, effector: Option<Array2<f64>>) -> Self {
+        Self {
+            link: from_parent.clone(),
+            effector_local: effector,
+        }
+    }
+
+    /// Rotations about the x-, y- and z-axis for the three `params` (in the order of application)
+    fn rotations(params: &[f64]) -> [Array2<f64>; 3] {
+        [
+            rotate_x_4x4(params[0]),
+            rotate_y_4x4(params[1]),
+            rotate_z_4x4(params[2]),
+        ]
+    }
+}
+
+impl Rigid for SphericalJoint {
+    type Transformation = Array2<f64>;
+
+    type Point = Array1<f64>;
+
+    type FloatType = f64;
+
+    type NodeId = String;
+
+    fn transform(&self, params: &[f64], index: usize) -> Self::Transformation {
+        let [x, y, z] = Self::rotations(&params[index..index + 3]);
+        self.link.dot(&x).dot(&y).dot(&z)
+    }
+
+    fn param_count(&self) -> usize {
+        3
+    }
+
+    fn is_revolute(&self) -> bool {
+        true
+    }
+
+    fn globalize(&self, other: &Self::Point) -> Self::Point {
+        self.link.dot(other)
+    }
+
+    fn localize(&self, other: &Self::Point) -> Self::Point {
+        invert_transformation_4x4(&self.link).dot(other)
+    }
+
+    fn dim(&self) -> usize {
+        3
+    }
+
+    /// With respect to the rotation about the z-axis, the last of the three parameters and the only one whose axis
+    /// does not depend on the parameters. See [SphericalJoint::partial_derivative_dof] for all three.
+    fn partial_derivative(
+        &self,
+        pose: &Self::Transformation,
+        joint: &Self,
+        joint_pose: &Self::Transformation,
+        buffer: &mut [f64],
+        offset: usize,
+    ) {
+        self.partial_derivative_dof(pose, joint, joint_pose, &[0.0; 3], 2, &mut buffer[offset..]);
+    }
+
+    fn partial_derivative_dof(
+        &self,
+        pose: &Self::Transformation,
+        _joint: &Self,
+        joint_pose: &Self::Transformation,
+        joint_params: &[f64],
+        dof: usize,
+        target_buffer: &mut [f64],
+    ) {
+        // The axis of rotation `dof` is rotated by the subsequent rotations: joint_pose · (R_dof+1 ·...)ᵀ · e_dof
+        let rotations = SphericalJoint::rotations(joint_params);
+        let subsequent = rotations[dof + 1..]
+            .iter()
+            .fold(Array2::<f64>::eye(4), |product, rotation| product.dot(rotation));
+        let mut local_axis = Array1::<f64>::zeros(4);
+        local_axis[dof] = 1.0;
+        let axis_global = joint_pose.dot(&subsequent.t().dot(&local_axis));
+
+        let pose = match &self.effector_local {
+            Some(effector) => pose.dot(effector),
+            None => pose.clone(),
+        };
+        let lever = &pose.slice(s![0..3, 3]) - &joint_pose.slice(s![0..3, 3]);
+        let target = ArrayViewMut1::from(&mut target_buffer[..self.effector_size()]);
+        cross_3d::<Self::NodeId>(axis_global.slice(s![0..3]), lever.view(), target).unwrap();
+    }
+
+    fn effector(&self, pose: &Self::Transformation, buffer: &mut [f64], offset: usize) {
+        let target = &mut buffer[offset..offset + self.effector_size()];
+        if let Some(effector) = &self.effector_local {
+            let frame = pose.dot(effector);
+            ArrayViewMut1::from(target).assign(&frame.slice(s![0..3, 3]));
+        } else {
+            panic!("Should not call this method if no effector is defined")
+        }
+    }
+
+    fn effector_count(&self) -> usize {
+        if self.effector_local.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn neutral_element() -> Self::Transformation {
+        Array2::<f64>::eye(4)
+    }
+
+    fn concat(first: &Self::Transformation, second: &Self::Transformation) -> Self::Transformation {
+        first.dot(second)
+    }
+
+    fn invert(trafo: &Self::Transformation) -> Self::Transformation {
+        invert_transformation_4x4(trafo)
+    }
+
+    fn origin(trafo: &Self::Transformation) -> Option<[f64; 3]> {
+        Some([trafo[[0, 3]], trafo[[1, 3]], trafo[[2, 3]]])
+    }
+
+    fn orientation_error(current: &Self::Transformation, target: &Self::Transformation) -> Self::Point {
+        orientation_error(current.view(), target.view())
+    }
+
+    fn rotation_vector_difference(current: &[f64], target: &[f64]) -> [f64; 3] {
+        rotation_vector_difference(current, target)
+    }
+
+    fn solve_linear(
+        matrix: &[f64],
+        rows: usize,
+        cols: usize,
+        vector: &[f64],
+        damping: f64,
+        limit: f64,
+        parameters: &mut [f64],
+    ) {
+        solve_linear_buffer(matrix, rows, cols, vector, damping, limit, parameters);
+    }
+
+    fn apply_pseudo_inverse(matrix: &[f64], rows: usize, cols: usize, vector: &[f64], result: &mut [f64]) {
+        apply_pseudo_inverse_buffer(matrix, rows, cols, vector, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arena::iterables::OptimizedDirectionIterable, differentiable::ComputeSelection,
+        forward::TransformationAccumulation, ndarray::translate_x_4x4, DepthFirstArenaTree, Differentiable,
+        DifferentiableModel, DifferentialInverseModel, DirectedArenaTree, DirectionIterable, FiniteDifferenceModel,
+        Forward, ForwardModel, Inverse, NodeLike, UpdateRule,
+    };
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_spherical_jacobian() {
+        // Shoulder and elbow as ball joints, the hand at the end of the forearm
+        let mut tree = DirectedArenaTree::<SphericalJoint, String>::new();
+        let shoulder = tree.set_root(SphericalJoint::new(&Array2::eye(4), None), "shoulder".to_string());
+        let elbow = tree
+            .add(
+                SphericalJoint::new(&translate_x_4x4(0.3), Some(translate_x_4x4(0.25))),
+                "elbow".to_string(),
+                &shoulder,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+        let params = [0.3, -0.5, 0.8, -0.2, 0.6, 0.4];
+
+        let mut model = DifferentiableModel::new();
+        model.setup(&tree, &[], &[&elbow]).unwrap();
+        assert_eq!(model.shape(), (3, 6));
+        // Only the elbow: its three columns
+        let mut elbow_only = DifferentiableModel::new();
+        elbow_only.setup(&tree, &[&elbow], &[&elbow]).unwrap();
+        assert_eq!(elbow_only.shape(), (3, 3));
+
+        let mut reference = FiniteDifferenceModel::new(1e-6);
+        reference.setup(&tree, &[], &[&elbow]).unwrap();
+        model.compute(&tree, &params, ComputeSelection::All);
+        reference.compute(&tree, &params, ComputeSelection::All);
+        assert_abs_diff_eq!(model.jacobian(), reference.jacobian(), epsilon = 1e-8);
+        assert_abs_diff_eq!(model.flat_effectors(), reference.flat_effectors());
+
+        elbow_only.compute(&tree, &params, ComputeSelection::All);
+        assert_abs_diff_eq!(elbow_only.jacobian(), &model.jacobian()[9..], epsilon = 1e-12);
+
+        // Without the parameters, the partial derivative is the one of the rotation about z (the last column)
+        let (node, pose) = tree.iter().accumulate(&params, 2).last().unwrap();
+        let mut column = [0.0; 4];
+        node.get().partial_derivative(&pose, node.get(), &pose, &mut column, 1);
+        assert_abs_diff_eq!(&column[1..], &model.jacobian()[15..], epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_spherical_ik() {
+        let mut tree = DirectedArenaTree::<SphericalJoint, String>::new();
+        let shoulder = tree.set_root(SphericalJoint::new(&Array2::eye(4), None), "shoulder".to_string());
+        let elbow = tree
+            .add(
+                SphericalJoint::new(&translate_x_4x4(0.3), Some(translate_x_4x4(0.25))),
+                "elbow".to_string(),
+                &shoulder,
+            )
+            .unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&elbow]).unwrap();
+        let targets = fk.solve(&tree, &[0.3, -0.5, 0.8, -0.2, 0.6, 0.4])[0].to_vec();
+
+        let mut ik = DifferentialInverseModel::new(
+            42,
+            100,
+            1e-12,
+            DifferentiableModel::new(),
+            1.0,
+            UpdateRule::PseudoInverse,
+        );
+        ik.setup(&tree, &[], &[&elbow]).unwrap();
+        // All six parameters are updated
+        let mut params = vec![0.1; 6];
+        let info = ik.solve(&tree, &mut params, &targets);
+
+        assert!(info.converged, "{info:?}");
+        assert_abs_diff_eq!(fk.solve(&tree, &params)[0], &targets[..], epsilon = 1e-5);
+    }
+}
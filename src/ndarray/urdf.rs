@@ -0,0 +1,233 @@
+//! Importer for robots described in the [URDF](http://wiki.ros.org/urdf/XML) format (feature `urdf`).
+//!
+//! Each `<link>` becomes a node named after the link. The `<joint>` whose child is the link determines
+//! its [Segment]: the joint's `<origin>` becomes the transformation from the parent and its type and `<axis>`
+//! the [Axis] (`revolute` and `continuous` joints rotate, `prismatic` joints translate and `fixed` joints
+//! are rigid). The root link is fixed at the origin. Links without children (the tips of the chains) get an
+//! effector at their origin. Joint limits, inertias and geometries are ignored.
+
+use super::{
+    robot::{Axis, LinkNodeId, Segment},
+    rotate_x_4x4, rotate_y_4x4, rotate_z_4x4,
+};
+use crate::{arena::iterables::BaseDirectionIterable, DirectedArenaTree, DirectionIterable, MannequinError};
+use ndarray::{s, Array1, Array2};
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+
+/// Joint of the URDF file that connects the link `child` to the link `parent`
+struct Joint<'a> {
+    name: &'a str,
+    kind: &'a str,
+    parent: &'a str,
+    child: &'a str,
+    origin: Array2<f64>,
+    axis: [f64; 3],
+}
+
+/// Builds a tree from the URDF document `xml` (see the [module documentation](self)). Fails with
+/// [MannequinError::UnknownNode] if a joint refers to an undefined link, with [MannequinError::InvalidUrdf]
+/// for unsupported joint types, malformed values or if the links do not form a single tree, and with
+/// [MannequinError::XmlError] if `xml` is not well-formed.
+pub fn from_urdf(xml: &str) -> Result<DirectedArenaTree<Segment, LinkNodeId>, MannequinError<LinkNodeId>> {
+    let document = Document::parse(xml)?;
+    let robot = document.root_element();
+    let links = robot
+        .children()
+        .filter(|node| node.has_tag_name("link"))
+        .map(|node| attribute(node, "name"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut joints: HashMap<&str, Joint> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    for node in robot.children().filter(|node| node.has_tag_name("joint")) {
+        let joint = parse_joint(node)?;
+        for link in [joint.parent, joint.child] {
+            if !links.contains(&link) {
+                return Err(MannequinError::UnknownNode(link.to_string()));
+            }
+        }
+        children.entry(joint.parent).or_default().push(joint.child);
+        if let Some(other) = joints.insert(joint.child, joint) {
+            return Err(MannequinError::InvalidUrdf(format!(
+                "link {} has multiple parents",
+                other.child
+            )));
+        }
+    }
+
+    let mut roots = links.iter().filter(|link| !joints.contains_key(*link));
+    let root = *roots.next().ok_or(MannequinError::RootNotSet)?;
+    if let Some(other) = roots.next() {
+        return Err(MannequinError::InvalidUrdf(format!(
+            "{root} and {other} are both roots"
+        )));
+    }
+
+    let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+    let effector = |link: &str| (!children.contains_key(link)).then(|| Array2::eye(4));
+    tree.set_root(
+        Segment::new(&Array2::eye(4), Axis::Fixed, effector(root)),
+        root.to_string(),
+    );
+    // Depth-first such that parents are added before their children (which keep the order of the file)
+    let mut stack = children.get(root).cloned().unwrap_or_default();
+    stack.reverse();
+    while let Some(link) = stack.pop() {
+        let joint = &joints[link];
+        let segment = Segment::new(&joint.origin, joint_axis(joint)?, effector(link));
+        tree.add(segment, link.to_string(), &joint.parent.to_string())?;
+        stack.extend(children.get(link).into_iter().flatten().rev());
+    }
+
+    if tree.len() != links.len() {
+        return Err(MannequinError::InvalidUrdf(
+            "links are not connected to the root".to_string(),
+        ));
+    }
+    Ok(tree)
+}
+
+fn parse_joint<'a>(node: Node<'a, '_>) -> Result<Joint<'a>, MannequinError<LinkNodeId>> {
+    let child_element = |tag: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(tag))
+            .ok_or_else(|| MannequinError::InvalidUrdf(format!("joint without <{tag}>")))
+    };
+    let origin = node.children().find(|child| child.has_tag_name("origin"));
+    let xyz = parse_triple(origin.and_then(|origin| origin.attribute("xyz")), [0.0; 3])?;
+    let rpy = parse_triple(origin.and_then(|origin| origin.attribute("rpy")), [0.0; 3])?;
+    // Fixed axes: roll (x), pitch (y) and yaw (z) are applied in this order
+    let mut transformation = rotate_z_4x4(rpy[2])
+        .dot(&rotate_y_4x4(rpy[1]))
+        .dot(&rotate_x_4x4(rpy[0]));
+    transformation.slice_mut(s![..3, 3]).assign(&Array1::from(xyz.to_vec()));
+
+    let axis = node.children().find(|child| child.has_tag_name("axis"));
+    Ok(Joint {
+        name: attribute(node, "name")?,
+        kind: attribute(node, "type")?,
+        parent: attribute(child_element("parent")?, "link")?,
+        child: attribute(child_element("child")?, "link")?,
+        origin: transformation,
+        axis: parse_triple(axis.and_then(|axis| axis.attribute("xyz")), [1.0, 0.0, 0.0])?,
+    })
+}
+
+fn joint_axis(joint: &Joint) -> Result<Axis, MannequinError<LinkNodeId>> {
+    // Index of the coordinate axis the joint is aligned with (if any)
+    let unit = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        .iter()
+        .position(|unit| *unit == joint.axis);
+    let axis = match (joint.kind, unit) {
+        ("revolute" | "continuous", Some(0)) => Axis::RotationX,
+        ("revolute" | "continuous", Some(1)) => Axis::RotationY,
+        ("revolute" | "continuous", Some(_)) => Axis::RotationZ,
+        ("revolute" | "continuous", None) => Axis::Rotation(Array1::from(joint.axis.to_vec())),
+        ("prismatic", Some(0)) => Axis::TranslationX,
+        ("prismatic", Some(1)) => Axis::TranslationY,
+        ("prismatic", Some(_)) => Axis::TranslationZ,
+        ("prismatic", None) => Axis::Translation(Array1::from(joint.axis.to_vec())),
+        ("fixed", _) => Axis::Fixed,
+        (kind, _) => {
+            return Err(MannequinError::InvalidUrdf(format!(
+                "unsupported type {kind} of joint {}",
+                joint.name
+            )))
+        }
+    };
+    Ok(axis)
+}
+
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str, MannequinError<LinkNodeId>> {
+    node.attribute(name)
+        .ok_or_else(|| MannequinError::InvalidUrdf(format!("<{}> without {name}", node.tag_name().name())))
+}
+
+/// Parses three whitespace-separated numbers (e.g., `xyz="0 0 1"`), `default` if the attribute is missing
+fn parse_triple(value: Option<&str>, default: [f64; 3]) -> Result<[f64; 3], MannequinError<LinkNodeId>> {
+    let Some(value) = value else {
+        return Ok(default);
+    };
+    let invalid = || MannequinError::InvalidUrdf(format!("expected three numbers: {value}"));
+    let numbers = value
+        .split_whitespace()
+        .map(|number| number.parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    numbers.try_into().map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arena::iterables::OptimizedDirectionIterable, DepthFirstArenaTree, NodeLike};
+    use approx::assert_abs_diff_eq;
+    use ndarray::array;
+
+    const ARM: &str = r#"
+        <robot name="arm">
+            <link name="base"/>
+            <link name="upper"/>
+            <link name="hand"/>
+            <link name="lower"/>
+            <joint name="elbow" type="revolute">
+                <parent link="upper"/>
+                <child link="lower"/>
+                <origin xyz="0 0 0.3"/>
+                <axis xyz="0 1 0"/>
+            </joint>
+            <joint name="shoulder" type="continuous">
+                <parent link="base"/>
+                <child link="upper"/>
+                <origin xyz="0 0 0.5" rpy="0 0 1.5707963267948966"/>
+                <axis xyz="0 0 1"/>
+            </joint>
+            <joint name="wrist" type="prismatic">
+                <parent link="lower"/>
+                <child link="hand"/>
+                <origin xyz="0 0 0.25"/>
+                <axis xyz="0 0 -1"/>
+            </joint>
+        </robot>"#;
+
+    #[test]
+    fn test_from_urdf() {
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = from_urdf(ARM).unwrap().into();
+
+        let ids = tree.iter().map(|node| node.id().as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["base", "upper", "lower", "hand"]);
+        let segments = tree.iter().map(|node| node.get()).collect::<Vec<_>>();
+        assert_eq!(segments[0].axis, Axis::Fixed);
+        assert_eq!(segments[1].axis, Axis::RotationZ);
+        assert_eq!(segments[2].axis, Axis::RotationY);
+        assert_eq!(segments[3].axis, Axis::Translation(array![0.0, 0.0, -1.0]));
+        // Only the tip has an effector
+        assert_eq!(
+            segments
+                .iter()
+                .filter(|segment| segment.effector_local.is_some())
+                .count(),
+            1
+        );
+        assert!(segments[3].effector_local.is_some());
+        assert_abs_diff_eq!(
+            segments[1].link,
+            array![
+                [0.0, -1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.5],
+                [0.0, 0.0, 0.0, 1.0]
+            ],
+            epsilon = 1e-12
+        );
+
+        assert!(matches!(
+            from_urdf(&ARM.replace(r#"<link name="hand"/>"#, "")),
+            Err(MannequinError::UnknownNode(link)) if link == "hand"
+        ));
+        assert!(matches!(
+            from_urdf(&ARM.replace("prismatic", "floating")),
+            Err(MannequinError::InvalidUrdf(_))
+        ));
+    }
+}
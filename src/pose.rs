@@ -0,0 +1,519 @@
+//! Utilities for poses (parameter vectors), e.g., for pose libraries and keyframes.
+//!
+//! With the `serde` feature, poses can be serialized keyed by the nodes' identifiers. Unlike a bare array,
+//! the format does not depend on the order of the nodes in the tree and thus survives reordering,
+//! removing, or adding nodes.
+
+use crate::{DepthFirstArenaTree, DepthFirstIterable, MannequinError, NodeLike, Rigid};
+use num_traits::{Float, NumCast, One, Zero};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::{collections::BTreeMap, fmt::Display};
+
+/// Metadata key of the lower joint limit, e.g., `tree.set_metadata(&id, LIMIT_MIN, "-1.57")`
+pub const LIMIT_MIN: &str = "limit_min";
+/// Metadata key of the upper joint limit
+pub const LIMIT_MAX: &str = "limit_max";
+
+/// Metadata key of the rest value of a joint (e.g., a slightly bent knee), see [rest_params]
+pub const REST: &str = "rest";
+
+/// Lower and upper limit of a node's joint parameters stored as metadata ([LIMIT_MIN] and [LIMIT_MAX]).
+/// Missing (or unparsable) limits are infinite.
+pub fn joint_limits<N, R>(node: &N) -> (R::FloatType, R::FloatType)
+where
+    N: NodeLike<R, R::NodeId>,
+    R: Rigid,
+{
+    let limit = |key: &str, default: R::FloatType| {
+        node.metadata(key)
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .and_then(<R::FloatType as NumCast>::from)
+            .unwrap_or(default)
+    };
+    (
+        limit(LIMIT_MIN, R::FloatType::neg_infinity()),
+        limit(LIMIT_MAX, R::FloatType::infinity()),
+    )
+}
+
+/// The limits of each parameter (see [joint_limits]), e.g., to seed
+/// [crate::DifferentialInverseModel::set_limits] from the metadata. A joint's limits are repeated for all its
+/// parameters.
+pub fn limits_from_metadata<T, R>(tree: &T) -> Vec<(R::FloatType, R::FloatType)>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    tree.iter()
+        .flat_map(|node| std::iter::repeat(joint_limits(node)).take(node.get().param_count()))
+        .collect()
+}
+
+/// Whether each parameter belongs to a revolute joint (see [Rigid::is_revolute]), e.g., to interpolate angles
+/// along the shorter way in [crate::trajectory::trajectory].
+pub fn revolute_params<T, R>(tree: &T) -> Vec<bool>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    tree.iter()
+        .flat_map(|node| std::iter::repeat(node.get().is_revolute()).take(node.get().param_count()))
+        .collect()
+}
+
+/// The rest pose: the rest value of each joint stored as metadata ([REST]) for all its parameters. Joints without
+/// rest value default to zero. Intended as the starting configuration (e.g., for inverse kinematics) and as the
+/// target of posture terms instead of the neutral pose (see [crate::DifferentialInverseModel::set_rest_pose]).
+/// Fails with [MannequinError::InvalidMetadata] naming the first joint whose rest value is not a number.
+pub fn rest_params<T, R>(tree: &T) -> Result<Vec<R::FloatType>, MannequinError<R::NodeId>>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    let mut params = Vec::with_capacity(tree.len());
+    for node in tree.iter() {
+        let rest = match node.metadata(REST) {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .and_then(<R::FloatType as NumCast>::from)
+                .ok_or_else(|| MannequinError::InvalidMetadata(node.id().clone()))?,
+            None => R::FloatType::zero(),
+        };
+        params.extend(std::iter::repeat(rest).take(node.get().param_count()));
+    }
+    Ok(params)
+}
+
+/// Checks that each parameter lies within the limits of its joint (see [joint_limits]), e.g., before
+/// solving. Fails with [MannequinError::LimitViolation] naming the first violating joint in depth-first order.
+pub fn validate_params<T, R>(tree: &T, params: &[R::FloatType]) -> Result<(), MannequinError<R::NodeId>>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    let mut offset = 0;
+    for node in tree.iter() {
+        let count = node.get().param_count();
+        let joint = params
+            .get(offset..offset + count)
+            .ok_or(MannequinError::DimensionMismatch(params.len()))?;
+        offset += count;
+
+        let (min, max) = joint_limits(node);
+        if joint.iter().any(|param| *param < min || *param > max) {
+            return Err(MannequinError::LimitViolation(node.id().clone()));
+        }
+    }
+    if offset != params.len() {
+        return Err(MannequinError::DimensionMismatch(params.len()));
+    }
+    Ok(())
+}
+
+/// Difference `a - b` of two angles wrapped into `[-π, π]`, i.e., the shortest way around the circle.
+pub fn angular_difference<F: Float>(a: F, b: F) -> F {
+    let pi = F::from(std::f64::consts::PI).unwrap();
+    let tau = pi + pi;
+    let difference = (a - b) % tau;
+    if difference > pi {
+        difference - tau
+    } else if difference < -pi {
+        difference + tau
+    } else {
+        difference
+    }
+}
+
+/// Weighted Euclidean distance between the poses `a` and `b` in joint space. Parameters of revolute joints
+/// (see [Rigid::is_revolute]) are compared on the circle such that, e.g., `π - ε` and `-π + ε` are close.
+/// The `weights` (one per parameter) default to one.
+pub fn pose_distance<T, R>(
+    tree: &T,
+    a: &[R::FloatType],
+    b: &[R::FloatType],
+    weights: Option<&[R::FloatType]>,
+) -> R::FloatType
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    debug_assert_eq!(a.len(), b.len());
+    debug_assert!(weights.map_or(true, |weights| weights.len() == a.len()));
+
+    a.iter()
+        .zip(b)
+        .zip(revolute_params(tree))
+        .enumerate()
+        .fold(R::FloatType::zero(), |sum, (index, ((a, b), revolute))| {
+            let difference = if revolute { angular_difference(*a, *b) } else { *a - *b };
+            let weight = weights.map_or(R::FloatType::one(), |weights| weights[index]);
+            sum + weight * difference * difference
+        })
+        .sqrt()
+}
+
+/// Index of the pose in `library` that is closest to `query` with respect to [pose_distance] (e.g., for pose
+/// matching). `None` if the library is empty.
+pub fn nearest_pose<T, R>(
+    tree: &T,
+    query: &[R::FloatType],
+    library: &[Vec<R::FloatType>],
+    weights: Option<&[R::FloatType]>,
+) -> Option<usize>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    library
+        .iter()
+        .map(|pose| pose_distance(tree, query, pose, weights))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(index, _)| index)
+}
+
+/// Transfers the pose `source_params` of `source` onto the skeleton `target` with the same structure (see
+/// [DepthFirstArenaTree::zip_nodes]) but possibly different proportions. Angles are copied, such that the
+/// positions follow from the target's links (e.g., with forward kinematics). With `scale_translations`,
+/// parameters of translational joints are scaled by the ratio of the lengths of the target and source link
+/// (the offset of the joint in its neutral position, see [Rigid::origin]), otherwise they are copied as well.
+///
+/// Fails with [MannequinError::StructureMismatch] if the trees or the number of parameters of a joint differ.
+pub fn retarget<R>(
+    source: &DepthFirstArenaTree<R, R::NodeId>,
+    source_params: &[R::FloatType],
+    target: &DepthFirstArenaTree<R, R::NodeId>,
+    scale_translations: bool,
+) -> Result<Vec<R::FloatType>, MannequinError<R::NodeId>>
+where
+    R: Rigid,
+{
+    let link_length = |rigid: &R| {
+        let neutral = vec![R::FloatType::zero(); rigid.param_count()];
+        R::origin(&rigid.transform(&neutral, 0))
+            .map(|origin| origin.iter().fold(R::FloatType::zero(), |sum, x| sum + *x * *x).sqrt())
+    };
+
+    let mut offset = 0;
+    let mut result = Vec::with_capacity(source_params.len());
+    for (node, counterpart) in source.zip_nodes(target)? {
+        let (rigid, other) = (node.get(), counterpart.get());
+        let count = rigid.param_count();
+        if count != other.param_count() {
+            return Err(MannequinError::StructureMismatch(node.id().clone()));
+        }
+        let joint = source_params
+            .get(offset..offset + count)
+            .ok_or(MannequinError::DimensionMismatch(source_params.len()))?;
+        offset += count;
+
+        let scale = match (link_length(rigid), link_length(other)) {
+            (Some(from), Some(to)) if scale_translations && !rigid.is_revolute() && from > R::FloatType::zero() => {
+                to / from
+            }
+            _ => R::FloatType::one(),
+        };
+        result.extend(joint.iter().map(|param| *param * scale));
+    }
+    if offset != source_params.len() {
+        return Err(MannequinError::DimensionMismatch(source_params.len()));
+    }
+    Ok(result)
+}
+
+#[cfg(feature = "serde")]
+/// Parameters of a single joint. Joints with one degree of freedom (the common case) are stored as a
+/// plain number, others as an array.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JointParams<F> {
+    Single(F),
+    Multiple(Vec<F>),
+}
+
+/// Exports `params` as a flat JSON object mapping the (displayed) identifier of each node to its
+/// parameter(s), e.g., `{"elbow": 0.5, "shoulder": 0.1}`. Nodes without parameters (e.g., fixed joints)
+/// are omitted.
+#[cfg(feature = "serde")]
+pub fn pose_to_json<T, R>(tree: &T, params: &[R::FloatType]) -> Result<String, MannequinError<R::NodeId>>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+    R::FloatType: Serialize,
+    R::NodeId: Display,
+{
+    let mut offset = 0;
+    let mut pose = BTreeMap::new();
+    for node in tree.iter() {
+        let count = node.get().param_count();
+        let joint = params
+            .get(offset..offset + count)
+            .ok_or(MannequinError::DimensionMismatch(params.len()))?;
+        offset += count;
+        match joint {
+            [] => {}
+            [param] => {
+                pose.insert(node.id().to_string(), JointParams::Single(*param));
+            }
+            _ => {
+                pose.insert(node.id().to_string(), JointParams::Multiple(joint.to_vec()));
+            }
+        }
+    }
+    if offset != params.len() {
+        return Err(MannequinError::DimensionMismatch(params.len()));
+    }
+    Ok(serde_json::to_string(&pose)?)
+}
+
+/// Rebuilds the parameter vector (in the order of `tree`) from a JSON object created by [pose_to_json].
+/// Joints missing in `json` are set to zero (i.e., the neutral pose), entries for nodes that are not in
+/// the tree are ignored. Fails if the number of parameters of a joint does not match.
+#[cfg(feature = "serde")]
+pub fn pose_from_json<T, R>(tree: &T, json: &str) -> Result<Vec<R::FloatType>, MannequinError<R::NodeId>>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+    R::FloatType: DeserializeOwned,
+    R::NodeId: Display,
+{
+    let pose: BTreeMap<String, JointParams<R::FloatType>> = serde_json::from_str(json)?;
+    let mut params = Vec::with_capacity(tree.len());
+    for node in tree.iter() {
+        let count = node.get().param_count();
+        match pose.get(&node.id().to_string()) {
+            None => params.extend(std::iter::repeat(R::FloatType::zero()).take(count)),
+            Some(JointParams::Single(param)) if count == 1 => params.push(*param),
+            Some(JointParams::Multiple(joint)) if joint.len() == count => params.extend_from_slice(joint),
+            Some(JointParams::Single(_)) => return Err(MannequinError::DimensionMismatch(1)),
+            Some(JointParams::Multiple(joint)) => return Err(MannequinError::DimensionMismatch(joint.len())),
+        }
+    }
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndarray::robot::{Axis, LinkNodeId, Segment};
+    use crate::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable};
+    use crate::{DifferentiableModel, Forward, ForwardModel};
+    use ndarray::prelude::*;
+
+    /// Arm with a fixed forearm and a translational end (pose `[shoulder, elbow, wrist]`)
+    fn arm() -> DepthFirstArenaTree<Segment, LinkNodeId> {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+
+        let shoulder = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "shoulder".to_string());
+        let elbow = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationY, None),
+                "elbow".to_string(),
+                &shoulder,
+            )
+            .unwrap();
+        tree.add(Segment::new(&trafo, Axis::Fixed, None), "forearm".to_string(), &elbow)
+            .unwrap();
+        tree.add(
+            Segment::new(&trafo, Axis::TranslationX, Some(trafo.clone())),
+            "wrist".to_string(),
+            &elbow,
+        )
+        .unwrap();
+        tree.into()
+    }
+
+    #[test]
+    fn test_nearest_pose() {
+        let tree = arm();
+        let pi = std::f64::consts::PI;
+
+        assert!((angular_difference(pi - 0.1, -pi + 0.1) + 0.2).abs() < 1e-12);
+        assert!((angular_difference(0.3, 0.1) - 0.2).abs() < 1e-12);
+
+        let library = vec![vec![0.0, 0.0, 0.0], vec![pi - 0.05, 0.0, 0.0], vec![2.0, 0.5, 0.0]];
+        // Across the wraparound, the query is close to the second pose
+        let query = [-pi + 0.05, 0.1, 0.0];
+        assert_eq!(nearest_pose(&tree, &query, &library, None), Some(1));
+        assert!((pose_distance(&tree, &query, &library[1], None) - 0.1_f64.hypot(0.1)).abs() < 1e-9);
+
+        // The wrist translates and is not wrapped
+        let library = vec![vec![0.0, 0.0, 2.0 * pi - 0.1], vec![0.0, 0.0, 1.0]];
+        assert_eq!(nearest_pose(&tree, &[0.0, 0.0, 0.0], &library, None), Some(1));
+
+        // Weights shift the preference
+        let library = vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.5, 0.0]];
+        assert_eq!(nearest_pose(&tree, &[0.0; 3], &library, None), Some(1));
+        assert_eq!(
+            nearest_pose(&tree, &[0.0; 3], &library, Some(&[0.1, 1.0, 1.0])),
+            Some(0)
+        );
+        assert_eq!(nearest_pose(&tree, &[0.0; 3], &[], None), None);
+    }
+
+    #[test]
+    fn test_rest_params() {
+        let mut tree = arm();
+        assert_eq!(rest_params(&tree).unwrap(), &[0.0, 0.0, 0.0]);
+
+        tree.set_metadata(&"elbow".to_string(), REST, "0.3").unwrap();
+        tree.set_metadata(&"wrist".to_string(), REST, "2.5").unwrap();
+        // Fixed joints have no parameters
+        tree.set_metadata(&"forearm".to_string(), REST, "1.0").unwrap();
+        assert_eq!(rest_params(&tree).unwrap(), &[0.0, 0.3, 2.5]);
+
+        // Invalid values are reported instead of being replaced silently
+        tree.set_metadata(&"shoulder".to_string(), REST, "bent").unwrap();
+        assert!(matches!(
+            rest_params(&tree),
+            Err(MannequinError::InvalidMetadata(id)) if id == "shoulder"
+        ));
+        tree.set_metadata(&"shoulder".to_string(), REST, " 2.5").unwrap();
+        assert!(matches!(
+            rest_params(&tree),
+            Err(MannequinError::InvalidMetadata(id)) if id == "shoulder"
+        ));
+    }
+
+    #[test]
+    fn test_retarget() {
+        let scaled = |scale: f64| {
+            let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+            let mut trafo = Segment::neutral_element();
+            trafo.slice_mut(s![..3, 3]).assign(&array![10.0 * scale, 0.0, 0.0]);
+            let shoulder = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "shoulder".to_string());
+            let elbow = tree
+                .add(
+                    Segment::new(&trafo, Axis::RotationY, None),
+                    "elbow".to_string(),
+                    &shoulder,
+                )
+                .unwrap();
+            tree.add(
+                Segment::new(&trafo, Axis::TranslationX, Some(trafo.clone())),
+                "wrist".to_string(),
+                &elbow,
+            )
+            .unwrap();
+            let tree: DepthFirstArenaTree<_, _> = tree.into();
+            tree
+        };
+        let (small, large) = (scaled(1.0), scaled(2.0));
+        let params = [0.3, -0.7, 1.5];
+
+        // Angles transfer, the translation is doubled
+        let result = retarget(&small, &params, &large, true).unwrap();
+        assert_eq!(result, &[0.3, -0.7, 3.0]);
+        assert_eq!(retarget(&small, &params, &large, false).unwrap(), &params);
+
+        // Thus, the whole pose is scaled
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&small, &[&"wrist".to_string()]).unwrap();
+        let from = fk.solve(&small, &params)[0].to_vec();
+        fk.setup(&large, &[&"wrist".to_string()]).unwrap();
+        let to = fk.solve(&large, &result)[0].to_vec();
+        from.iter()
+            .zip(&to)
+            .for_each(|(a, b)| assert!((2.0 * a - b).abs() < 1e-9));
+
+        assert!(matches!(
+            retarget(&small, &params, &arm(), true),
+            Err(MannequinError::StructureMismatch(_))
+        ));
+        assert!(matches!(
+            retarget(&small, &params[..2], &large, true),
+            Err(MannequinError::DimensionMismatch(2))
+        ));
+    }
+
+    #[test]
+    fn test_validate_params() {
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let mut trafo = Segment::neutral_element();
+        trafo.slice_mut(s![..3, 3]).assign(&array![10.0, 0.0, 0.0]);
+        let shoulder = tree.set_root(Segment::new(&trafo, Axis::RotationZ, None), "shoulder".to_string());
+        let elbow = tree
+            .add(
+                Segment::new(&trafo, Axis::RotationZ, None),
+                "elbow".to_string(),
+                &shoulder,
+            )
+            .unwrap();
+        tree.set_metadata(&shoulder, LIMIT_MIN, "-1.0").unwrap();
+        tree.set_metadata(&shoulder, LIMIT_MAX, "1.0").unwrap();
+        // Only bounded from below
+        tree.set_metadata(&elbow, LIMIT_MIN, "0.0").unwrap();
+        let tree: DepthFirstArenaTree<_, _> = tree.into();
+
+        assert!(validate_params(&tree, &[0.5, 0.0]).is_ok());
+        assert!(validate_params(&tree, &[-1.0, 100.0]).is_ok());
+        assert!(matches!(
+            validate_params(&tree, &[1.5, 0.0]),
+            Err(MannequinError::LimitViolation(id)) if id == "shoulder"
+        ));
+        assert!(matches!(
+            validate_params(&tree, &[0.5, -0.1]),
+            Err(MannequinError::LimitViolation(id)) if id == "elbow"
+        ));
+        assert!(matches!(
+            validate_params(&tree, &[0.5]),
+            Err(MannequinError::DimensionMismatch(1))
+        ));
+    }
+
+    #[test]
+    fn test_limits_from_metadata() {
+        let mut tree = arm();
+        tree.set_metadata(&"shoulder".to_string(), LIMIT_MIN, "-1.0").unwrap();
+        tree.set_metadata(&"shoulder".to_string(), LIMIT_MAX, "1.0").unwrap();
+        // Fixed joints have no parameters
+        tree.set_metadata(&"forearm".to_string(), LIMIT_MAX, "2.0").unwrap();
+        tree.set_metadata(&"wrist".to_string(), LIMIT_MAX, "5.0").unwrap();
+
+        let infinity = f64::INFINITY;
+        assert_eq!(
+            limits_from_metadata(&tree),
+            vec![(-1.0, 1.0), (-infinity, infinity), (-infinity, 5.0)]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_pose_json() {
+        let tree = arm();
+
+        let params = vec![0.1, -0.5, 0.7];
+        let json = pose_to_json(&tree, &params).unwrap();
+        assert_eq!(json, r#"{"elbow":-0.5,"shoulder":0.1,"wrist":0.7}"#);
+
+        let recovered = pose_from_json(&tree, &json).unwrap();
+        assert_eq!(recovered, params);
+
+        let mut fk = ForwardModel::new(DifferentiableModel::new());
+        fk.setup(&tree, &[&"wrist".to_string()]).unwrap();
+        let expected = fk.solve(&tree, &params)[0].to_vec();
+        assert_eq!(fk.solve(&tree, &recovered)[0], expected.as_slice());
+
+        // Missing joints default to the neutral pose, unknown ones are ignored
+        let recovered = pose_from_json(&tree, r#"{"wrist": 0.7, "ankle": 1.0}"#).unwrap();
+        assert_eq!(recovered, vec![0.0, 0.0, 0.7]);
+
+        assert!(matches!(
+            pose_to_json(&tree, &[0.0, 1.0]),
+            Err(MannequinError::DimensionMismatch(2))
+        ));
+        assert!(matches!(
+            pose_from_json(&tree, r#"{"elbow": [1.0, 2.0]}"#),
+            Err(MannequinError::DimensionMismatch(2))
+        ));
+        assert!(matches!(
+            pose_from_json(&tree, "[1.0"),
+            Err(MannequinError::JsonError(_))
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+//! Helpers for simulating the model over time given the output of the forward dynamics
+//! (e.g., [crate::ndarray::dynamics::forward_dynamics]).
+//!
+//! The functions operate on parameter vectors only and are thus independent of the backend.
+
+use crate::{pose::limits_from_metadata, DepthFirstIterable, Rigid};
+use num_traits::{Float, Zero};
+
+/// Spring-damper that softly enforces the joint limits (see [crate::pose::joint_limits]) in a simulation.
+/// Instead of stopping hard, a joint is pushed back as soon as it comes closer than `margin` to one of its
+/// limits, with a torque proportional to the penetration (`stiffness`) and to the velocity into the limit
+/// (`damping`). The torque never pulls a joint towards its limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitSpring<F> {
+    pub stiffness: F,
+    pub damping: F,
+    pub margin: F,
+}
+
+/// Computes the torques of the `spring` pushing each joint back from its limits at the configuration
+/// `params` moving with `velocities`. Zero for joints far from (or without) limits. Add the result to
+/// the actuation torques passed to the forward dynamics. A joint's limits apply to all its parameters
+/// (see [crate::pose::limits_from_metadata]).
+pub fn limit_torques<T, R>(
+    tree: &T,
+    params: &[R::FloatType],
+    velocities: &[R::FloatType],
+    spring: &LimitSpring<R::FloatType>,
+) -> Vec<R::FloatType>
+where
+    T: DepthFirstIterable<R, R::NodeId>,
+    R: Rigid,
+{
+    let limits = limits_from_metadata(tree);
+    debug_assert_eq!(params.len(), limits.len());
+    debug_assert_eq!(velocities.len(), limits.len());
+
+    let zero = R::FloatType::zero();
+    limits
+        .into_iter()
+        .zip(params.iter().zip(velocities))
+        .map(|((min, max), (param, velocity))| {
+            let lower = min + spring.margin - *param;
+            let upper = *param - (max - spring.margin);
+            if lower > zero {
+                (spring.stiffness * lower - spring.damping * *velocity).max(zero)
+            } else if upper > zero {
+                -(spring.stiffness * upper + spring.damping * *velocity).max(zero)
+            } else {
+                zero
+            }
+        })
+        .collect()
+}
+
+/// Advances the joint positions `params` and `velocities` by `dt` given the joint `accelerations`
+/// using the semi-implicit (symplectic) Euler method: the velocities are updated first and the new
+/// velocities are used to update the positions. Contrary to the explicit method, the energy of an
+/// undamped system stays bounded for small time steps.
+///
+/// The optional `damping` holds a (non-negative) coefficient per joint. It is applied implicitly,
+/// that is, `q̇ ← (q̇ + dt·q̈) / (1 + dt·c)`, which is stable for any time step.
+pub fn integrate<F: Float>(params: &mut [F], velocities: &mut [F], accelerations: &[F], dt: F, damping: Option<&[F]>) {
+    debug_assert_eq!(params.len(), velocities.len());
+    debug_assert_eq!(params.len(), accelerations.len());
+    debug_assert!(damping.map_or(true, |damping| damping.len() == params.len()));
+
+    params
+        .iter_mut()
+        .zip(velocities.iter_mut())
+        .zip(accelerations)
+        .enumerate()
+        .for_each(|(index, ((param, velocity), acceleration))| {
+            *velocity = *velocity + dt * *acceleration;
+            if let Some(damping) = damping {
+                *velocity = *velocity / (F::one() + dt * damping[index]);
+            }
+            *param = *param + dt * *velocity;
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndarray::{
+        dynamics::forward_dynamics,
+        robot::{Axis, LinkNodeId, Segment},
+        spherical::SphericalJoint,
+    };
+    use crate::{DepthFirstArenaTree, DirectedArenaTree, DirectionIterable, Rigid};
+    use ndarray::prelude::*;
+
+    #[test]
+    fn test_integrate() {
+        let (mass, lc, izz, g) = (2.0, 0.75, 0.1, 9.81);
+
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        tree.set_root(
+            Segment::with_inertia(
+                &Segment::neutral_element(),
+                Axis::RotationZ,
+                None,
+                mass,
+                array![lc, 0.0, 0.0],
+                Array2::from_diag(&array![0.3, 0.4, izz]),
+            ),
+            "pendulum".to_string(),
+        );
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+
+        // Kinetic and potential energy (the center of mass is at height l·sin(θ))
+        let energy = |angle: f64, velocity: f64| {
+            0.5 * (izz + mass * lc * lc) * velocity * velocity + mass * g * lc * angle.sin()
+        };
+        let simulate = |damping: Option<&[f64]>| {
+            // Released from the horizontal
+            let (mut params, mut velocities) = ([0.0], [0.0]);
+            let mut max_deviation = 0.0f64;
+            (0..2000).for_each(|_| {
+                let accelerations = forward_dynamics(&tree, &params, &velocities, &[0.0], &[0.0, -g, 0.0]).unwrap();
+                integrate(
+                    &mut params,
+                    &mut velocities,
+                    accelerations.as_slice().unwrap(),
+                    1e-3,
+                    damping,
+                );
+                max_deviation = max_deviation.max((energy(params[0], velocities[0]) - energy(0.0, 0.0)).abs());
+            });
+            (energy(params[0], velocities[0]), max_deviation)
+        };
+
+        // Undamped: the energy oscillates slightly but doesn't drift (peak kinetic energy is ~14.7)
+        let (_, max_deviation) = simulate(None);
+        assert!(max_deviation < 0.1, "{max_deviation}");
+
+        // Damped: energy is dissipated and the pendulum comes to rest at the bottom
+        let (final_energy, _) = simulate(Some(&[4.0]));
+        assert!(final_energy < energy(0.0, 0.0) - 10.0);
+        assert!((final_energy - energy(-std::f64::consts::FRAC_PI_2, 0.0)).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_limit_torques() {
+        let (mass, lc, izz, g) = (2.0, 0.75, 0.1, 9.81);
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let id = tree.set_root(
+            Segment::with_inertia(
+                &Segment::neutral_element(),
+                Axis::RotationZ,
+                None,
+                mass,
+                array![lc, 0.0, 0.0],
+                Array2::from_diag(&array![0.3, 0.4, izz]),
+            ),
+            "pendulum".to_string(),
+        );
+        tree.set_metadata(&id, crate::pose::LIMIT_MIN, "-0.5").unwrap();
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+
+        let spring = LimitSpring {
+            stiffness: 500.0,
+            damping: 5.0,
+            margin: 0.0,
+        };
+        // Released from the horizontal, the pendulum falls into the limit
+        let simulate = |spring: Option<&LimitSpring<f64>>| {
+            let (mut params, mut velocities) = ([0.0], [0.0]);
+            let trajectory = (0..3000)
+                .map(|_| {
+                    let torques = spring.map_or(vec![0.0], |spring| limit_torques(&tree, &params, &velocities, spring));
+                    let accelerations =
+                        forward_dynamics(&tree, &params, &velocities, &torques, &[0.0, -g, 0.0]).unwrap();
+                    integrate(
+                        &mut params,
+                        &mut velocities,
+                        accelerations.as_slice().unwrap(),
+                        1e-3,
+                        None,
+                    );
+                    params[0]
+                })
+                .collect::<Vec<_>>();
+            trajectory
+        };
+
+        // Without the spring, the limit is passed
+        let free = simulate(None);
+        assert!(free.iter().any(|angle| *angle < -1.5));
+
+        // With the spring, the overshoot is bounded and the pendulum bounces back
+        let limited = simulate(Some(&spring));
+        let (lowest, minimum) =
+            limited.iter().enumerate().fold(
+                (0, f64::INFINITY),
+                |(i, m), (j, a)| if *a < m { (j, *a) } else { (i, m) },
+            );
+        assert!(minimum > -0.7, "{minimum}");
+        assert!(limited[lowest..].iter().any(|angle| *angle > minimum + 0.02));
+        // It comes to rest close to the limit where the spring carries the weight
+        let rest = -0.5 - mass * g * lc * 0.5_f64.cos() / spring.stiffness;
+        assert!((limited.last().unwrap() - rest).abs() < 0.02);
+
+        // Far from the limit, there is no torque
+        assert_eq!(limit_torques(&tree, &[0.3], &[1.0], &spring), vec![0.0]);
+    }
+
+    #[test]
+    fn test_limit_torques_multiple_params() {
+        let spring = LimitSpring {
+            stiffness: 100.0,
+            damping: 0.0,
+            margin: 0.0,
+        };
+
+        // The fixed joint consumes no parameter
+        let mut tree = DirectedArenaTree::<Segment, LinkNodeId>::new();
+        let root = tree.set_root(
+            Segment::new(&Segment::neutral_element(), Axis::Fixed, None),
+            "base".to_string(),
+        );
+        tree.set_metadata(&root, crate::pose::LIMIT_MAX, "0.0").unwrap();
+        let id = tree
+            .add(
+                Segment::new(&Segment::neutral_element(), Axis::RotationZ, None),
+                "joint".to_string(),
+                &root,
+            )
+            .unwrap();
+        tree.set_metadata(&id, crate::pose::LIMIT_MAX, "0.5").unwrap();
+        let tree: DepthFirstArenaTree<Segment, LinkNodeId> = tree.into();
+        let torques = limit_torques(&tree, &[0.6], &[0.0], &spring);
+        assert_eq!(torques.len(), 1);
+        assert!((torques[0] + 10.0).abs() < 1e-9);
+
+        // The limits of the spherical joint apply to each of its three parameters
+        let mut tree = DirectedArenaTree::<SphericalJoint, String>::new();
+        let id = tree.set_root(
+            SphericalJoint::new(&SphericalJoint::neutral_element(), None),
+            "ball".to_string(),
+        );
+        tree.set_metadata(&id, crate::pose::LIMIT_MIN, "-1.0").unwrap();
+        tree.set_metadata(&id, crate::pose::LIMIT_MAX, "1.0").unwrap();
+        let tree: DepthFirstArenaTree<SphericalJoint, String> = tree.into();
+        let torques = limit_torques(&tree, &[-1.1, 0.0, 1.2], &[0.0; 3], &spring);
+        assert_eq!(torques.len(), 3);
+        assert!((torques[0] - 10.0).abs() < 1e-9);
+        assert_eq!(torques[1], 0.0);
+        assert!((torques[2] + 20.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,149 @@
+//! Helpers for turning poses (parameter vectors, e.g., IK keyframes) into playable motion.
+//!
+//! The functions operate on parameter vectors only and are thus independent of the backend.
+
+use crate::pose::angular_difference;
+use num_traits::Float;
+
+/// Quintic time scaling `10τ³ - 15τ⁴ + 6τ⁵` mapping `[0, 1]` onto `[0, 1]` with vanishing velocity
+/// and acceleration at both ends.
+fn quintic<F: Float>(tau: F) -> F {
+    let c = |x: f64| F::from(x).unwrap();
+    tau * tau * tau * (c(10.0) + tau * (c(-15.0) + tau * c(6.0)))
+}
+
+/// Generates intermediate parameter vectors from `from` to `to` sampled every `dt` over `duration`
+/// (both included). A quintic time scaling is applied such that the motion starts and ends with
+/// zero velocity (and acceleration).
+///
+/// Parameters flagged in `revolute` (one per parameter, see [crate::pose::revolute_params]) are angles
+/// and follow the shorter way around the circle, e.g., from `π - ε` to `-π + ε` through π. Their last
+/// frame is thus only equivalent to `to` modulo 2π. Without flags, all parameters are interpolated linearly.
+pub fn trajectory<F: Float>(from: &[F], to: &[F], revolute: Option<&[bool]>, duration: F, dt: F) -> Vec<Vec<F>> {
+    debug_assert_eq!(from.len(), to.len());
+    debug_assert!(revolute.map_or(true, |revolute| revolute.len() == from.len()));
+    debug_assert!(duration > F::zero() && dt > F::zero());
+
+    let differences = from
+        .iter()
+        .zip(to)
+        .enumerate()
+        .map(|(index, (a, b))| match revolute {
+            Some(revolute) if revolute[index] => angular_difference(*b, *a),
+            _ => *b - *a,
+        })
+        .collect::<Vec<_>>();
+    let steps = (duration / dt).ceil().to_usize().unwrap_or(0).max(1);
+    (0..=steps)
+        .map(|step| {
+            let time = (F::from(step).unwrap() * dt).min(duration);
+            let blend = quintic(time / duration);
+            from.iter().zip(&differences).map(|(a, d)| *a + *d * blend).collect()
+        })
+        .collect()
+}
+
+/// Limits the acceleration (second difference over `dt`) of each parameter across consecutive `frames` to
+/// `max_accel` (e.g., to remove jerks when chaining IK solutions). The first two frames are kept, each
+/// subsequent frame follows the input as closely as the acceleration bound allows.
+pub fn smooth_trajectory<F: Float>(frames: &mut [Vec<F>], max_accel: F, dt: F) {
+    debug_assert!(max_accel >= F::zero() && dt > F::zero());
+    let max_change = max_accel * dt * dt;
+
+    (2..frames.len()).for_each(|index| {
+        let (smoothed, rest) = frames.split_at_mut(index);
+        let (previous, current) = (&smoothed[index - 2], &smoothed[index - 1]);
+        debug_assert_eq!(current.len(), rest[0].len());
+
+        rest[0]
+            .iter_mut()
+            .zip(current.iter().zip(previous))
+            .for_each(|(target, (current, previous))| {
+                // Change of the displacement per step, i.e., the second difference
+                let extrapolated = *current + (*current - *previous);
+                let change = (*target - extrapolated).max(-max_change).min(max_change);
+                *target = extrapolated + change;
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::{izip, Itertools};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_trajectory() {
+        let from = [0.0, 1.0, -2.0];
+        let to = [1.0, 1.0, 2.0];
+        let dt = 0.01;
+
+        let frames = trajectory(&from, &to, None, 1.0, dt);
+
+        assert_eq!(frames.len(), 101);
+        assert_eq!(frames.first().unwrap(), &from);
+        assert_eq!(frames.last().unwrap(), &to);
+
+        // Velocities at start and end vanish (only third order terms remain) compared to
+        // the peak velocity (15 / 8 * 4.0) at the midpoint
+        let velocity = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| ((y - x) / dt).abs()).fold(0.0, f64::max);
+        assert!(velocity(&frames[0], &frames[1]) < 1e-2);
+        assert!(velocity(&frames[99], &frames[100]) < 1e-2);
+        assert!(velocity(&frames[49], &frames[51]) / 2.0 > 7.0);
+
+        // The profile is symmetric: the midpoint is the average
+        frames[50]
+            .iter()
+            .zip(from.iter().zip(&to))
+            .for_each(|(x, (a, b))| assert!((x - (a + b) / 2.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_trajectory_wrapped() {
+        let from = [3.0, 3.0];
+        let to = [-3.0, -3.0];
+
+        let frames = trajectory(&from, &to, Some(&[true, false]), 1.0, 0.01);
+
+        // The angle crosses ±π instead of passing zero and ends at the equivalent of -3
+        assert!(frames
+            .iter()
+            .all(|frame| frame[0] >= 3.0 - 1e-9 && frame[0] <= 2.0 * PI - 3.0 + 1e-9));
+        assert!((frames.last().unwrap()[0] - (2.0 * PI - 3.0)).abs() < 1e-9);
+        // The unflagged parameter takes the linear way
+        assert!(frames.iter().any(|frame| frame[1].abs() < 0.1));
+        assert_eq!(frames.last().unwrap()[1], -3.0);
+    }
+
+    #[test]
+    fn test_smooth_trajectory() {
+        let (dt, max_accel) = (0.01, 20.0);
+
+        // A smooth motion (peak acceleration π² ≈ 9.9) with jitter of ±0.01 that causes
+        // accelerations of up to 400
+        let input = (0..200)
+            .map(|step| {
+                let time = step as f64 * dt;
+                let jitter = if step % 2 == 0 { 0.01 } else { -0.01 };
+                vec![(PI * time).sin() + jitter, 0.5]
+            })
+            .collect_vec();
+
+        let mut frames = input.clone();
+        smooth_trajectory(&mut frames, max_accel, dt);
+
+        frames.iter().tuple_windows().for_each(|(a, b, c)| {
+            izip!(a, b, c).for_each(|(a, b, c)| assert!(((c - 2.0 * b + a) / (dt * dt)).abs() <= max_accel + 1e-6));
+        });
+        // Constant parameters are not affected
+        assert!(izip!(&input, &frames).all(|(raw, smoothed)| raw[1] == smoothed[1]));
+
+        // Tracking is close except for the beginning where the jitter causes a wrong initial velocity
+        let deviations = izip!(&input, &frames)
+            .map(|(raw, smoothed)| (raw[0] - smoothed[0]).abs())
+            .collect_vec();
+        assert!(deviations.iter().all(|d| *d < 0.15));
+        assert!(deviations.iter().sum::<f64>() / (deviations.len() as f64) < 0.03);
+    }
+}